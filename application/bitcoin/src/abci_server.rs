@@ -4,7 +4,7 @@ use std::{
     ops::DerefMut,
     os::{macos::raw::stat, unix::prelude::OsStrExt},
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicI64, AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
@@ -31,6 +31,8 @@ where
 {
     committed_state: Arc<Mutex<NodeState<T>>>,
     current_state: Arc<Mutex<Option<NodeState<T>>>>,
+    // time of the block being executed, in unix seconds, from the BeginBlock header
+    block_time: AtomicI64,
 }
 
 impl<T: Clone + Send + Sync> ConsensusConnection<T> {
@@ -41,6 +43,7 @@ impl<T: Clone + Send + Sync> ConsensusConnection<T> {
         Self {
             committed_state,
             current_state,
+            block_time: AtomicI64::new(0),
         }
     }
 }
@@ -69,7 +72,13 @@ impl<T: Clone + Send + Sync + Storage> Consensus for ConsensusConnection<T> {
         }
     }
 
-    async fn begin_block(&self, _begin_block_request: RequestBeginBlock) -> ResponseBeginBlock {
+    async fn begin_block(&self, begin_block_request: RequestBeginBlock) -> ResponseBeginBlock {
+        // the proposer's time stored in the consensus block, the same on every node and replay
+        let time = begin_block_request
+            .header
+            .and_then(|header| header.time)
+            .map_or(0, |time| time.seconds);
+        self.block_time.store(time, Ordering::SeqCst);
         Default::default()
     }
 
@@ -96,7 +105,10 @@ impl<T: Clone + Send + Sync + Storage> Consensus for ConsensusConnection<T> {
             let txs = vec![utxos];
 
             // 构造区块
-            block = current_state.bc.construct_block(&txs).await;
+            block = current_state
+                .bc
+                .construct_block(&txs, self.block_time.load(Ordering::SeqCst))
+                .await;
 
             let _ = current_state
                 .utxos
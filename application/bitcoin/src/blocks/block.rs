@@ -1,4 +1,3 @@
-use chrono::Utc;
 use serde::{Serialize, Deserialize};
 
 use tracing::info;
@@ -9,6 +8,7 @@ use crate::{Transaction, utils::{serialize, hash_to_str}};
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
 pub struct BlockHeader {
+    // unix time in seconds, the time of the consensus block the block was made in
     timestamp: i64,
     prev_hash: String,
     txs_hash: String,
@@ -17,9 +17,9 @@ pub struct BlockHeader {
 }
 
 impl BlockHeader {
-    fn new(prev_hash: &str, bits: usize) -> Self {
+    fn new(prev_hash: &str, bits: usize, timestamp: i64) -> Self {
         Self {
-            timestamp: Utc::now().timestamp(),
+            timestamp,
             prev_hash: prev_hash.into(),
             txs_hash: String::new(),
             bits,
@@ -36,9 +36,9 @@ pub struct Block {
 }
 
 impl Block {
-    pub fn new(txs: &[Transaction], prev_hash: &str, bits: usize) -> Self {
+    pub fn new(txs: &[Transaction], prev_hash: &str, bits: usize, timestamp: i64) -> Self {
         let mut block = Block {
-            header: BlockHeader::new(prev_hash, bits),
+            header: BlockHeader::new(prev_hash, bits, timestamp),
             tranxs: txs.to_vec(),
             hash: String::new(),
         };
@@ -57,7 +57,8 @@ impl Block {
     pub fn create_genesis_block(bits: usize, genesis_addr: &str) -> Self {
         let coinbase = Transaction::new_coinbase(genesis_addr);
         info!("coinbase transaction is {:?}", coinbase);
-        Self::new(&vec![coinbase], "", bits)
+        // every node makes the genesis block on its own, there is no agreed time for it
+        Self::new(&vec![coinbase], "", bits, 0)
     }
 
     pub fn get_hash(&self) -> String {
@@ -48,14 +48,16 @@ impl<T: Storage> Blockchain<T> {
         *app_hash = hash;
     }
 
-    pub async fn construct_block(&mut self, txs: &[Transaction]) -> Block {
+    /// Makes a block of `txs` at `timestamp`, the time of the consensus block they were
+    /// delivered in, so every node replaying the chain makes the same block.
+    pub async fn construct_block(&mut self, txs: &[Transaction], timestamp: i64) -> Block {
         for tx in txs {
             if tx.verify(self).await == false {
                 panic!("ERROR: Invalid transaction")
             }
         }
 
-        let block = Block::new(txs, &self.app_hash.read().unwrap(), CURR_BITS);
+        let block = Block::new(txs, &self.app_hash.read().unwrap(), CURR_BITS, timestamp);
         let hash = block.get_hash();
         self.height.fetch_add(1, Ordering::Relaxed);
         self.storage.update_blocks(&hash, &block, self.height.load(Ordering::Relaxed)).await;
@@ -1,84 +1,194 @@
+use std::collections::{HashMap, HashSet};
 use std::net::SocketAddr;
-use tokio::sync::mpsc::{Receiver, Sender, UnboundedSender};
+use std::sync::Arc;
+use tokio::sync::mpsc::{Receiver, UnboundedReceiver};
 use tokio::sync::oneshot::Sender as OneShotSender;
-use tendermint_proto::Protobuf;
-use crate::{pow::ProofOfWork, QueryInfo, Transaction};
-use tracing::info;
+use tokio::sync::{broadcast, watch};
+use tokio::time::{interval, sleep_until, Instant};
+use crate::QueryInfo;
+use crate::{ChainContext, ConsensusEngine, OutMessage, PeerId, Step, Target};
+use crate::{Block, BlockResults, BlockStore, EndBlockResult, EventRecord, TxResult, ValidatorUpdateRecord};
+use crate::{AppInfo, ChainState, ChainStatus, CommittedBlock, Genesis, NodeConfig, BLOCK_PROTOCOL, EVENT_BUFFER};
+use crate::block_builder::{BlockBuilder, BlockLimits};
+use crate::mempool::{rejected, tx_hash, Mempool, MempoolTx};
+use crate::p2p::{P2pEvent, P2pHandle, P2pMessage, BAN_SCORE};
+use crate::error::{BlockError, BlockchainError, PoaError, PowError, TendermintError};
 use tendermint_abci::{Client as AbciClient, ClientBuilder};
 use tendermint_proto::abci::{
-    Event, EventAttribute, RequestBeginBlock, RequestDeliverTx, RequestEcho, RequestEndBlock,
-    RequestInfo, RequestInitChain, RequestQuery, ResponseQuery, ResponseDeliverTx
+    LastCommitInfo, RequestBeginBlock, RequestDeliverTx, RequestEndBlock, RequestQuery, ResponseQuery,
+    ResponseDeliverTx, ResponseCheckTx, ResponseBeginBlock, ResponseEndBlock, ResponseInitChain,
 };
 use bytes::Bytes;
-use base64::{decode, encode};
-use hex::encode as hex_encode;
+use futures::future::poll_fn;
+use base64::decode;
 
 use std::time::{SystemTime, UNIX_EPOCH};
-use tendermint_proto::types::Header;
+use tendermint_proto::types::{BlockId, Header, PartSetHeader};
 use tendermint_proto::version::Consensus;
 use tendermint_proto::google::protobuf::Timestamp;
-use tendermint_rpc::{
-    endpoint,
-    error::{Error, ErrorDetail},
-    request::Wrapper as RequestWrapper,
-    Code, Order, Response,
-};
-pub const DIFFICULTY: usize = 10;
 
+// txs of the block this node proposed, until the engine commits a block at its height
+struct Proposal {
+    txs: Vec<MempoolTx>,
+    // whether the txs filled the block, a partial block gives way to a full one
+    full: bool,
+}
+
+/// Drives the ABCI app: feeds it the txs through CheckTx and the blocks the consensus
+/// engine commits through BeginBlock..Commit, and keeps the block store in sync.
 pub struct Engine {
     pub app_address: SocketAddr,
     pub rx_abci_queries: Receiver<(OneShotSender<ResponseQuery>, QueryInfo)>,
     pub last_block_height: i64,
+    pub last_block_hash: Vec<u8>,
     pub last_app_hash: Bytes,
+    pub store: Arc<dyn BlockStore>,
+    pub genesis: Genesis,
+    pub state: ChainState,
     pub client: AbciClient,
     pub req_client: AbciClient,
+    pub mempool: Mempool,
+    pub block_builder: BlockBuilder,
+    pub consensus: Box<dyn ConsensusEngine>,
+    proposal: Option<Proposal>,
+    // callers of `broadcast_tx_commit` waiting for the DeliverTx result, keyed by tx hash
+    deliver_waiters: HashMap<Vec<u8>, OneShotSender<(i64, ResponseDeliverTx)>>,
+    // the peers, None for a node on its own
+    p2p: Option<P2pHandle>,
+    status: watch::Sender<ChainStatus>,
+    events: broadcast::Sender<Arc<CommittedBlock>>,
+    // set by the handshake
+    pub(crate) app_info: AppInfo,
 }
 
 impl Engine {
     pub fn new(
-        app_address: SocketAddr,
+        config: &NodeConfig,
         rx_abci_queries: Receiver<(OneShotSender<ResponseQuery>, QueryInfo)>,
-        // last_app_hash: Vec<u8>,
+        store: Arc<dyn BlockStore>,
+        genesis: Genesis,
+        consensus: Box<dyn ConsensusEngine>,
     ) -> Self {
-        let mut echo_client = ClientBuilder::default().connect(&app_address).unwrap();
-
-        let resp_info = echo_client.info(RequestInfo::default()).unwrap();
-        let last_block_height = resp_info.last_block_height;
-        let last_app_hash = resp_info.last_block_app_hash;
-
-        // Instantiate a new client to not be locked in an Info connection
+        let app_address = config.abci.proxy_app;
         let client = ClientBuilder::default().connect(&app_address).unwrap();
         let req_client = ClientBuilder::default().connect(&app_address).unwrap();
+        let mempool = Mempool::new(&app_address, config.mempool.size).unwrap();
+        let block_limits = BlockLimits {
+            max_interval: config.block_interval(),
+            create_empty_blocks: config.consensus.create_empty_blocks,
+            create_empty_blocks_interval: config.create_empty_blocks_interval(),
+            ..Default::default()
+        };
         Self {
             app_address,
             rx_abci_queries,
-            last_block_height,
+            // filled in by the handshake with the app
+            last_block_height: 0,
+            last_block_hash: vec![],
             client,
             req_client,
-            last_app_hash,
+            last_app_hash: Bytes::new(),
+            store,
+            genesis,
+            state: ChainState::default(),
+            mempool,
+            block_builder: BlockBuilder::new(block_limits),
+            consensus,
+            proposal: None,
+            deliver_waiters: HashMap::new(),
+            p2p: None,
+            status: watch::channel(ChainStatus::default()).0,
+            events: broadcast::channel(EVENT_BUFFER).0,
+            app_info: AppInfo::default(),
         }
     }
 
-    pub async fn run(&mut self, mut rx_output: Receiver<String>, deliver_tx: UnboundedSender<ResponseDeliverTx>) -> eyre::Result<()> {
-        // TODO: 如果高度大于1，那就不init chain
-        self.init_chain()?;
-        let deliver_req = deliver_tx.clone();
+    /// Follows the status of the chain, updated after every block.
+    pub fn subscribe_status(&self) -> watch::Receiver<ChainStatus> {
+        self.status.subscribe()
+    }
+
+    /// Channel the blocks the app commits are published on, with their results. Each
+    /// subscriber of the RPC subscribes to it on its own.
+    pub fn events(&self) -> broadcast::Sender<Arc<CommittedBlock>> {
+        self.events.clone()
+    }
+
+    fn publish_status(&self) {
+        let (tip_height, tip_hash) = self.consensus.tip(&self.context());
+        self.status.send_replace(ChainStatus {
+            tip_height,
+            tip_hash,
+            finalized_height: self.last_block_height,
+            finalized_hash: self.last_block_hash.clone(),
+            app_hash: self.last_app_hash.to_vec(),
+            app_info: self.app_info.clone(),
+        });
+    }
+
+    /// Runs the node until the request channels close. `p2p` is the network started
+    /// by `p2p::start`, without it the node runs on its own.
+    pub async fn run(
+        &mut self,
+        mut rx_output: Receiver<(OneShotSender<ResponseCheckTx>, Option<OneShotSender<(i64, ResponseDeliverTx)>>, String)>,
+        p2p: Option<(P2pHandle, UnboundedReceiver<P2pEvent>)>,
+    ) -> eyre::Result<()> {
+        let mut rx_p2p = match p2p {
+            Some((handle, rx_events)) => {
+                self.p2p = Some(handle);
+                Some(rx_events)
+            }
+            None => None,
+        };
+
+        // sync the app with the block store, InitChain on a fresh app
+        self.handshake()?;
+
+        log::info!("starting {} consensus", self.consensus.name());
+        let step = self.consensus.start(&self.context())?;
+        self.apply_step(step)?;
+        let mut ticker = self.consensus.tick_interval().map(interval);
 
         loop {
-            println!("listening and consuming the coming requests....");
+            // the oldest pending tx, or the time since the last block with empty blocks on,
+            // bounds how long we may wait before building a block, unless a block of ours is already in the works or it is not our turn; the
+            // engine wakes us up through `poll_step` once it is
+            let deadline = match self.proposal {
+                Some(_) => None,
+                None if !self.consensus.can_propose(&self.context()) => None,
+                None => self.block_builder.deadline(&self.mempool),
+            };
             tokio::select! {
-                Some(transaction) = rx_output.recv() => {
-                    println!("--------------------------------");
-                    println!("send transaction and consensus start...");
-                    self.handle_tx(transaction, deliver_req.clone())?;
-                    println!("--------------------------------");
+                Some((tx_check, tx_deliver, transaction)) = rx_output.recv() => {
+                    self.handle_check_tx(tx_check, tx_deliver, transaction)?;
 
+                    if self.block_builder.is_full(&self.mempool) {
+                        log::debug!("mempool holds a full block, proposing it");
+                        self.propose_full_block()?;
+                    }
+                },
+                _ = sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                    log::debug!("block interval reached, proposing a block");
+                    self.propose_block()?;
+                },
+                Some(event) = async { rx_p2p.as_mut().unwrap().recv().await }, if rx_p2p.is_some() => {
+                    self.handle_p2p_event(event)?;
+                },
+                // a failing engine event is logged, the engine goes on with the next one
+                _ = async { ticker.as_mut().unwrap().tick().await }, if ticker.is_some() => {
+                    match self.consensus.tick(&self.context()) {
+                        Ok(step) => self.apply_step(step)?,
+                        Err(err) => log::error!("{} engine tick failed: {}", self.consensus.name(), err),
+                    }
+                },
+                step = poll_fn(|cx| self.consensus.poll_step(cx)) => {
+                    match step {
+                        Ok(step) => self.apply_step(step)?,
+                        Err(err) => log::error!("{} engine failed: {}", self.consensus.name(), err),
+                    }
                 },
                 Some((tx_query, req)) = self.rx_abci_queries.recv() => {
-                    println!("********************************");
-                    println!("query start....");
                     self.handle_abci_query(tx_query, req)?;
-                    println!("********************************");
                 }
                 else => break,
             }
@@ -86,36 +196,326 @@ impl Engine {
         Ok(())
     }
 
-    fn handle_tx(&mut self, trans: String, deliver_tx: UnboundedSender<ResponseDeliverTx>) -> eyre::Result<()> {
-        // increment block
-        let proposed_block_height = self.last_block_height + 1;
+    /// Snapshot of the committed chain for the consensus engine.
+    pub fn context(&self) -> ChainContext {
+        ChainContext {
+            store: self.store.clone(),
+            state: self.state.clone(),
+            height: self.last_block_height,
+            last_block_hash: self.last_block_hash.clone(),
+            last_app_hash: self.last_app_hash.to_vec(),
+        }
+    }
 
-        self.last_block_height = proposed_block_height;
+    /// Runs the incoming transaction through `CheckTx` on the mempool connection and
+    /// sends the app's verdict back to the caller. Accepted transactions stay queued
+    /// in the mempool until they are put in a block, and `tx_deliver`, if the caller
+    /// waits for the block, is kept to hand the DeliverTx result back once that happens.
+    fn handle_check_tx(
+        &mut self,
+        tx_check: OneShotSender<ResponseCheckTx>,
+        tx_deliver: Option<OneShotSender<(i64, ResponseDeliverTx)>>,
+        trans: String,
+    ) -> eyre::Result<()> {
+        let resp = match decode(&trans) {
+            Ok(data) => {
+                let tx = Bytes::from(data);
+                let hash = tx_hash(&tx);
+                let resp = self.mempool.check_tx(tx.clone(), self.block_builder.limits())?;
+                if resp.code == 0 {
+                    if let Some(tx_deliver) = tx_deliver {
+                        // callers that gave up waiting leave their waiter behind
+                        self.deliver_waiters.retain(|_, waiter| !waiter.is_closed());
+                        self.deliver_waiters.insert(hash, tx_deliver);
+                    }
+                    self.gossip_tx(&tx, None);
+                }
+                resp
+            }
+            Err(err) => rejected(&format!("failed to decode tx as base64: {}", err)),
+        };
 
-        self.begin_block(proposed_block_height)?;
+        if resp.code != 0 {
+            log::debug!("transaction rejected by CheckTx, code: {:?}, log: {:?}", resp.code, resp.log);
+        }
 
-        self.aggrement_tx(trans, deliver_tx)?;
+        // broadcast_tx_async callers do not wait for the result
+        if let Err(err) = tx_check.send(resp) {
+            log::debug!("caller dropped before receiving the CheckTx result: {:?}", err);
+        }
 
-        self.end_block(proposed_block_height)?;
+        Ok(())
+    }
 
-        self.commit()?;
+    /// Drains the next block from the mempool and hands it to the consensus engine,
+    /// unless a block of ours is in the works or the engine does not let us propose.
+    fn propose_block(&mut self) -> eyre::Result<()> {
+        let ctx = self.context();
+        if self.proposal.is_some() || !self.consensus.can_propose(&ctx) {
+            return Ok(());
+        }
+        let full = self.block_builder.is_full(&self.mempool);
+        let txs = self.block_builder.build(&mut self.mempool);
+        if txs.is_empty() {
+            if !self.block_builder.empty_block_due() {
+                return Ok(());
+            }
+            log::debug!("no block for a while, proposing an empty one");
+        }
+
+        let block = self.aggrement_block(&ctx, &txs);
+        self.proposal = Some(Proposal { txs, full });
+
+        let step = self.consensus.propose(&ctx, block)?;
+        self.apply_step(step)
+    }
+
+    /// Proposes a full block, giving up on a partial block of ours if the engine can
+    /// still cancel it: its txs go back to the mempool and end up at the front of the full block.
+    fn propose_full_block(&mut self) -> eyre::Result<()> {
+        match &self.proposal {
+            Some(proposal) if proposal.full => return Ok(()),
+            Some(_) => {
+                if !self.consensus.cancel_proposal() {
+                    return Ok(());
+                }
+                log::debug!("dropping partial block for a full one");
+                if let Some(proposal) = self.proposal.take() {
+                    self.mempool.requeue(proposal.txs);
+                }
+            }
+            None => {}
+        }
+        self.propose_block()
+    }
+
+    /// Passes a message from a peer to the consensus engine. A message the engine
+    /// refuses is dropped and the peer penalized, a bad peer must not take the node down.
+    pub fn handle_peer_message(&mut self, from: &PeerId, payload: &[u8]) -> eyre::Result<()> {
+        let step = match self.consensus.handle_message(&self.context(), from, payload) {
+            Ok(step) => step,
+            Err(err) => {
+                self.penalize(from, &err);
+                return Ok(());
+            }
+        };
+        self.apply_step(step)
+    }
+
+    fn penalize(&self, from: &PeerId, err: &eyre::Report) {
+        log::warn!("dropping consensus message from {}: {}", from, err);
+        let score = penalty(err);
+        if let Some(p2p) = self.p2p.as_ref().filter(|_| score > 0) {
+            p2p.penalize(from, score, &err.to_string());
+        }
+    }
+
+    fn handle_p2p_event(&mut self, event: P2pEvent) -> eyre::Result<()> {
+        match event {
+            P2pEvent::Connected(peer) => log::info!("{} peers, {} joined", self.peer_count(), peer),
+            P2pEvent::Disconnected(peer) => log::info!("{} peers, {} left", self.peer_count(), peer),
+            P2pEvent::Message { from, message: P2pMessage::Consensus(payload) } => {
+                self.handle_peer_message(&from, &payload)?;
+            }
+            P2pEvent::Message { from, message: P2pMessage::Tx(tx) } => {
+                self.handle_peer_tx(&from, Bytes::from(tx))?;
+                if self.block_builder.is_full(&self.mempool) {
+                    self.propose_full_block()?;
+                }
+            }
+            // the handshake messages never get past the network
+            P2pEvent::Message { .. } => {}
+        }
+        Ok(())
+    }
+
+    /// Runs a tx a peer gossiped through `CheckTx`, and passes it on if the app accepts
+    /// it. A tx already in the mempool is refused, which ends the gossip.
+    fn handle_peer_tx(&mut self, from: &PeerId, tx: Bytes) -> eyre::Result<()> {
+        let resp = self.mempool.check_tx(tx.clone(), self.block_builder.limits())?;
+        if resp.code == 0 {
+            self.gossip_tx(&tx, Some(from));
+        } else {
+            log::debug!("tx from {} rejected by CheckTx, code: {:?}, log: {:?}", from, resp.code, resp.log);
+        }
+        Ok(())
+    }
+
+    // sends a tx accepted into the mempool to the peers, but the one it came from
+    fn gossip_tx(&self, tx: &Bytes, from: Option<&PeerId>) {
+        if let Some(p2p) = &self.p2p {
+            let target = match from {
+                Some(from) => Target::AllExcept(from.clone()),
+                None => Target::All,
+            };
+            p2p.send(&target, P2pMessage::Tx(tx.to_vec()));
+        }
+    }
+
+    fn peer_count(&self) -> usize {
+        self.p2p.as_ref().map_or(0, |p2p| p2p.peers().len())
+    }
+
+    /// Sends out the engine's messages, penalizes the peers it found at fault, moves the
+    /// txs of the blocks that joined or left the engine's chain in or out of the mempool,
+    /// and executes the blocks it committed.
+    pub fn apply_step(&mut self, step: Step) -> eyre::Result<()> {
+        for message in step.messages {
+            self.send_message(message);
+        }
+        for (from, err) in &step.faults {
+            self.penalize(from, err);
+        }
+        for block in step.orphaned {
+            log::info!("block {} orphaned, its txs go back to the mempool", block.get_height());
+            for (tx, resp) in self.mempool.restore(block.get_txs(), self.block_builder.limits())? {
+                self.respond_deliver_tx(&tx_hash(&tx), 0, ResponseDeliverTx {
+                    code: resp.code,
+                    log: format!("tx of an orphaned block refused by CheckTx: {}", resp.log),
+                    codespace: resp.codespace,
+                    ..Default::default()
+                });
+            }
+        }
+        for block in step.pending {
+            self.mempool.mark_reaped(block.get_txs());
+            self.settle_proposal(&block);
+        }
+        if !step.pending.is_empty() || !step.committed.is_empty() {
+            self.block_builder.block_added();
+        }
+        for block in step.committed {
+            self.commit_block(block)?;
+        }
+        self.publish_status();
+        Ok(())
+    }
+
+    // Txs of our proposal that did not make it into a block the engine went with, e.g.
+    // because a peer's block won the height, go back to the mempool.
+    fn settle_proposal(&mut self, block: &Block) {
+        if let Some(proposal) = self.proposal.take() {
+            let included: HashSet<Vec<u8>> = block.get_txs().iter().map(|tx| tx_hash(tx)).collect();
+            self.mempool.requeue(
+                proposal.txs.into_iter().filter(|mem_tx| !included.contains(&mem_tx.hash)).collect(),
+            );
+        }
+    }
+
+    fn send_message(&mut self, message: OutMessage) {
+        match &self.p2p {
+            Some(p2p) => p2p.send(&message.target, P2pMessage::Consensus(message.payload)),
+            // a single node has nobody to talk to
+            None => log::debug!("no peers to send the consensus message to {:?}", message.target),
+        }
+    }
+
+    /// Runs a block the consensus engine committed through BeginBlock / DeliverTx for
+    /// every tx / EndBlock / Commit, and answers the callers waiting on its txs.
+    fn commit_block(&mut self, block: Block) -> eyre::Result<()> {
+        let next_height = self.context().next_height();
+        if block.get_height() != next_height {
+            eyre::bail!("{} engine committed block {}, expected height {}", self.consensus.name(), block.get_height(), next_height);
+        }
+
+        // the block is stored before the app sees it, so it can be replayed after a crash
+        self.store.save_block(&block)?;
+
+        let results = self.execute_block(&block)?;
+
+        log::debug!("block {} committed, app hash {}", block.get_height(), hex::encode_upper(&self.last_app_hash));
+
+        for (tx, result) in block.get_txs().iter().zip(results.deliver_txs.iter()) {
+            self.respond_deliver_tx(&tx_hash(tx), block.get_height(), ResponseDeliverTx::from(result));
+        }
 
-        println!("交易发送成功,当前的app hash为:{:?}", self.last_app_hash);
+        self.settle_proposal(&block);
+
+        // the new state may invalidate txs still waiting in the mempool
+        for (mem_tx, resp) in self.mempool.update(block.get_txs())? {
+            log::debug!("transaction evicted on recheck, code: {:?}, log: {:?}", resp.code, resp.log);
+            self.respond_deliver_tx(&mem_tx.hash, 0, ResponseDeliverTx {
+                code: resp.code,
+                log: format!("tx evicted from mempool on recheck: {}", resp.log),
+                codespace: resp.codespace,
+                ..Default::default()
+            });
+        }
+
+        self.consensus.on_commit(&self.context(), &block)?;
+
+        // nobody may be subscribed, which is fine
+        let _ = self.events.send(Arc::new(CommittedBlock { block, results, state: self.state.clone() }));
+
+        // txs that piled up meanwhile may already fill the next block
+        if self.block_builder.is_full(&self.mempool) {
+            self.propose_block()?;
+        }
 
         Ok(())
     }
 
-    // 这里主要是处理共识的部分，如果要加区块链的共识，就修改这部分的逻辑
-    fn aggrement_tx(&mut self, trans: String, deliver_tx: UnboundedSender<ResponseDeliverTx>) -> eyre::Result<()> {
-        // 达到目标难度值，然后打包，将交易deliver
-        // step1：先计算达到目标难度
-        let pow = ProofOfWork::new(DIFFICULTY);
-        println!("创建了一个pow的难题,现在开始计算");
-        pow.run(trans.clone());
-
-        // step2: 得到一个batch区块(此块非blockchain的块，而是一个节点先打包的块，需要交给app对其中的交易进行状态转换)
-        // 这里暂时没有写mempool，所以会把发送过来的一笔交易直接打包为块，发送出去
-        self.deliver_tx(trans, deliver_tx)
+    // 得到一个batch区块(此块非blockchain的块，而是一个节点先打包的块，需要交给app对其中的交易进行状态转换)
+    // the consensus fields are left to the engine
+    fn aggrement_block(&self, ctx: &ChainContext, txs: &[MempoolTx]) -> Block {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
+
+        let mut block = Block::new(
+            ctx.next_height(),
+            ctx.last_block_hash.clone(),
+            ctx.last_app_hash.clone(),
+            since_epoch.as_nanos() as i64,
+            0,
+            txs.iter().map(|mem_tx| mem_tx.tx.to_vec()).collect(),
+        );
+        block.set_gas_wanted(txs.iter().map(|mem_tx| mem_tx.gas_wanted).sum());
+        block
+    }
+
+    /// Runs a sealed block through the app and stores what the app returned.
+    pub(crate) fn execute_block(&mut self, block: &Block) -> eyre::Result<BlockResults> {
+        let begin_block = self.begin_block(block)?;
+
+        let mut deliver_txs = vec![];
+        for tx in block.get_txs() {
+            let resp = self.deliver_tx(Bytes::from(tx.clone()))?;
+            deliver_txs.push(TxResult::from(&resp));
+        }
+
+        let end_block = self.end_block(block.get_height())?;
+
+        self.commit()?;
+
+        self.last_block_height = block.get_height();
+        self.last_block_hash = block.get_hash();
+
+        let results = BlockResults {
+            height: block.get_height(),
+            begin_block_events: begin_block.events.iter().map(EventRecord::from).collect(),
+            deliver_txs,
+            end_block: EndBlockResult::from(&end_block),
+            app_hash: self.last_app_hash.to_vec(),
+        };
+        self.store.save_results(&results)?;
+
+        // consensus param updates take effect from the next block, validator updates
+        // from the one after it
+        let mut state = self.state.clone();
+        state.apply_end_block(&results.end_block);
+        self.record_validators(block.get_height() + 2, &state.validators)?;
+        self.set_state(state)?;
+
+        Ok(results)
+    }
+
+    /// Hands the DeliverTx result, and the height of the block the tx is in, 0 for a tx
+    /// that left the mempool without a block, to the caller waiting on the tx with the given hash.
+    fn respond_deliver_tx(&mut self, hash: &[u8], height: i64, resp: ResponseDeliverTx) {
+        if let Some(tx_deliver) = self.deliver_waiters.remove(hash) {
+            if let Err(err) = tx_deliver.send((height, resp)) {
+                log::warn!("caller dropped before receiving the DeliverTx result: {:?}", err);
+            }
+        }
     }
 
     fn handle_abci_query(
@@ -123,121 +523,124 @@ impl Engine {
         tx_query: OneShotSender<ResponseQuery>,
         req: QueryInfo,
     ) -> eyre::Result<()> {
-        let req_height = req.height.unwrap_or(0);
-        let req_prove = req.prove;
-
-        /* 
-        Incoming request: Request { value: Some(Query(RequestQuery { data: b"\n-cosmos1syavy2npfyt9tcncdtsdzf7kny9lh777pahuux", path: "/cosmos.bank.v1beta1.Query/AllBalances", height: 0, prove: false })) }
-        Incoming request: Request { value: Some(Query(RequestQuery { data: b"0A2D636F736D6F73317379617679326E706679743974636E63647473647A66376B6E79396C68373737706168757578", path: "/cosmos.bank.v1beta1.Query/AllBalances", height: 0, prove: false })) }
-         */
-        let data = hex::decode(req.data).unwrap();
-        println!("data: {:?}", data);
-        // abci client call the `query` abci api
+        // the app reads a height of 0 as its latest state
+        let height = req.height.unwrap_or(0) as i64;
         let resp = self.req_client.query(RequestQuery {
-            data: data.into(),
-            path: req.path.unwrap(),
-            height: req_height as i64,
-            prove: req_prove,
-        })?; 
-
-        // 通过一个单通道单消费者(oneshoter)向用户返回响应结果
-        println!("the received response is: {:?}", resp);
-
-        let resp_key = match std::str::from_utf8(&resp.key) {
-            Ok(s) => s,
-            Err(e) => panic!("Failed to intepret key as UTF-8: {e}"),
-        };
-        println!("resp key为:{:?}", resp_key);
-
-        println!("resp value为 :{:?}", resp.clone().value);
-
-        if let Err(err) = tx_query.send(resp) {
-            eyre::bail!("{:?}", err);
-        } 
+            data: req.data.into(),
+            path: req.path.unwrap_or_default(),
+            height,
+            prove: req.prove,
+        })?;
+        log::debug!("the received response is: {:?}", resp);
+
+        if tx_query.send(resp).is_err() {
+            log::warn!("caller dropped before receiving the query result");
+        }
 
         Ok(())
     }
 }
 
 impl Engine {
-    /// Calls the `InitChain` hook on the app, ignores "already initialized" errors.
+    /// Calls the `InitChain` hook on the app with the genesis file, and records the app hash,
+    /// validators and consensus params the app returns. Ignores "already initialized" errors.
     pub fn init_chain(&mut self) -> eyre::Result<()> {
-        // 如果之前已经启动过，那么不会进行初始化
-        if self.last_block_height > 1 {
-            println!("start from the exist block data.....");
-            println!("当前区块高度为:{:?}", self.last_block_height);
-        } else {
-            let mut client = ClientBuilder::default().connect(&self.app_address).unwrap();
-            println!("start init chain request");
-            //TODO: 后续需要做改动，把初始化账户的功能放在这里（钱包和genesis account的功能是应该放在共识层的）
-            /*         match client.init_chain(Default::default()) {
-            /*             Ok(resp) => {
-                            println!("Init chain successfully! Hello ABCI.");
-                            self.last_app_hash = resp.app_hash
-                        } */
-                        Ok(_) => {
-                            println!("Init chain successfully! Hello ABCI.")
-                        }
-                        Err(err) => {
-                            // ignore errors about the chain being uninitialized
-                            if err.to_string().contains("already initialized") {
-                                log::warn!("{}", err);
-                                return Ok(());
-                            }
-                            eyre::bail!(err)
-                        }
-                    };  */
-    
-            // let resp = client.init_chain();
-            // println!("{:?}", resp);
+        log::info!("InitChain with chain id {:?}", self.genesis.chain_id);
+        let req = self.genesis.to_init_chain_request()?;
+
+        let resp = match self.client.init_chain(req) {
+            Ok(resp) => {
+                log::info!("InitChain done");
+                resp
+            }
+            Err(err) => {
+                // ignore errors about the chain being already initialized
+                if !err.to_string().contains("already initialized") {
+                    eyre::bail!(err)
+                }
+                log::warn!("{}", err);
+                ResponseInitChain {
+                    app_hash: self.last_app_hash.clone(),
+                    ..Default::default()
+                }
+            }
+        };
+
+        self.last_app_hash = resp.app_hash.clone();
+        self.set_state(ChainState::from_genesis(&self.genesis, &resp)?)?;
+
+        // the genesis validators vote on the first two blocks
+        let initial_height = self.state.initial_height;
+        for height in initial_height..initial_height + 2 {
+            self.record_validators(height, &self.state.validators)?;
+        }
+        Ok(())
+    }
+
+    // Saves the validator set voting on a height, unless the store has one already: the
+    // sets of replayed blocks were recorded when the blocks were first executed.
+    fn record_validators(&self, height: i64, validators: &[ValidatorUpdateRecord]) -> eyre::Result<()> {
+        if self.store.load_validators(height)?.is_none() {
+            self.store.save_validators(height, validators)?;
         }
         Ok(())
     }
 
-    /// Calls the `BeginBlock` hook on the ABCI app. For now, it just makes a request with
-    /// the new block height.
+    /// Replaces the chain state, persists it and applies its consensus params.
+    pub(crate) fn set_state(&mut self, state: ChainState) -> eyre::Result<()> {
+        self.store.save_state(&state)?;
+        self.block_builder.set_block_params(state.block_params.max_bytes, state.block_params.max_gas);
+        self.state = state;
+        Ok(())
+    }
+
+    /// Calls the `BeginBlock` hook on the ABCI app with the header of the block being executed,
+    /// and returns the app's response so its events can be stored with the block.
     // If we wanted to, we could add additional arguments to be forwarded from the Consensus
     // to the App logic on the beginning of each block.
-    fn begin_block(&mut self, height: i64) -> eyre::Result<()> {
-        let now = SystemTime::now();
-        let since_epoch = now.duration_since(UNIX_EPOCH).expect("Time went backwards");
-        let proposer_addr: Vec<u8> = vec![44, 59, 240, 228, 247, 237, 123, 26, 6, 191, 172, 106, 236, 240, 203, 76, 139, 146, 50, 40];
+    fn begin_block(&mut self, block: &Block) -> eyre::Result<ResponseBeginBlock> {
+        let (seconds, nanos) = block.get_time();
 
         let req = RequestBeginBlock {
             header: Some(Header {
                 version: std::option::Option::Some(Consensus{
-                    block: 11,
-                    app: 0,
+                    block: BLOCK_PROTOCOL,
+                    app: self.state.app_version,
                 }),
-                chain_id: "test-chain".to_string(),
+                chain_id: self.state.chain_id.clone(),
                 time: std::option::Option::Some(Timestamp{
-                    seconds: since_epoch.as_secs() as i64,
-                    nanos: since_epoch.subsec_nanos() as i32,
+                    seconds,
+                    nanos,
+                }),
+                height: block.get_height(),
+                // the previous block's PoW hash, empty for the first block
+                last_block_id: Some(BlockId {
+                    hash: block.get_header().prev_hash.clone(),
+                    part_set_header: Some(PartSetHeader::default()),
                 }),
-                height: self.last_block_height,
+                // merkle root of the txs the PoW committed to
+                data_hash: block.get_header().txs_hash.clone(),
                 // current app hash(对于区块链来说，这里是当前最新的区块哈希)
-                app_hash: self.last_app_hash.to_vec().clone(),
-                proposer_address: proposer_addr,
+                app_hash: block.get_header().app_hash.clone(),
+                // the signer of the block, or the first validator for unsigned blocks
+                proposer_address: if block.get_header().proposer.is_empty() {
+                    self.state.proposer_address()
+                } else {
+                    block.get_header().proposer.clone()
+                },
                 ..Default::default()
 
             }),
+            // votes that committed the previous block, for the engines that collect them
+            last_commit_info: block.get_last_commit().map(LastCommitInfo::from),
             ..Default::default()
         };
 
-        self.client.begin_block(req)?;
-        Ok(())
+        Ok(self.client.begin_block(req)?)
     }
 
-
-
-    // TODO: 后续可以增加checkTx，用于存放内存池之前的校验
-
     /// Calls the `DeliverTx` hook on the ABCI app.
-    fn deliver_tx(&mut self, tx: String, deliver_tx_chan: UnboundedSender<ResponseDeliverTx>) -> eyre::Result<()> {
-
-        let data = decode(tx).expect("Failed to decode Base64 data");
-        let tx_bytes = Bytes::from(data);
-
+    fn deliver_tx(&mut self, tx_bytes: Bytes) -> eyre::Result<ResponseDeliverTx> {
         let deliver_tx_resp = match self.client.deliver_tx(RequestDeliverTx { tx: tx_bytes }) {
             Ok(response) => response,
             Err(err) => {
@@ -254,25 +657,24 @@ impl Engine {
                 }
             }
         };
-        println!("deliver tx的返回消息为{:?}", deliver_tx_resp.clone());
-        deliver_tx_chan.send(deliver_tx_resp);
+        log::trace!("DeliverTx response: {:?}", deliver_tx_resp);
 
-        Ok(())
+        Ok(deliver_tx_resp)
     }
 
-    /// Calls the `EndBlock` hook on the ABCI app. For now, it just makes a request with
-    /// the proposed block height.
+    /// Calls the `EndBlock` hook on the ABCI app with the proposed block height and
+    /// returns the app's response so it can be stored with the block.
     // If we wanted to, we could add additional arguments to be forwarded from the Consensus
     // to the App logic on the end of each block.
-    fn end_block(&mut self, height: i64) -> eyre::Result<()> {
+    fn end_block(&mut self, height: i64) -> eyre::Result<ResponseEndBlock> {
         let req = RequestEndBlock { height };
-        let _resp = self.client.end_block(req)?;
+        let resp = self.client.end_block(req)?;
 
         // save the ened block hash(app hash) returned by the abci server
         /*         let event: Event = resp.events.first().unwrap().clone();
         let event_attribute: EventAttribute = event.attributes.first().unwrap().clone();
         self.last_app_hash = event_attribute.value.clone(); */
-        Ok(())
+        Ok(resp)
     }
 
     /// Calls the `Commit` hook on the ABCI app.
@@ -284,6 +686,19 @@ impl Engine {
     }
 }
 
+// How much a peer whose message the engine refused is penalized. A block or vote that
+// breaks the chain rules gets it banned, a message that does not decode counts less as
+// it may come from an older version, and failures of our own are not the peer's fault.
+fn penalty(err: &eyre::Report) -> u32 {
+    if err.is::<BlockError>() || err.is::<PowError>() || err.is::<PoaError>() || err.is::<TendermintError>() {
+        BAN_SCORE
+    } else if let Some(BlockchainError::SerializeError(_)) = err.downcast_ref() {
+        BAN_SCORE / 5
+    } else {
+        0
+    }
+}
+
 pub fn counter_to_bytes(counter: u64) -> [u8; 8] {
     counter.to_be_bytes()
 }
@@ -1,70 +1,84 @@
-use crate::{QueryInfo, Transaction};
-
-use eyre::WrapErr;
-use futures::SinkExt;
-use tendermint_proto::{abci::{ResponseQuery, ResponseDeliverTx}, crypto::ProofOps};
-use tendermint_rpc::{
-    endpoint,
-    error::{Error, ErrorDetail},
-    request::Wrapper as RequestWrapper,
-    Code, Order, Response,
-};
-use tokio::spawn;
-use tokio::sync::mpsc::{Sender, Receiver, UnboundedReceiver, UnboundedSender};
-use tokio::sync::oneshot::{channel as oneshot_channel, Sender as OneShotSender};
+use std::net::SocketAddr;
 use std::sync::Arc;
-use tokio::sync::Mutex;
-use serde_json::to_vec;
-use super::{EventAttributeJson, EventJson};
+use std::time::Duration;
 
-use tokio::net::TcpStream;
-use tokio_util::codec::{Framed, LengthDelimitedCodec};
+use serde_json::Value;
+use tendermint_proto::abci::{ResponseCheckTx, ResponseDeliverTx, ResponseQuery};
+use tokio::sync::mpsc::Sender;
+use tokio::sync::oneshot::{channel as oneshot_channel, Sender as OneShotSender};
+use tokio::sync::{broadcast, watch};
 use warp::{Filter, Rejection};
-// use super::MyResponseQuery;
-use base64::encode;
 
-use core::panic;
-use std::net::SocketAddr;
+use crate::error::RpcError;
+use crate::rpc::{broadcast_tx_commit_json, broadcast_tx_json, query_request, query_response_json, response_json};
+use crate::rpc::{serve_websocket, tx_param, RpcNode};
+use crate::{tx_hash, ChainStatus, CommittedBlock, QueryInfo};
+
 /// Client Api which will provide a exposed port(eg:26657) for users to get and post msg
 pub struct ClientApi<T> {
     // commonly: 26657 port
     abci_client_address: SocketAddr,
     req: Sender<(OneShotSender<T>, QueryInfo)>,
-    deliver_rx: Arc<Mutex<UnboundedReceiver<ResponseDeliverTx>>>
+    // where the chain is, from `Engine::subscribe_status`
+    status: watch::Receiver<ChainStatus>,
+    // blocks, results and node info for the methods the app is not involved in
+    node: RpcNode,
+    // how long broadcast_tx_commit waits for the tx to be in a block
+    commit_timeout: Duration,
+    // committed blocks, from `Engine::events`, for the websocket subscriptions
+    events: broadcast::Sender<Arc<CommittedBlock>>,
 }
 
 impl<T: Send + Sync + std::fmt::Debug> ClientApi<T> {
     pub fn new(
         abci_client_address: SocketAddr,
         req: Sender<(OneShotSender<T>, QueryInfo)>,
-        deliver_rx: UnboundedReceiver<ResponseDeliverTx>
+        status: watch::Receiver<ChainStatus>,
+        node: RpcNode,
+        commit_timeout: Duration,
+        events: broadcast::Sender<Arc<CommittedBlock>>,
     ) -> Self {
         Self {
             abci_client_address,
             req,
-            deliver_rx: Arc::new(Mutex::new(deliver_rx)),
+            status,
+            node,
+            commit_timeout,
+            events,
         }
     }
 }
 
-use serde::{Deserialize, Serialize};
-use serde_json::{json, Value};
-use warp::Reply;
-use core::str::Bytes;
+// A JSON-RPC response with the result of a method, or its error as CometBFT reports them.
+fn rpc_reply(id: &Value, result: Result<Value, RpcError>) -> warp::reply::Json {
+    warp::reply::json(&response_json(id, result))
+}
 
 impl ClientApi<ResponseQuery> {
     pub fn get_routes(
         self,
-        tx_req: Sender<String>,
+        tx_req: Sender<(OneShotSender<ResponseCheckTx>, Option<OneShotSender<(i64, ResponseDeliverTx)>>, String)>,
     ) -> impl Filter<Extract = impl warp::Reply, Error = Rejection> + Clone {
 
+        let events = self.events.clone();
+        // CometBFT's event subscriptions, each connection with its own receiver
+        let route_websocket = warp::path("websocket")
+            .and(warp::path::end())
+            .and(warp::ws())
+            .map(move |ws: warp::ws::Ws| {
+                let committed = events.subscribe();
+                ws.on_upgrade(move |socket| serve_websocket(socket, committed))
+            });
+
         let route_abci = warp::path::end() 
             .and(warp::post()) 
             .and(warp::body::json()) 
             .and_then(move |json_request: serde_json::Value| {
                 let tx_abci_queries = self.req.clone();
                 let abci_tx = tx_req.clone();
-                let deliver_rx_resp = self.deliver_rx.clone();
+                let status = self.status.borrow().clone();
+                let node = self.node.clone();
+                let commit_timeout = self.commit_timeout;
 
                 async move {
                     let method = json_request["method"].as_str().unwrap_or_default();
@@ -72,115 +86,93 @@ impl ClientApi<ResponseQuery> {
     
                     match method {
                         "abci_query" => {
-                            println!("start abci_query req");
-                            let path = params["path"].as_str().unwrap_or_default();
-                            match path {
-                                "/cosmos.bank.v1beta1.Query/AllBalances" | "/cosmos.auth.v1beta1.Query/Account" => {
-                                    let data = params["data"].as_str().unwrap_or_default();
-                                    let prove = params["prove"].as_bool().unwrap_or_default();
-            
-            
-                                    let req = QueryInfo {
-                                        path: Some(String::from(path)),
-                                        data: data.as_bytes().to_vec(),
-                                        height: None,
-                                        prove,
-                                    };
-
-                                    println!("query request: {:?}", req.clone());
-
-                                    let (tx_query, rx_query) = oneshot_channel();
-                                    match tx_abci_queries.send((tx_query, req.clone())).await {
-                                        Ok(_) => {}
-                                        Err(err) => log::error!("Error forwarding abci query: {}", err),
-                                    };
-                                    let resp: ResponseQuery = rx_query.await.unwrap();
-
-                                    println!("ID is {:?}", json_request["id"]);
-
-                                    // let result = format!("Hello, World! Your path: {}, data: {}, prove: {}", path, data, prove);
-                                    Ok(warp::reply::json(&serde_json::json!({
-                                        "jsonrpc": "2.0".to_string(),
-                                        "result": {
-                                            "response": {
-                                                "code": resp.code,
-                                                "log": resp.log,
-                                                "info": resp.info,
-                                                "index": resp.index.to_string(),
-                                                "key": encode(&resp.key),
-                                                "value": encode(&resp.value),
-                                                "proofOps": resp.proof_ops,
-                                                "height": resp.height.to_string(),
-                                                "codespace": resp.codespace,
-                                            },
-                                        },
-                                        "id": json_request["id"]
-                                    })))
-                                }
-                                _ => {
-                                    let result = format!("Hello, World!");
-                                    Ok(warp::reply::json(&serde_json::json!({
-                                        "jsonrpc": "2.0".to_string(),
-                                        "result": result,
-                                        "id": json_request["id"]
-                                    })))
-                                }
+                            let req = match query_request(params) {
+                                Ok(req) => req,
+                                Err(err) => return Ok(rpc_reply(&json_request["id"], Err(err))),
+                            };
+                            log::debug!("query request: {:?}", req);
+
+                            let (tx_query, rx_query) = oneshot_channel();
+                            if let Err(err) = tx_abci_queries.send((tx_query, req)).await {
+                                log::error!("Error forwarding abci query: {}", err);
                             }
-                            
+                            let result = match rx_query.await {
+                                Ok(resp) => Ok(query_response_json(&resp)),
+                                Err(_) => Err(RpcError::NodeStopped),
+                            };
+                            Ok(rpc_reply(&json_request["id"], result))
                         }
                         "broadcast_tx_commit" => {
-                            println!("start broadcast_tx_commit req");
-                            let transaction = params["tx"].as_str().unwrap_or_default();
-                            // let result = format!("Hello, World! Your transaction is : {}", transaction);
-                            println!("交易数据是{:?}", transaction);
+                            let hash = match tx_param(params) {
+                                Ok(tx) => tx_hash(&tx),
+                                Err(err) => return Ok(rpc_reply(&json_request["id"], Err(err))),
+                            };
+                            let transaction = params["tx"].as_str().unwrap_or_default().to_string();
+                            let (tx_check, rx_check) = oneshot_channel();
+                            let (tx_deliver, rx_deliver) = oneshot_channel();
+                            if abci_tx.send((tx_check, Some(tx_deliver), transaction)).await.is_err() {
+                                return Ok(rpc_reply(&json_request["id"], Err(RpcError::NodeStopped)));
+                            }
 
-                            if let Err(e) = abci_tx.send(transaction.to_string()).await {
-                                let result = format!("ERROR IN: broadcast_tx_commit: {:?}. Err: {}",transaction, e);
-                                Ok(warp::reply::json(&serde_json::json!({
-                                    "jsonrpc": "2.0".to_string(),
-                                    "result": result,
-                                    "id": json_request["id"]
-                                })))
+                            let check_resp = match rx_check.await {
+                                Ok(resp) => resp,
+                                Err(_) => return Ok(rpc_reply(&json_request["id"], Err(RpcError::NodeStopped))),
+                            };
+                            let result = if check_resp.code != 0 {
+                                // rejected by CheckTx, the tx never reaches a block
+                                Ok(broadcast_tx_commit_json(&hash, &check_resp, None))
                             } else {
-                                let mut deliver_rx_guard = deliver_rx_resp.lock().await;
-                                let deliver_resp = deliver_rx_guard.recv().await.unwrap();
-
-                                let events = deliver_resp.clone().events;
-                                let events_json: Vec<EventJson> = events.iter().map(|event| EventJson {
-                                    r#type: event.r#type.clone(),
-                                    attributes: event.attributes.iter().map(|attr| EventAttributeJson {
-                                        key: base64::encode(attr.key.to_vec().as_slice()),
-                                        value: base64::encode(attr.value.to_vec().as_slice()),
-                                        index: attr.index,
-                                    }).collect(),
-                                }).collect();
-
-
-                                Ok(warp::reply::json(&serde_json::json!({
-                                    "jsonrpc": "2.0".to_string(),
-                                    "result": {
-                                        "deliver_tx": {
-                                            "code": deliver_resp.code,
-                                            "data": deliver_resp.data,
-                                            "log": deliver_resp.log,
-                                            "info": deliver_resp.info,
-                                            "gas_wanted": deliver_resp.gas_wanted,
-                                            "gas_used": deliver_resp.gas_used,
-                                            "events": events_json,
-                                            "codespace": deliver_resp.codespace,
-                                        },
-                                    },
-                                    "id": json_request["id"]
-                                })))
+                                match tokio::time::timeout(commit_timeout, rx_deliver).await {
+                                    Ok(Ok(delivered)) => Ok(broadcast_tx_commit_json(&hash, &check_resp, Some(delivered))),
+                                    Ok(Err(_)) => Err(RpcError::NodeStopped),
+                                    Err(_) => Err(RpcError::CommitTimeout { hash: hex::encode_upper(&hash) }),
+                                }
+                            };
+                            Ok(rpc_reply(&json_request["id"], result))
+                        }
+                        "broadcast_tx_sync" | "broadcast_tx_async" => {
+                            let hash = match tx_param(params) {
+                                Ok(tx) => tx_hash(&tx),
+                                Err(err) => return Ok(rpc_reply(&json_request["id"], Err(err))),
+                            };
+                            let transaction = params["tx"].as_str().unwrap_or_default().to_string();
+                            let (tx_check, rx_check) = oneshot_channel();
+                            if abci_tx.send((tx_check, None, transaction)).await.is_err() {
+                                return Ok(rpc_reply(&json_request["id"], Err(RpcError::NodeStopped)));
                             }
+                            let result = if method == "broadcast_tx_async" {
+                                Ok(broadcast_tx_json(&hash, None))
+                            } else {
+                                match rx_check.await {
+                                    Ok(resp) => Ok(broadcast_tx_json(&hash, Some(&resp))),
+                                    Err(_) => Err(RpcError::NodeStopped),
+                                }
+                            };
+                            Ok(rpc_reply(&json_request["id"], result))
                         }
+                        "status" => Ok(rpc_reply(&json_request["id"], node.status(&status))),
+                        "abci_info" => Ok(rpc_reply(&json_request["id"], node.abci_info(&status))),
+                        "block" => Ok(rpc_reply(&json_request["id"], node.block(&status, params))),
+                        "block_results" => Ok(rpc_reply(&json_request["id"], node.block_results(&status, params))),
+                        "blockchain" => Ok(rpc_reply(&json_request["id"], node.blockchain(&status, params))),
+                        "commit" => Ok(rpc_reply(&json_request["id"], node.commit(&status, params))),
+                        "validators" => Ok(rpc_reply(&json_request["id"], node.validators(&status, params))),
+                        "genesis" => Ok(rpc_reply(&json_request["id"], node.genesis())),
+                        "health" => Ok(rpc_reply(&json_request["id"], node.health())),
+                        "net_info" => Ok(rpc_reply(&json_request["id"], node.net_info())),
+                        "tx" => Ok(rpc_reply(&json_request["id"], node.tx(params))),
+                        "tx_search" => Ok(rpc_reply(&json_request["id"], node.tx_search(&status, params))),
                         _ => {
-                            Err(warp::reject::not_found())
+                            Ok(warp::reply::json(&serde_json::json!({
+                                "jsonrpc": "2.0".to_string(),
+                                "error": { "code": -32601, "message": "Method not found", "data": "" },
+                                "id": json_request["id"]
+                            })))
                         }
                     }
                 }
             });
 
-        route_abci
+        route_websocket.or(route_abci)
     }
 }
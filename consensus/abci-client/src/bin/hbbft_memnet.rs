@@ -0,0 +1,52 @@
+use clap::{App, Arg};
+use eyre::Result;
+use hbbft::NetworkInfo;
+
+use pow_abci::{ChainState, HbbftEngine, MemNetwork};
+
+// Runs N hbbft nodes in one process over the in-memory network, each proposing a few
+// txs per epoch, and checks that they all commit the very same blocks.
+fn main() -> Result<()> {
+    let matches = App::new("hbbft_memnet")
+        .about("run N hbbft nodes in process and check they agree on the same blocks")
+        .arg(Arg::with_name("nodes").long("nodes").takes_value(true).default_value("4"))
+        .arg(Arg::with_name("blocks").long("blocks").takes_value(true).default_value("3"))
+        .get_matches();
+
+    let nodes: usize = matches.value_of("nodes").unwrap().parse()?;
+    let blocks: i64 = matches.value_of("blocks").unwrap().parse()?;
+
+    let ids: Vec<String> = (0..nodes).map(|i| format!("node-{}", i)).collect();
+    let netinfos = NetworkInfo::generate_map(ids.clone(), &mut rand::thread_rng())
+        .map_err(|err| eyre::eyre!("failed to generate the hbbft keys: {:?}", err))?;
+
+    let state = ChainState {
+        chain_id: "hbbft-memnet".to_string(),
+        initial_height: 1,
+        ..Default::default()
+    };
+    let mut network = MemNetwork::new();
+    for (id, netinfo) in netinfos {
+        network.add_node(id, Box::new(HbbftEngine::with_netinfo(netinfo)), state.clone())?;
+    }
+
+    for height in 1..=blocks {
+        for (i, id) in ids.iter().enumerate() {
+            let tx = format!("tx-{}-from-{}", height, i).into_bytes();
+            network.propose(id, vec![tx], height * 1_000_000_000)?;
+        }
+        network.run()?;
+
+        let expected = network.block(&ids[0], height)?.map(|block| block.get_hash());
+        for id in &ids {
+            let block = network.block(id, height)?;
+            if block.as_ref().map(|block| block.get_hash()) != expected || expected.is_none() {
+                eyre::bail!("node {} did not commit the same block {} as {}", id, height, ids[0]);
+            }
+        }
+        let block = network.block(&ids[0], height)?.unwrap();
+        println!("all {} nodes committed block {} with {} txs", nodes, height, block.get_txs().len());
+    }
+
+    Ok(())
+}
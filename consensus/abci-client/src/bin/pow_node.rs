@@ -1,10 +1,17 @@
 use eyre::{Result, WrapErr};
-use std::net::{SocketAddr, IpAddr, Ipv4Addr};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
 
-use clap::{crate_name, crate_version, App, AppSettings, ArgMatches, SubCommand};
-use tokio::sync::mpsc::{channel, Receiver, unbounded_channel};
+use clap::{crate_name, crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+use tokio::sync::mpsc::{channel, Receiver};
+use tracing_subscriber::EnvFilter;
 
-use pow_abci::{ClientApi, Engine};
+use pow_abci::{
+    consensus_engine, deal_hbbft_keys, p2p, default_home, ClientApi, Engine, Genesis, GenesisValidator, NodeConfig, NodeKey,
+    PoaAuthorities, PoaAuthority, PrivValidatorKey, SledBlockStore, SLOT_MS,
+};
+use pow_abci::rpc::{NodeInfo, RpcNode};
 
 pub const CHANNEL_CAPACITY: usize = 1_000;
 
@@ -14,61 +21,372 @@ async fn main() -> Result<()> {
         .version(crate_version!())
         .about("a minimal practice of replacing the tendermint consensus with pow adapting the abci")
         .args_from_usage("-v... 'Sets the level of verbosity")
+        .subcommand(
+            SubCommand::with_name("init")
+                .about("Create the node home directory with config.toml, genesis.json, a node key and the data directory")
+                .arg(home_arg())
+                .args(&setting_args())
+                .arg(Arg::with_name("moniker").long("moniker").takes_value(true).help("Name of the node"))
+                .arg(Arg::with_name("genesis").long("genesis").takes_value(true).help("Copy this genesis file (e.g. the one created by `gaia-rs init`) instead of creating a new one"))
+        )
+        .subcommand(
+            SubCommand::with_name("hbbft-keys")
+                .about("Deal the hbbft threshold keys to the validator homes, and write the validator set to their genesis files")
+                .arg(Arg::with_name("homes").required(true).multiple(true).help("Home directories of the validators, already initialized"))
+        )
+        .subcommand(
+            SubCommand::with_name("poa-authorities")
+                .about("Write the node keys of the authority homes, in turn order, to their genesis files")
+                .arg(Arg::with_name("slot").long("slot").takes_value(true).help("Length of a turn in milliseconds [default: 2000]"))
+                .arg(Arg::with_name("homes").required(true).multiple(true).help("Home directories of the authorities, already initialized"))
+        )
+        .subcommand(
+            SubCommand::with_name("validators")
+                .about("Write the validator keys of the homes to their genesis files, as the validators InitChain starts with")
+                .arg(Arg::with_name("power").long("power").takes_value(true).help("Voting power of each validator [default: 10]"))
+                .arg(Arg::with_name("homes").required(true).multiple(true).help("Home directories of the validators, already initialized"))
+        )
         .subcommand(
             SubCommand::with_name("run")
-            //TODO:
-            // .args_from_usage("--port=<string> 'This is a abci client server'")
+                .about("Run the node with the config from the home directory")
+                .arg(home_arg())
+                .args(&setting_args())
         )
         .setting(AppSettings::SubcommandRequiredElseHelp)
         .get_matches();
-    
+
+    // info by default, -v for debug and -vv for trace, RUST_LOG takes precedence
+    let level = match matches.occurrences_of("v") {
+        0 => "info",
+        1 => "debug",
+        _ => "trace",
+    };
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    tracing_subscriber::fmt().with_env_filter(filter).init();
+
     match matches.subcommand() {
-        ("run", Some(sub_matches)) => run().await?,
-        // ("run", Some(sub_matches)) => run(sub_matches).await?,
+        ("init", Some(sub_matches)) => init(sub_matches)?,
+        ("hbbft-keys", Some(sub_matches)) => hbbft_keys(sub_matches)?,
+        ("poa-authorities", Some(sub_matches)) => poa_authorities(sub_matches)?,
+        ("validators", Some(sub_matches)) => validators(sub_matches)?,
+        ("run", Some(sub_matches)) => run(sub_matches).await?,
         _ => unreachable!(),
     }
     Ok(())
 }
 
+fn home_arg() -> Arg<'static, 'static> {
+    Arg::with_name("home")
+        .long("home")
+        .takes_value(true)
+        .env("POW_HOME")
+        .help("Node home directory [default: $HOME/.pow-node]")
+}
+
+// settings of config.toml that can be overridden from the command line or the environment
+fn setting_args() -> Vec<Arg<'static, 'static>> {
+    vec![
+        Arg::with_name("rpc.laddr").long("rpc.laddr").takes_value(true).env("POW_RPC_LADDR")
+            .help("RPC listen address, e.g. 127.0.0.1:26657"),
+        Arg::with_name("rpc.timeout_broadcast_tx_commit").long("rpc.timeout_broadcast_tx_commit").takes_value(true)
+            .env("POW_RPC_TIMEOUT_BROADCAST_TX_COMMIT")
+            .help("How long broadcast_tx_commit waits for the tx to be in a block, in milliseconds"),
+        Arg::with_name("proxy_app").long("proxy_app").takes_value(true).env("POW_PROXY_APP")
+            .help("ABCI app address, e.g. 127.0.0.1:26658"),
+        Arg::with_name("engine").long("engine").takes_value(true).env("POW_ENGINE")
+            .possible_values(&["pow", "instant", "hbbft", "poa", "tendermint", "raft"])
+            .help("Consensus engine"),
+        Arg::with_name("difficulty").long("difficulty").takes_value(true).env("POW_DIFFICULTY")
+            .help("PoW difficulty of the first blocks in leading zero bits, retargeted afterwards"),
+        Arg::with_name("target-block-time").long("target-block-time").takes_value(true).env("POW_TARGET_BLOCK_TIME")
+            .help("Block time the difficulty retarget aims for, in milliseconds"),
+        Arg::with_name("mining-threads").long("mining-threads").takes_value(true).env("POW_MINING_THREADS")
+            .help("Threads searching for nonces, 0 for one per CPU"),
+        Arg::with_name("confirmation-depth").long("confirmation-depth").takes_value(true).env("POW_CONFIRMATION_DEPTH")
+            .help("Blocks on top of a PoW block before it is final and executed by the app"),
+        Arg::with_name("chain-id").long("chain-id").takes_value(true).env("POW_CHAIN_ID")
+            .help("Chain id"),
+        Arg::with_name("block-interval").long("block-interval").takes_value(true).env("POW_BLOCK_INTERVAL")
+            .help("Longest time a pending tx waits for a block, in milliseconds"),
+        Arg::with_name("create-empty-blocks").long("create-empty-blocks").takes_value(true).env("POW_CREATE_EMPTY_BLOCKS")
+            .possible_values(&["true", "false"])
+            .help("Whether blocks are made when no tx is pending"),
+        Arg::with_name("create-empty-blocks-interval").long("create-empty-blocks-interval").takes_value(true)
+            .env("POW_CREATE_EMPTY_BLOCKS_INTERVAL")
+            .help("Time without a block after which an empty block is made, in milliseconds, 0 for the block interval"),
+        Arg::with_name("p2p.laddr").long("p2p.laddr").takes_value(true).env("POW_P2P_LADDR")
+            .help("P2P listen address, e.g. 0.0.0.0:26656"),
+        Arg::with_name("p2p.persistent_peers").long("p2p.persistent_peers").takes_value(true).env("POW_P2P_PERSISTENT_PEERS")
+            .help("Comma separated peers to stay connected to, as <node id>@<host>:<port>"),
+    ]
+}
+
+fn home_dir(matches: &ArgMatches<'_>) -> PathBuf {
+    matches.value_of("home").map(PathBuf::from).unwrap_or_else(default_home)
+}
+
+fn apply_settings(config: &mut NodeConfig, matches: &ArgMatches<'_>) -> Result<()> {
+    if let Some(laddr) = matches.value_of("rpc.laddr") {
+        config.rpc.laddr = laddr.parse().wrap_err("invalid --rpc.laddr")?;
+    }
+    if let Some(timeout) = matches.value_of("rpc.timeout_broadcast_tx_commit") {
+        config.rpc.timeout_broadcast_tx_commit_ms = timeout.parse().wrap_err("invalid --rpc.timeout_broadcast_tx_commit")?;
+    }
+    if let Some(proxy_app) = matches.value_of("proxy_app") {
+        config.abci.proxy_app = proxy_app.parse().wrap_err("invalid --proxy_app")?;
+    }
+    if let Some(engine) = matches.value_of("engine") {
+        config.consensus.engine = engine.parse()?;
+    }
+    if let Some(difficulty) = matches.value_of("difficulty") {
+        config.consensus.difficulty = difficulty.parse().wrap_err("invalid --difficulty")?;
+    }
+    if let Some(target) = matches.value_of("target-block-time") {
+        config.consensus.target_block_time_ms = target.parse().wrap_err("invalid --target-block-time")?;
+    }
+    if let Some(threads) = matches.value_of("mining-threads") {
+        config.consensus.mining_threads = threads.parse().wrap_err("invalid --mining-threads")?;
+    }
+    if let Some(depth) = matches.value_of("confirmation-depth") {
+        config.consensus.confirmation_depth = depth.parse().wrap_err("invalid --confirmation-depth")?;
+    }
+    if let Some(chain_id) = matches.value_of("chain-id") {
+        config.chain_id = chain_id.to_string();
+    }
+    if let Some(interval) = matches.value_of("block-interval") {
+        config.consensus.block_interval_ms = interval.parse().wrap_err("invalid --block-interval")?;
+    }
+    if let Some(create) = matches.value_of("create-empty-blocks") {
+        config.consensus.create_empty_blocks = create.parse().wrap_err("invalid --create-empty-blocks")?;
+    }
+    if let Some(interval) = matches.value_of("create-empty-blocks-interval") {
+        config.consensus.create_empty_blocks_interval_ms = interval.parse().wrap_err("invalid --create-empty-blocks-interval")?;
+    }
+    if let Some(laddr) = matches.value_of("p2p.laddr") {
+        config.p2p.laddr = laddr.parse().wrap_err("invalid --p2p.laddr")?;
+    }
+    if let Some(peers) = matches.value_of("p2p.persistent_peers") {
+        config.p2p.persistent_peers = peers.split(',').filter(|peer| !peer.trim().is_empty()).map(|peer| peer.trim().to_string()).collect();
+    }
+    Ok(())
+}
+
+fn init(sub_matches: &ArgMatches<'_>) -> Result<()> {
+    let home = home_dir(sub_matches);
+
+    let mut config = NodeConfig {
+        home: home.clone(),
+        ..Default::default()
+    };
+    apply_settings(&mut config, sub_matches)?;
+    if let Some(moniker) = sub_matches.value_of("moniker") {
+        config.moniker = moniker.to_string();
+    }
+
+    fs::create_dir_all(config.config_dir())?;
+    fs::create_dir_all(config.data_dir())?;
+
+    // existing files are kept, so init can be re-run safely
+    let config_file = config.config_dir().join(pow_abci::CONFIG_FILE);
+    if config_file.exists() {
+        println!("Found config file: {:?}", config_file);
+    } else {
+        config.save()?;
+        println!("Generated config file: {:?}", config_file);
+    }
+
+    let node_key_file = config.node_key_file();
+    if node_key_file.exists() {
+        println!("Found node key: {:?}", node_key_file);
+    } else {
+        let node_key = NodeKey::generate();
+        node_key.save(&node_key_file)?;
+        println!("Generated node key {} in {:?}", node_key.id(), node_key_file);
+    }
+
+    let priv_validator_key_file = config.priv_validator_key_file();
+    if priv_validator_key_file.exists() {
+        println!("Found validator key: {:?}", priv_validator_key_file);
+    } else {
+        let key = PrivValidatorKey::generate();
+        key.save(&priv_validator_key_file)?;
+        println!("Generated validator key {} in {:?}", key.address, priv_validator_key_file);
+    }
+
+    let genesis_file = config.genesis_file();
+    if genesis_file.exists() {
+        println!("Found genesis file: {:?}", genesis_file);
+    } else {
+        let mut genesis = match sub_matches.value_of("genesis") {
+            Some(path) => Genesis::load(path)?,
+            None => Genesis::new("test-chain"),
+        };
+        if !config.chain_id.is_empty() {
+            genesis.chain_id = config.chain_id.clone();
+        }
+        genesis.save(&genesis_file)?;
+        println!("Generated genesis file: {:?}", genesis_file);
+    }
+
+    Ok(())
+}
+
+fn hbbft_keys(sub_matches: &ArgMatches<'_>) -> Result<()> {
+    let mut configs = vec![];
+    let mut node_ids = vec![];
+    for home in sub_matches.values_of("homes").unwrap() {
+        let config = NodeConfig::load(home)?;
+        node_ids.push(NodeKey::load(config.node_key_file())?.id());
+        configs.push(config);
+    }
+
+    let (validators, mut shares) = deal_hbbft_keys(&node_ids);
+    if validators.validators.len() != node_ids.len() {
+        eyre::bail!("the homes share node keys, every validator needs its own");
+    }
+
+    for (config, node_id) in configs.iter().zip(&node_ids) {
+        shares.remove(node_id).unwrap().save(config.hbbft_key_file())?;
+
+        let mut genesis = Genesis::load(config.genesis_file())?;
+        genesis.hbbft = Some(validators.clone());
+        genesis.save(config.genesis_file())?;
+        println!("Dealt hbbft key share to node {} in {:?}", node_id, config.home);
+    }
+    println!("{} validators, tolerating {} faulty", validators.validators.len(), validators.max_faulty());
+
+    Ok(())
+}
+
+fn poa_authorities(sub_matches: &ArgMatches<'_>) -> Result<()> {
+    let slot_ms = match sub_matches.value_of("slot") {
+        Some(slot) => slot.parse().wrap_err("invalid --slot")?,
+        None => SLOT_MS,
+    };
+
+    let mut configs = vec![];
+    let mut authorities: Vec<PoaAuthority> = vec![];
+    for home in sub_matches.values_of("homes").unwrap() {
+        let config = NodeConfig::load(home)?;
+        let node_key = NodeKey::load(config.node_key_file())?;
+        if authorities.iter().any(|authority| authority.address == node_key.id()) {
+            eyre::bail!("the homes share node keys, every authority needs its own");
+        }
+        authorities.push(PoaAuthority {
+            address: node_key.id(),
+            pub_key: node_key.public_key().to_vec(),
+            name: config.moniker.clone(),
+        });
+        configs.push(config);
+    }
+
+    let authorities = PoaAuthorities { slot_ms, authorities };
+    for config in &configs {
+        let mut genesis = Genesis::load(config.genesis_file())?;
+        genesis.poa = Some(authorities.clone());
+        genesis.save(config.genesis_file())?;
+        println!("Wrote {} poa authorities to {:?}", authorities.authorities.len(), config.genesis_file());
+    }
+
+    Ok(())
+}
+
+fn validators(sub_matches: &ArgMatches<'_>) -> Result<()> {
+    let power: i64 = match sub_matches.value_of("power") {
+        Some(power) => power.parse().wrap_err("invalid --power")?,
+        None => 10,
+    };
+
+    let mut configs = vec![];
+    let mut validators: Vec<GenesisValidator> = vec![];
+    for home in sub_matches.values_of("homes").unwrap() {
+        let config = NodeConfig::load(home)?;
+        let key = PrivValidatorKey::load(config.priv_validator_key_file())?;
+        if validators.iter().any(|validator| validator.address == key.address) {
+            eyre::bail!("the homes share validator keys, every validator needs its own");
+        }
+        validators.push(GenesisValidator {
+            address: key.address.clone(),
+            pub_key: key.pub_key.clone(),
+            power,
+            name: config.moniker.clone(),
+        });
+        configs.push(config);
+    }
+
+    for config in &configs {
+        let mut genesis = Genesis::load(config.genesis_file())?;
+        genesis.validators = validators.clone();
+        genesis.save(config.genesis_file())?;
+        println!("Wrote {} validators to {:?}", validators.len(), config.genesis_file());
+    }
+
+    Ok(())
+}
+
+async fn run(sub_matches: &ArgMatches<'_>) -> Result<()> {
+    let mut config = NodeConfig::load(home_dir(sub_matches))?;
+    apply_settings(&mut config, sub_matches)?;
 
-// async fn run(sub_matches: &ArgMatches<'_>) -> Result<()> {
-async fn run() -> Result<()> {
+    let node_key = NodeKey::load(config.node_key_file())?;
+    println!("Starting node {:?} with id {}", config.moniker, node_key.id());
 
     let (tx_req, mut rx_req) = channel(CHANNEL_CAPACITY);
 
     // 用于和共识的ABCI接口进行通信的mpsc channel
     let (tx_abci_req, mut rx_abci_queries) = channel(CHANNEL_CAPACITY);
 
-    let (deliver_tx, mut deliver_rx) = unbounded_channel();
+    // consensus-layer block store
+    fs::create_dir_all(config.data_dir())?;
+    let store = Arc::new(SledBlockStore::new(config.blockstore_dir())?);
 
-    //TODO: Add genesis account
-    // let _genesis_account = matches.value_of("genesis_account").unwrap();
-    // expose the client port 26657
-    tokio::spawn(async move {
-        // let address = "127.0.0.1:26657".to_string();
-        // let addr = address.parse::<SocketAddr>().unwrap();
-        let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-        let port = 26657;
-        let abci_client_address = SocketAddr::new(ip, port);
-        let client_api = ClientApi::new(abci_client_address, tx_abci_req, deliver_rx);
-        println!("Startd ABCI client listen on: {:?}", &abci_client_address);
-        warp::serve(client_api.get_routes(tx_req)).run(abci_client_address).await
-    });
+    // genesis, sent to the app through InitChain on the first start
+    let mut genesis = Genesis::load(config.genesis_file())?;
+    if !config.chain_id.is_empty() {
+        genesis.chain_id = config.chain_id.clone();
+    }
 
+    // peers on the same chain, commonly on 26656
+    let p2p = p2p::start(
+        node_key.clone(),
+        genesis.chain_id.clone(),
+        genesis.hash(),
+        config.p2p.laddr,
+        config.persistent_peers()?,
+    )
+    .await?;
 
-    // client will connect the server with 26658
-    let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, 1));
-    let port = 26658;
-    let app_address = SocketAddr::new(ip, port);
+    // what the RPC serves from the node itself
+    let rpc_node = RpcNode {
+        store: store.clone(),
+        genesis: genesis.clone(),
+        node_info: NodeInfo {
+            id: node_key.id(),
+            moniker: config.moniker.clone(),
+            p2p_laddr: config.p2p.laddr,
+            rpc_laddr: config.rpc.laddr,
+        },
+        validator: PrivValidatorKey::load(config.priv_validator_key_file()).ok(),
+        p2p: Some(p2p.0.clone()),
+    };
 
-    // let init_app_hash = vec![0];
+    // client will connect the app server, commonly on 26658
+    let consensus = consensus_engine(&config, &genesis, &node_key)?;
+    let mut engine = Engine::new(&config, rx_abci_queries, store, genesis, consensus);
 
-    let mut engine = Engine::new(app_address, rx_abci_queries);
+    // expose the client port, commonly 26657
+    let abci_client_address = config.rpc.laddr;
+    let status = engine.subscribe_status();
+    let commit_timeout = config.timeout_broadcast_tx_commit();
+    let events = engine.events();
+    tokio::spawn(async move {
+        let client_api = ClientApi::new(abci_client_address, tx_abci_req, status, rpc_node, commit_timeout, events);
+        println!("Startd ABCI client listen on: {:?}", &abci_client_address);
+        warp::serve(client_api.get_routes(tx_req)).run(abci_client_address).await
+    });
 
-    // engine.run(rx_req).await?;
-    engine.run(rx_req, deliver_tx).await?;
+    engine.run(rx_req, Some(p2p)).await?;
 
     Ok(())
 
 }
-
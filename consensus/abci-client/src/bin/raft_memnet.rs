@@ -0,0 +1,99 @@
+use clap::{App, Arg};
+use eyre::Result;
+
+use pow_abci::raft::{MemRaftStorage, RaftEngine, RaftTimeouts};
+use pow_abci::{ChainState, MemNetwork};
+
+// most ticks to wait for something to happen
+const MAX_TICKS: usize = 1_000;
+
+// Runs N raft nodes in one process over the in-memory network: the leader commits a
+// few blocks, is taken down, a new leader takes over, and the old one is restarted on
+// its log and catches up. Checks that all nodes end up with the very same blocks.
+fn main() -> Result<()> {
+    let matches = App::new("raft_memnet")
+        .about("run N raft nodes in process, crash the leader and check they agree on the same blocks")
+        .arg(Arg::with_name("nodes").long("nodes").takes_value(true).default_value("3"))
+        .arg(Arg::with_name("blocks").long("blocks").takes_value(true).default_value("3"))
+        .get_matches();
+
+    let nodes: usize = matches.value_of("nodes").unwrap().parse()?;
+    let blocks: i64 = matches.value_of("blocks").unwrap().parse()?;
+
+    let ids: Vec<String> = (0..nodes).map(|i| format!("node-{}", i)).collect();
+    let storages: Vec<MemRaftStorage> = ids.iter().map(|_| MemRaftStorage::new()).collect();
+    let state = ChainState {
+        chain_id: "raft-memnet".to_string(),
+        initial_height: 1,
+        ..Default::default()
+    };
+    let engine = |i: usize| -> Result<Box<RaftEngine>> {
+        Ok(Box::new(RaftEngine::new(ids[i].clone(), ids.clone(), RaftTimeouts::default(), Box::new(storages[i].clone()))?))
+    };
+
+    let mut network = MemNetwork::new();
+    for (i, id) in ids.iter().enumerate() {
+        network.add_node(id.clone(), engine(i)?, state.clone())?;
+    }
+
+    let mut height = 0;
+    let leader = commit_blocks(&mut network, &ids, &mut height, blocks)?;
+    println!("{} committed blocks 1..={}, taking it down", leader, height);
+
+    network.set_down(&leader, true);
+    let live: Vec<String> = ids.iter().filter(|id| **id != leader).cloned().collect();
+    let new_leader = commit_blocks(&mut network, &live, &mut height, blocks)?;
+    println!("{} took over and committed up to block {}", new_leader, height);
+
+    let index = ids.iter().position(|id| *id == leader).unwrap();
+    network.set_down(&leader, false);
+    network.restart(&leader, engine(index)?)?;
+    wait_for(&mut network, |network| network.height(&leader) >= height)?;
+    println!("{} restarted and caught up to block {}", leader, network.height(&leader));
+
+    for h in 1..=height {
+        let expected = network.block(&ids[0], h)?.map(|block| block.get_hash());
+        for id in &ids {
+            if network.block(id, h)?.map(|block| block.get_hash()) != expected || expected.is_none() {
+                eyre::bail!("node {} did not commit the same block {} as {}", id, h, ids[0]);
+            }
+        }
+    }
+    println!("all {} nodes committed the same {} blocks", nodes, height);
+
+    Ok(())
+}
+
+// Waits for a leader among `ids`, has it commit `blocks` blocks of txs on all of them
+// and returns it.
+fn commit_blocks(network: &mut MemNetwork, ids: &[String], height: &mut i64, blocks: i64) -> Result<String> {
+    let mut leader = None;
+    for _ in 0..blocks {
+        let tx = format!("tx-{}", *height + 1).into_bytes();
+        let target = *height + 1;
+        wait_for(network, |network| {
+            // only the leader may propose
+            for id in ids {
+                if network.propose(id, vec![tx.clone()], target * 1_000_000_000).unwrap_or(false) {
+                    leader = Some(id.clone());
+                    return true;
+                }
+            }
+            false
+        })?;
+        wait_for(network, |network| ids.iter().all(|id| network.height(id) >= target))?;
+        *height = ids.iter().map(|id| network.height(id)).min().unwrap_or(0);
+    }
+    leader.ok_or_else(|| eyre::eyre!("no leader was elected"))
+}
+
+fn wait_for(network: &mut MemNetwork, mut done: impl FnMut(&mut MemNetwork) -> bool) -> Result<()> {
+    for _ in 0..MAX_TICKS {
+        network.run()?;
+        if done(network) {
+            return Ok(());
+        }
+        network.tick()?;
+    }
+    eyre::bail!("nothing happened in {} ticks", MAX_TICKS)
+}
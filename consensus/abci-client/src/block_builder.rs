@@ -0,0 +1,166 @@
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::mempool::{Mempool, MempoolTx};
+
+/// Default max block size, the same as CometBFT's `block.max_bytes` (21MB).
+pub const MAX_BLOCK_BYTES: i64 = 22_020_096;
+/// Default max block gas, `-1` means no limit.
+pub const MAX_BLOCK_GAS: i64 = -1;
+/// Default longest time a pending transaction waits before a block is built.
+pub const MAX_BLOCK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Limits a single block has to respect.
+#[derive(Debug, Clone)]
+pub struct BlockLimits {
+    /// Max total size of the txs in a block, in bytes.
+    pub max_bytes: i64,
+    /// Max sum of the `gas_wanted` reported by `CheckTx`, `-1` for unlimited.
+    pub max_gas: i64,
+    /// Max time between the first pending tx arriving and the block being built.
+    pub max_interval: Duration,
+    /// Whether blocks are built with no txs, `max_interval` after the last block unless
+    /// `create_empty_blocks_interval` is set.
+    pub create_empty_blocks: bool,
+    /// Time without a block after which an empty block is built, even with
+    /// `create_empty_blocks` off. Zero to leave it to `create_empty_blocks`.
+    pub create_empty_blocks_interval: Duration,
+}
+
+impl BlockLimits {
+    /// Whether a tx of `bytes` fits in a block at all, a negative `max_bytes` means unlimited.
+    pub fn fits_tx(&self, bytes: usize) -> bool {
+        self.max_bytes < 0 || bytes as i64 <= self.max_bytes
+    }
+
+    /// Whether txs of `bytes` and `gas` in total fill a whole block, negative limits
+    /// are never reached.
+    pub fn filled_by(&self, bytes: i64, gas: i64) -> bool {
+        let bytes_full = self.max_bytes >= 0 && bytes >= self.max_bytes;
+        let gas_full = self.max_gas >= 0 && gas >= self.max_gas;
+        bytes_full || gas_full
+    }
+}
+
+impl Default for BlockLimits {
+    fn default() -> Self {
+        Self {
+            max_bytes: MAX_BLOCK_BYTES,
+            max_gas: MAX_BLOCK_GAS,
+            max_interval: MAX_BLOCK_INTERVAL,
+            create_empty_blocks: false,
+            create_empty_blocks_interval: Duration::ZERO,
+        }
+    }
+}
+
+/// Decides when the pending transactions should be cut into a block and which ones go in.
+///
+/// A block is built as soon as the mempool holds enough txs to fill it, or when the
+/// oldest pending tx has waited for `max_interval`, whichever comes first. With empty
+/// blocks on, a block is also built once the chain went the empty block interval without
+/// one, txs or not, so the height and the block time keep moving.
+pub struct BlockBuilder {
+    limits: BlockLimits,
+    // when the chain last moved, the empty block interval counts from it
+    last_block_at: Instant,
+}
+
+impl BlockBuilder {
+    pub fn new(limits: BlockLimits) -> Self {
+        Self {
+            limits,
+            last_block_at: Instant::now(),
+        }
+    }
+
+    pub fn limits(&self) -> &BlockLimits {
+        &self.limits
+    }
+
+    /// Applies the block size and gas limits from the chain's consensus params.
+    pub fn set_block_params(&mut self, max_bytes: i64, max_gas: i64) {
+        self.limits.max_bytes = max_bytes;
+        self.limits.max_gas = max_gas;
+    }
+
+    /// Whether the pending txs already fill a whole block.
+    pub fn is_full(&self, mempool: &Mempool) -> bool {
+        self.limits.filled_by(mempool.size_bytes(), mempool.gas_wanted())
+    }
+
+    /// Restarts the wait for an empty block, a block just joined the chain.
+    pub fn block_added(&mut self) {
+        self.last_block_at = Instant::now();
+    }
+
+    /// The instant the next block has to be built at, if anything is pending or an
+    /// empty block is due at some point.
+    pub fn deadline(&self, mempool: &Mempool) -> Option<Instant> {
+        let pending = mempool
+            .oldest_received_at()
+            .map(|received_at| received_at + self.limits.max_interval);
+        let empty = self.empty_block_interval().map(|interval| self.last_block_at + interval);
+        match (pending, empty) {
+            (Some(pending), Some(empty)) => Some(pending.min(empty)),
+            (pending, empty) => pending.or(empty),
+        }
+    }
+
+    /// Whether the chain went long enough without a block to build one with no txs.
+    pub fn empty_block_due(&self) -> bool {
+        self.empty_block_interval()
+            .map_or(false, |interval| Instant::now() >= self.last_block_at + interval)
+    }
+
+    fn empty_block_interval(&self) -> Option<Duration> {
+        if !self.limits.create_empty_blocks_interval.is_zero() {
+            Some(self.limits.create_empty_blocks_interval)
+        } else if self.limits.create_empty_blocks {
+            Some(self.limits.max_interval)
+        } else {
+            None
+        }
+    }
+
+    /// Drains the next block's worth of transactions from the mempool.
+    pub fn build(&self, mempool: &mut Mempool) -> Vec<MempoolTx> {
+        mempool.reap_max_bytes_max_gas(self.limits.max_bytes, self.limits.max_gas)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limits(max_bytes: i64, max_gas: i64) -> BlockLimits {
+        BlockLimits { max_bytes, max_gas, ..Default::default() }
+    }
+
+    #[test]
+    fn a_tx_fits_up_to_max_bytes() {
+        assert!(limits(100, -1).fits_tx(100));
+        assert!(!limits(100, -1).fits_tx(101));
+    }
+
+    #[test]
+    fn negative_max_bytes_fits_any_tx() {
+        assert!(limits(-1, -1).fits_tx(0));
+        assert!(limits(-1, -1).fits_tx(MAX_BLOCK_BYTES as usize * 2));
+    }
+
+    #[test]
+    fn a_block_is_full_at_either_limit() {
+        assert!(!limits(100, 10).filled_by(99, 9));
+        assert!(limits(100, 10).filled_by(100, 0));
+        assert!(limits(100, 10).filled_by(0, 10));
+    }
+
+    #[test]
+    fn negative_limits_never_fill_a_block() {
+        assert!(!limits(-1, -1).filled_by(0, 0));
+        assert!(!limits(-1, -1).filled_by(i64::MAX, i64::MAX));
+        assert!(limits(-1, 10).filled_by(0, 10));
+    }
+}
@@ -0,0 +1,181 @@
+use serde::{Serialize, Deserialize};
+
+use crate::pow::ProofOfWork;
+use crate::utils::{ecdsa_p256_sha256_sign_verify, merkle_root, serialize};
+use crate::{Commit, NodeKey};
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct BlockHeader {
+    pub height: i64,
+    pub prev_hash: Vec<u8>,
+    // merkle root of the raw txs
+    pub txs_hash: Vec<u8>,
+    // app hash returned by Commit for the previous block, or for an older one with
+    // engines that commit blocks some time after they are made
+    pub app_hash: Vec<u8>,
+    // unix time in nanoseconds
+    pub timestamp: i64,
+    pub bits: usize,
+    pub nonce: usize,
+    // sum of the gas_wanted CheckTx reported for the txs when the block was built
+    pub gas_wanted: i64,
+    // address of the node key that signed the block, empty for engines without signatures
+    pub proposer: Vec<u8>,
+    // hash of the previous block's commit, empty for engines without one
+    pub last_commit_hash: Vec<u8>,
+}
+
+impl BlockHeader {
+    /// Everything in the header but the nonce, which is what the PoW commits to.
+    pub fn pow_data(&self) -> Vec<u8> {
+        serialize(&(
+            self.height,
+            &self.prev_hash,
+            &self.txs_hash,
+            &self.app_hash,
+            self.timestamp,
+            self.bits,
+            self.gas_wanted,
+            &self.proposer,
+            &self.last_commit_hash,
+        ))
+        .unwrap()
+    }
+
+    /// PoW hash of the header with its nonce, which is also the block hash.
+    pub fn hash(&self) -> Vec<u8> {
+        ProofOfWork::hash(&self.pow_data(), self.nonce).to_vec()
+    }
+
+    /// Whether the header hash meets the difficulty the header claims.
+    pub fn meets_difficulty(&self) -> bool {
+        ProofOfWork::new(self.bits).validate(&self.pow_data(), self.nonce)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct Block {
+    header: BlockHeader,
+    txs: Vec<Vec<u8>>,
+    hash: Vec<u8>,
+    // proposer's signature of the block hash
+    signature: Vec<u8>,
+    // votes that committed the previous block, for BFT engines
+    last_commit: Option<Commit>,
+}
+
+impl Block {
+    pub fn new(
+        height: i64,
+        prev_hash: Vec<u8>,
+        app_hash: Vec<u8>,
+        timestamp: i64,
+        bits: usize,
+        txs: Vec<Vec<u8>>,
+    ) -> Self {
+        let header = BlockHeader {
+            height,
+            prev_hash,
+            txs_hash: merkle_root(&txs),
+            app_hash,
+            timestamp,
+            bits,
+            nonce: 0,
+            gas_wanted: 0,
+            proposer: vec![],
+            last_commit_hash: vec![],
+        };
+        let hash = header.hash();
+
+        Self { header, txs, hash, signature: vec![], last_commit: None }
+    }
+
+    pub fn get_header(&self) -> &BlockHeader {
+        &self.header
+    }
+
+    pub fn get_height(&self) -> i64 {
+        self.header.height
+    }
+
+    pub fn get_hash(&self) -> Vec<u8> {
+        self.hash.clone()
+    }
+
+    pub fn get_txs(&self) -> &[Vec<u8>] {
+        &self.txs
+    }
+
+    pub fn get_signature(&self) -> &[u8] {
+        &self.signature
+    }
+
+    pub fn get_last_commit(&self) -> Option<&Commit> {
+        self.last_commit.as_ref()
+    }
+
+    /// Attaches the commit of the previous block and re-hashes the header.
+    pub fn set_last_commit(&mut self, commit: Commit) {
+        self.header.last_commit_hash = commit.hash();
+        self.last_commit = Some(commit);
+        self.hash = self.header.hash();
+    }
+
+    /// Records the proposer's address for engines that sign elsewhere, and re-hashes the header.
+    pub fn set_proposer(&mut self, proposer: Vec<u8>) {
+        self.header.proposer = proposer;
+        self.hash = self.header.hash();
+    }
+
+    /// Moves the block on top of another parent and re-hashes the header.
+    pub fn set_parent(&mut self, height: i64, prev_hash: Vec<u8>, app_hash: Vec<u8>) {
+        self.header.height = height;
+        self.header.prev_hash = prev_hash;
+        self.header.app_hash = app_hash;
+        self.hash = self.header.hash();
+    }
+
+    /// Moves the block to another time and re-hashes the header.
+    pub fn set_timestamp(&mut self, timestamp: i64) {
+        self.header.timestamp = timestamp;
+        self.hash = self.header.hash();
+    }
+
+    /// Records the node as the proposer and signs the block hash with its key.
+    pub fn sign(&mut self, node_key: &NodeKey) {
+        self.header.proposer = node_key.address();
+        self.hash = self.header.hash();
+        self.signature = node_key.sign(&self.hash);
+    }
+
+    /// Whether the hash of the header was signed by the owner of `public_key`. The hash
+    /// is recomputed rather than taken from the block, which comes from the peer.
+    pub fn verify_signature(&self, public_key: &[u8]) -> bool {
+        ecdsa_p256_sha256_sign_verify(public_key, &self.signature, &self.header.hash())
+    }
+
+    /// Records the gas the txs want and re-hashes the header.
+    pub fn set_gas_wanted(&mut self, gas_wanted: i64) {
+        self.header.gas_wanted = gas_wanted;
+        self.hash = self.header.hash();
+    }
+
+    /// Sets the difficulty the block is mined at and re-hashes the header.
+    pub fn set_bits(&mut self, bits: usize) {
+        self.header.bits = bits;
+        self.hash = self.header.hash();
+    }
+
+    /// Records the nonce found by the PoW and re-hashes the header.
+    pub fn set_nonce(&mut self, nonce: usize) {
+        self.header.nonce = nonce;
+        self.hash = self.header.hash();
+    }
+
+    /// Unix time of the block split into seconds and nanoseconds.
+    pub fn get_time(&self) -> (i64, i32) {
+        let seconds = self.header.timestamp.div_euclid(1_000_000_000);
+        let nanos = self.header.timestamp.rem_euclid(1_000_000_000) as i32;
+        (seconds, nanos)
+    }
+}
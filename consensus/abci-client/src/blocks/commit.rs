@@ -0,0 +1,59 @@
+use serde::{Serialize, Deserialize};
+use tendermint_proto::abci::{LastCommitInfo, Validator, VoteInfo};
+
+use crate::utils::{serialize, sha256_digest};
+
+/// Precommits of the validators for a block, the proof a BFT engine committed it.
+/// It goes into the next block, which hands it to the app as `LastCommitInfo`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct Commit {
+    pub height: i64,
+    pub round: i32,
+    pub block_hash: Vec<u8>,
+    /// One entry per validator of the height, in validator order.
+    pub signatures: Vec<CommitSig>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct CommitSig {
+    pub validator_address: Vec<u8>,
+    pub power: i64,
+    // time of the precommit, unix nanoseconds
+    pub timestamp: i64,
+    // signature of the precommit, empty if the validator's precommit did not make it
+    pub signature: Vec<u8>,
+}
+
+impl Commit {
+    pub fn hash(&self) -> Vec<u8> {
+        sha256_digest(&serialize(self).unwrap())
+    }
+
+    /// Voting power of the validators whose precommit is in the commit.
+    pub fn signed_power(&self) -> i64 {
+        self.signatures.iter().filter(|sig| !sig.signature.is_empty()).map(|sig| sig.power).sum()
+    }
+
+    pub fn total_power(&self) -> i64 {
+        self.signatures.iter().map(|sig| sig.power).sum()
+    }
+}
+
+impl From<&Commit> for LastCommitInfo {
+    fn from(commit: &Commit) -> Self {
+        Self {
+            round: commit.round,
+            votes: commit
+                .signatures
+                .iter()
+                .map(|sig| VoteInfo {
+                    validator: Some(Validator {
+                        address: sig.validator_address.clone().into(),
+                        power: sig.power,
+                    }),
+                    signed_last_block: !sig.signature.is_empty(),
+                })
+                .collect(),
+        }
+    }
+}
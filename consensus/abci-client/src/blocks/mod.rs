@@ -0,0 +1,9 @@
+mod block;
+mod commit;
+mod results;
+mod validation;
+
+pub use block::*;
+pub use commit::*;
+pub use results::*;
+pub use validation::*;
@@ -0,0 +1,183 @@
+use serde::{Serialize, Deserialize};
+use tendermint_proto::abci::{
+    BlockParams, ConsensusParams, Event, EventAttribute, ResponseDeliverTx, ResponseEndBlock,
+    ValidatorUpdate,
+};
+use tendermint_proto::crypto::{public_key::Sum, PublicKey};
+
+/// Storable copy of an ABCI event.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct EventRecord {
+    pub r#type: String,
+    pub attributes: Vec<EventAttributeRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct EventAttributeRecord {
+    pub key: Vec<u8>,
+    pub value: Vec<u8>,
+    pub index: bool,
+}
+
+/// Storable copy of a `ResponseDeliverTx`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct TxResult {
+    pub code: u32,
+    pub data: Vec<u8>,
+    pub log: String,
+    pub info: String,
+    pub gas_wanted: i64,
+    pub gas_used: i64,
+    pub events: Vec<EventRecord>,
+    pub codespace: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct ValidatorUpdateRecord {
+    // "ed25519" or "secp256k1"
+    pub pub_key_type: String,
+    pub pub_key: Vec<u8>,
+    pub power: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct BlockParamsRecord {
+    pub max_bytes: i64,
+    pub max_gas: i64,
+}
+
+/// Storable copy of a `ResponseEndBlock`.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct EndBlockResult {
+    pub validator_updates: Vec<ValidatorUpdateRecord>,
+    pub block_params_update: Option<BlockParamsRecord>,
+    pub events: Vec<EventRecord>,
+}
+
+/// Everything the app returned while executing one block.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct BlockResults {
+    pub height: i64,
+    pub begin_block_events: Vec<EventRecord>,
+    pub deliver_txs: Vec<TxResult>,
+    pub end_block: EndBlockResult,
+    // app hash returned by Commit
+    pub app_hash: Vec<u8>,
+}
+
+impl From<&Event> for EventRecord {
+    fn from(event: &Event) -> Self {
+        Self {
+            r#type: event.r#type.clone(),
+            attributes: event.attributes.iter().map(|attr| EventAttributeRecord {
+                key: attr.key.to_vec(),
+                value: attr.value.to_vec(),
+                index: attr.index,
+            }).collect(),
+        }
+    }
+}
+
+impl From<&EventRecord> for Event {
+    fn from(event: &EventRecord) -> Self {
+        Self {
+            r#type: event.r#type.clone(),
+            attributes: event.attributes.iter().map(|attr| EventAttribute {
+                key: attr.key.clone().into(),
+                value: attr.value.clone().into(),
+                index: attr.index,
+            }).collect(),
+        }
+    }
+}
+
+impl From<&ResponseDeliverTx> for TxResult {
+    fn from(resp: &ResponseDeliverTx) -> Self {
+        Self {
+            code: resp.code,
+            data: resp.data.to_vec(),
+            log: resp.log.clone(),
+            info: resp.info.clone(),
+            gas_wanted: resp.gas_wanted,
+            gas_used: resp.gas_used,
+            events: resp.events.iter().map(EventRecord::from).collect(),
+            codespace: resp.codespace.clone(),
+        }
+    }
+}
+
+impl From<&TxResult> for ResponseDeliverTx {
+    fn from(result: &TxResult) -> Self {
+        Self {
+            code: result.code,
+            data: result.data.clone().into(),
+            log: result.log.clone(),
+            info: result.info.clone(),
+            gas_wanted: result.gas_wanted,
+            gas_used: result.gas_used,
+            events: result.events.iter().map(Event::from).collect(),
+            codespace: result.codespace.clone(),
+        }
+    }
+}
+
+impl From<&ValidatorUpdate> for ValidatorUpdateRecord {
+    fn from(update: &ValidatorUpdate) -> Self {
+        let (pub_key_type, pub_key) = match update.pub_key.as_ref().and_then(|key| key.sum.as_ref()) {
+            Some(Sum::Ed25519(key)) => ("ed25519", key.clone()),
+            Some(Sum::Secp256k1(key)) => ("secp256k1", key.clone()),
+            None => ("", vec![]),
+        };
+        Self {
+            pub_key_type: pub_key_type.to_string(),
+            pub_key,
+            power: update.power,
+        }
+    }
+}
+
+impl From<&ValidatorUpdateRecord> for ValidatorUpdate {
+    fn from(record: &ValidatorUpdateRecord) -> Self {
+        let sum = match record.pub_key_type.as_str() {
+            "secp256k1" => Sum::Secp256k1(record.pub_key.clone()),
+            _ => Sum::Ed25519(record.pub_key.clone()),
+        };
+        Self {
+            pub_key: Some(PublicKey { sum: Some(sum) }),
+            power: record.power,
+        }
+    }
+}
+
+impl From<&ResponseEndBlock> for EndBlockResult {
+    fn from(resp: &ResponseEndBlock) -> Self {
+        Self {
+            validator_updates: resp.validator_updates.iter().map(ValidatorUpdateRecord::from).collect(),
+            block_params_update: resp
+                .consensus_param_updates
+                .as_ref()
+                .and_then(|params| params.block.as_ref())
+                .map(|block| BlockParamsRecord {
+                    max_bytes: block.max_bytes,
+                    max_gas: block.max_gas,
+                }),
+            events: resp.events.iter().map(EventRecord::from).collect(),
+        }
+    }
+}
+
+impl From<&EndBlockResult> for ResponseEndBlock {
+    fn from(result: &EndBlockResult) -> Self {
+        Self {
+            validator_updates: result.validator_updates.iter().map(ValidatorUpdate::from).collect(),
+            consensus_param_updates: result.block_params_update.as_ref().map(|block| ConsensusParams {
+                block: Some(BlockParams {
+                    max_bytes: block.max_bytes,
+                    max_gas: block.max_gas,
+                }),
+                ..Default::default()
+            }),
+            events: result.events.iter().map(Event::from).collect(),
+        }
+    }
+}
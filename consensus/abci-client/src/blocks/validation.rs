@@ -0,0 +1,184 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::error::{BlockError, BlockchainError};
+use crate::utils::merkle_root;
+use crate::{Block, BlockHeader, BlockParamsRecord, BlockStore};
+
+/// Number of previous blocks whose median time a block must come after.
+pub const MEDIAN_TIME_SPAN: usize = 11;
+/// How far ahead of the local clock a block time may be, in nanoseconds.
+pub const MAX_FUTURE_DRIFT: i64 = 15_000_000_000;
+
+/// Checks that the hash and txs of a block are the ones its header commits to, and
+/// that the txs fit in a block under the chain's consensus params.
+///
+/// The hash comes from the peer along with the header, and is what proposers sign and
+/// votes point at, so it has to be recomputed before anything trusts it.
+pub fn validate_block_body(block: &Block, params: &BlockParamsRecord) -> Result<(), BlockError> {
+    let header = block.get_header();
+    if block.get_hash() != header.hash() {
+        return Err(BlockError::HashMismatch { height: header.height });
+    }
+    if merkle_root(block.get_txs()) != header.txs_hash {
+        return Err(BlockError::TxsHashMismatch { height: header.height });
+    }
+    let bytes: i64 = block.get_txs().iter().map(|tx| tx.len() as i64).sum();
+    if params.max_bytes >= 0 && bytes > params.max_bytes {
+        return Err(BlockError::TooLarge { height: header.height, bytes, max_bytes: params.max_bytes });
+    }
+    // Only the app knows what a tx wants, so `gas_wanted` is what the proposer declares
+    // and this check is advisory: it rejects blocks that admit to going over the limit,
+    // not ones that under-declare. The limit is enforced on the txs a node picks for its
+    // own blocks.
+    if params.max_gas >= 0 && header.gas_wanted > params.max_gas {
+        return Err(BlockError::GasExceeded { height: header.height, gas_wanted: header.gas_wanted, max_gas: params.max_gas });
+    }
+    Ok(())
+}
+
+/// Median time of the last `MEDIAN_TIME_SPAN` of `recent`, the headers of the blocks
+/// before a block in height order. None before the first block.
+pub fn median_time_past(recent: &[BlockHeader]) -> Option<i64> {
+    let recent = &recent[recent.len().saturating_sub(MEDIAN_TIME_SPAN)..];
+    let mut timestamps: Vec<i64> = recent.iter().map(|header| header.timestamp).collect();
+    timestamps.sort_unstable();
+    timestamps.get(timestamps.len() / 2).copied()
+}
+
+/// Headers of the committed blocks before the one at `height`, at most `count` of them,
+/// in height order.
+pub fn recent_headers(store: &dyn BlockStore, height: i64, count: usize) -> Result<Vec<BlockHeader>, BlockchainError> {
+    let mut headers = vec![];
+    for h in (height - count as i64).max(1)..height {
+        if let Some(block) = store.load_block(h)? {
+            headers.push(block.get_header().clone());
+        }
+    }
+    Ok(headers)
+}
+
+/// The time a proposer gives its block: its own clock, moved past the median time of
+/// the blocks before it if the clock is behind the chain's.
+pub fn next_timestamp(now: i64, recent: &[BlockHeader]) -> i64 {
+    match median_time_past(recent) {
+        Some(median) if now <= median => median + 1,
+        _ => now,
+    }
+}
+
+/// Checks that a block time comes after the median time of the blocks before it, and
+/// is not too far ahead of the local clock.
+pub fn validate_timestamp(header: &BlockHeader, recent: &[BlockHeader]) -> Result<(), BlockError> {
+    if let Some(median) = median_time_past(recent) {
+        if header.timestamp <= median {
+            return Err(BlockError::TimestampBeforeMedian { height: header.height, timestamp: header.timestamp, median });
+        }
+    }
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_nanos() as i64;
+    if header.timestamp > now + MAX_FUTURE_DRIFT {
+        return Err(BlockError::TimestampTooFarAhead { height: header.height, timestamp: header.timestamp });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unlimited() -> BlockParamsRecord {
+        BlockParamsRecord { max_bytes: -1, max_gas: -1 }
+    }
+
+    // the block with one of its fields replaced, as a peer could send it
+    fn tampered(block: &Block, field: &str, value: serde_json::Value) -> Block {
+        let mut json = serde_json::to_value(block).unwrap();
+        json[field] = value;
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn accepts_a_block_matching_its_header() {
+        let block = Block::new(1, vec![], vec![], 1, 0, vec![b"tx".to_vec()]);
+        assert!(validate_block_body(&block, &unlimited()).is_ok());
+    }
+
+    #[test]
+    fn rejects_a_hash_copied_from_another_block() {
+        let signed = Block::new(1, vec![], vec![], 1, 0, vec![b"tx".to_vec()]);
+        let forged = Block::new(1, vec![], vec![], 1, 0, vec![b"other tx".to_vec()]);
+        let forged = tampered(&forged, "hash", serde_json::json!(signed.get_hash()));
+        assert!(matches!(validate_block_body(&forged, &unlimited()), Err(BlockError::HashMismatch { height: 1 })));
+    }
+
+    #[test]
+    fn rejects_txs_swapped_under_the_header() {
+        let block = Block::new(1, vec![], vec![], 1, 0, vec![b"tx".to_vec()]);
+        let swapped = tampered(&block, "txs", serde_json::json!([b"other tx".to_vec()]));
+        assert!(matches!(validate_block_body(&swapped, &unlimited()), Err(BlockError::TxsHashMismatch { height: 1 })));
+    }
+
+    #[test]
+    fn rejects_blocks_over_the_size_limit() {
+        let block = Block::new(1, vec![], vec![], 1, 0, vec![vec![0; 10]]);
+        let params = BlockParamsRecord { max_bytes: 9, max_gas: -1 };
+        assert!(matches!(validate_block_body(&block, &params), Err(BlockError::TooLarge { .. })));
+    }
+
+    fn now() -> i64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_nanos() as i64
+    }
+
+    // headers of blocks 1..=n at the given times
+    fn recent(timestamps: &[i64]) -> Vec<BlockHeader> {
+        timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, timestamp)| BlockHeader { height: i as i64 + 1, timestamp: *timestamp, ..Default::default() })
+            .collect()
+    }
+
+    fn header(timestamp: i64) -> BlockHeader {
+        BlockHeader { height: 12, timestamp, ..Default::default() }
+    }
+
+    #[test]
+    fn median_time_of_the_last_blocks() {
+        assert_eq!(median_time_past(&[]), None);
+        assert_eq!(median_time_past(&recent(&[5, 1, 3])), Some(3));
+        // only the last MEDIAN_TIME_SPAN count, the first block falls out
+        let mut timestamps = vec![1_000];
+        timestamps.extend(1..=MEDIAN_TIME_SPAN as i64);
+        assert_eq!(median_time_past(&recent(&timestamps)), Some(6));
+    }
+
+    #[test]
+    fn rejects_a_time_at_the_median() {
+        let base = now() - 60_000_000_000;
+        let recent = recent(&(0..MEDIAN_TIME_SPAN as i64).map(|i| base + i).collect::<Vec<_>>());
+        let median = base + MEDIAN_TIME_SPAN as i64 / 2;
+        assert!(matches!(
+            validate_timestamp(&header(median), &recent),
+            Err(BlockError::TimestampBeforeMedian { timestamp, median: m, .. }) if timestamp == median && m == median
+        ));
+        assert!(validate_timestamp(&header(median + 1), &recent).is_ok());
+    }
+
+    #[test]
+    fn bounds_the_drift_ahead_of_our_clock() {
+        // a second of slack for the clock moving on during the test
+        assert!(validate_timestamp(&header(now() + MAX_FUTURE_DRIFT - 1_000_000_000), &[]).is_ok());
+        assert!(matches!(
+            validate_timestamp(&header(now() + MAX_FUTURE_DRIFT + 1_000_000_000), &[]),
+            Err(BlockError::TimestampTooFarAhead { height: 12, .. })
+        ));
+    }
+
+    #[test]
+    fn moves_a_clock_behind_the_chain_past_the_median() {
+        let recent = recent(&[10, 20, 30]);
+        assert_eq!(next_timestamp(25, &recent), 25);
+        assert_eq!(next_timestamp(20, &recent), 21);
+        assert_eq!(next_timestamp(5, &recent), 21);
+        assert_eq!(next_timestamp(5, &[]), 5);
+    }
+}
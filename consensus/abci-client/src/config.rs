@@ -0,0 +1,313 @@
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+
+use crate::bft::TendermintTimeouts;
+use crate::p2p::PeerAddress;
+use crate::raft::RaftTimeouts;
+use crate::GENESIS_FILE;
+
+pub const CONFIG_DIR: &str = "config";
+pub const DATA_DIR: &str = "data";
+pub const CONFIG_FILE: &str = "config.toml";
+pub const NODE_KEY_FILE: &str = "node_key.json";
+pub const HBBFT_KEY_FILE: &str = "hbbft_key.json";
+pub const PRIV_VALIDATOR_KEY_FILE: &str = "priv_validator_key.json";
+pub const BLOCKSTORE_DB: &str = "blockstore.db";
+pub const RAFT_DB: &str = "raft.db";
+pub const DEFAULT_HOME: &str = ".pow-node";
+
+/// Node settings, read from `<home>/config/config.toml`.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct NodeConfig {
+    pub moniker: String,
+    // overrides the chain id of the genesis file when not empty
+    #[serde(default)]
+    pub chain_id: String,
+    pub rpc: RpcConfig,
+    pub abci: AbciConfig,
+    pub consensus: ConsensusConfig,
+    pub mempool: MempoolConfig,
+    #[serde(default)]
+    pub p2p: P2pConfig,
+    // home directory the config was loaded from, not part of the file
+    #[serde(skip)]
+    pub home: PathBuf,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct RpcConfig {
+    /// Address the Tendermint compatible JSON-RPC server listens on.
+    pub laddr: SocketAddr,
+    /// How long `broadcast_tx_commit` waits for the tx to be in a block, in milliseconds.
+    pub timeout_broadcast_tx_commit_ms: u64,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            laddr: "127.0.0.1:26657".parse().unwrap(),
+            timeout_broadcast_tx_commit_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct AbciConfig {
+    /// Address of the ABCI app.
+    pub proxy_app: SocketAddr,
+}
+
+/// Consensus engine the node runs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EngineKind {
+    /// Proof of work with difficulty retargeting.
+    Pow,
+    /// Every block is committed as soon as it is built, for a single dev node.
+    Instant,
+    /// HoneyBadgerBFT among the validators of the genesis file.
+    Hbbft,
+    /// Authorities of the genesis file take turns signing blocks.
+    Poa,
+    /// Tendermint BFT among the validators of the chain, with instant finality.
+    Tendermint,
+    /// Raft among the members of the config, tolerating crashed nodes only.
+    Raft,
+}
+
+impl std::str::FromStr for EngineKind {
+    type Err = eyre::Report;
+
+    fn from_str(s: &str) -> eyre::Result<Self> {
+        match s {
+            "pow" => Ok(EngineKind::Pow),
+            "instant" => Ok(EngineKind::Instant),
+            "hbbft" => Ok(EngineKind::Hbbft),
+            "poa" => Ok(EngineKind::Poa),
+            "tendermint" => Ok(EngineKind::Tendermint),
+            "raft" => Ok(EngineKind::Raft),
+            _ => eyre::bail!("unknown consensus engine {:?}, expected one of: pow, instant, hbbft, poa, tendermint, raft", s),
+        }
+    }
+}
+
+// missing keys take the defaults, so config files of older versions still load
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct ConsensusConfig {
+    pub engine: EngineKind,
+    /// Longest time a pending tx waits before a block is built, in milliseconds.
+    pub block_interval_ms: u64,
+    /// Whether blocks are made when no tx is pending, so the height and block time keep
+    /// moving for time-based app logic.
+    pub create_empty_blocks: bool,
+    /// Time without a block after which an empty block is made, in milliseconds, even
+    /// with `create_empty_blocks` off. 0 makes them every `block_interval_ms` when
+    /// `create_empty_blocks` is on.
+    pub create_empty_blocks_interval_ms: u64,
+
+    // pow engine
+    /// Leading zero bits required from the first blocks, until the retarget has enough
+    /// blocks to average over. Part of the chain rules: changing it once blocks exist
+    /// makes the stored blocks fail validation.
+    pub difficulty: usize,
+    /// Block time the difficulty retarget aims for, in milliseconds.
+    pub target_block_time_ms: u64,
+    /// Number of previous blocks the retarget averages the block time over.
+    pub retarget_window: usize,
+    /// Threads searching for nonces, 0 for one per CPU.
+    pub mining_threads: usize,
+    /// Blocks on top of a block before it is final and goes to the app, 0 to commit
+    /// every block as soon as it is mined. Part of the chain rules, like `difficulty`.
+    pub confirmation_depth: u64,
+
+    // tendermint engine
+    /// How long validators wait for the proposal of a round, in milliseconds.
+    pub timeout_propose_ms: u64,
+    /// How long validators wait for prevotes to agree once +2/3 prevoted, in milliseconds.
+    pub timeout_prevote_ms: u64,
+    /// How long validators wait for precommits to agree once +2/3 precommitted, in milliseconds.
+    pub timeout_precommit_ms: u64,
+    /// How much longer every timeout gets with each new round of a height, in milliseconds.
+    pub timeout_delta_ms: u64,
+
+    // raft engine
+    /// Node ids of every member of the cluster, this node included.
+    pub raft_members: Vec<String>,
+    /// Length of a raft tick, in milliseconds.
+    pub raft_tick_ms: u64,
+    /// Ticks without a leader before a follower starts an election, randomized up to twice as many.
+    pub raft_election_ticks: u64,
+    /// Ticks between the leader's heartbeats.
+    pub raft_heartbeat_ticks: u64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct MempoolConfig {
+    pub size: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct P2pConfig {
+    /// Address the node accepts peer connections on.
+    pub laddr: SocketAddr,
+    /// Peers the node keeps a connection to, as `<node id>@<host>:<port>`.
+    pub persistent_peers: Vec<String>,
+}
+
+impl Default for P2pConfig {
+    fn default() -> Self {
+        Self {
+            laddr: "0.0.0.0:26656".parse().unwrap(),
+            persistent_peers: vec![],
+        }
+    }
+}
+
+impl Default for NodeConfig {
+    fn default() -> Self {
+        Self {
+            moniker: "pow-node".to_string(),
+            chain_id: String::new(),
+            rpc: RpcConfig::default(),
+            abci: AbciConfig {
+                proxy_app: "127.0.0.1:26658".parse().unwrap(),
+            },
+            consensus: ConsensusConfig::default(),
+            mempool: MempoolConfig {
+                size: crate::MEMPOOL_SIZE,
+            },
+            p2p: P2pConfig::default(),
+            home: PathBuf::new(),
+        }
+    }
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            engine: EngineKind::Pow,
+            difficulty: crate::DIFFICULTY,
+            target_block_time_ms: 2_000,
+            retarget_window: crate::RETARGET_WINDOW,
+            mining_threads: 0,
+            confirmation_depth: crate::CONFIRMATION_DEPTH,
+            block_interval_ms: 1_000,
+            create_empty_blocks: false,
+            create_empty_blocks_interval_ms: 0,
+            timeout_propose_ms: 3_000,
+            timeout_prevote_ms: 1_000,
+            timeout_precommit_ms: 1_000,
+            timeout_delta_ms: 500,
+            raft_members: vec![],
+            raft_tick_ms: 100,
+            raft_election_ticks: 10,
+            raft_heartbeat_ticks: 2,
+        }
+    }
+}
+
+impl NodeConfig {
+    /// Reads `<home>/config/config.toml`.
+    pub fn load(home: impl AsRef<Path>) -> eyre::Result<Self> {
+        let home = home.as_ref();
+        let path = home.join(CONFIG_DIR).join(CONFIG_FILE);
+        let content = fs::read_to_string(&path)
+            .wrap_err_with(|| format!("failed to read {:?}, run `pow-node init --home {:?}` first", path, home))?;
+        let mut config: NodeConfig = toml::from_str(&content)
+            .wrap_err_with(|| format!("failed to parse {:?}", path))?;
+        config.home = home.to_path_buf();
+        Ok(config)
+    }
+
+    pub fn save(&self) -> eyre::Result<()> {
+        fs::create_dir_all(self.config_dir())?;
+        fs::write(self.config_dir().join(CONFIG_FILE), toml::to_string_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn timeout_broadcast_tx_commit(&self) -> Duration {
+        Duration::from_millis(self.rpc.timeout_broadcast_tx_commit_ms)
+    }
+
+    pub fn block_interval(&self) -> Duration {
+        Duration::from_millis(self.consensus.block_interval_ms)
+    }
+
+    pub fn create_empty_blocks_interval(&self) -> Duration {
+        Duration::from_millis(self.consensus.create_empty_blocks_interval_ms)
+    }
+
+    pub fn target_block_time(&self) -> Duration {
+        Duration::from_millis(self.consensus.target_block_time_ms)
+    }
+
+    pub fn tendermint_timeouts(&self) -> TendermintTimeouts {
+        TendermintTimeouts {
+            propose: Duration::from_millis(self.consensus.timeout_propose_ms),
+            prevote: Duration::from_millis(self.consensus.timeout_prevote_ms),
+            precommit: Duration::from_millis(self.consensus.timeout_precommit_ms),
+            delta: Duration::from_millis(self.consensus.timeout_delta_ms),
+        }
+    }
+
+    pub fn raft_timeouts(&self) -> RaftTimeouts {
+        RaftTimeouts {
+            tick: Duration::from_millis(self.consensus.raft_tick_ms),
+            election_ticks: self.consensus.raft_election_ticks,
+            heartbeat_ticks: self.consensus.raft_heartbeat_ticks,
+        }
+    }
+
+    pub fn persistent_peers(&self) -> eyre::Result<Vec<PeerAddress>> {
+        let peers = self.p2p.persistent_peers.iter().map(|peer| peer.parse()).collect::<Result<_, _>>()?;
+        Ok(peers)
+    }
+
+    pub fn config_dir(&self) -> PathBuf {
+        self.home.join(CONFIG_DIR)
+    }
+
+    pub fn data_dir(&self) -> PathBuf {
+        self.home.join(DATA_DIR)
+    }
+
+    pub fn genesis_file(&self) -> PathBuf {
+        self.config_dir().join(GENESIS_FILE)
+    }
+
+    pub fn node_key_file(&self) -> PathBuf {
+        self.config_dir().join(NODE_KEY_FILE)
+    }
+
+    pub fn hbbft_key_file(&self) -> PathBuf {
+        self.config_dir().join(HBBFT_KEY_FILE)
+    }
+
+    pub fn priv_validator_key_file(&self) -> PathBuf {
+        self.config_dir().join(PRIV_VALIDATOR_KEY_FILE)
+    }
+
+    pub fn blockstore_dir(&self) -> PathBuf {
+        self.data_dir().join(BLOCKSTORE_DB)
+    }
+
+    pub fn raft_dir(&self) -> PathBuf {
+        self.data_dir().join(RAFT_DB)
+    }
+}
+
+/// `$HOME/.pow-node`, or `./.pow-node` when `$HOME` is not set.
+pub fn default_home() -> PathBuf {
+    std::env::var("HOME")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| PathBuf::from("."))
+        .join(DEFAULT_HOME)
+}
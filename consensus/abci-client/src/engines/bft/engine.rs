@@ -0,0 +1,974 @@
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use tokio::time::{sleep, Sleep};
+
+use super::{HeightVotes, Proposal, TendermintMessage, ValidatorSet, Vote, VoteType};
+use crate::error::{BlockError, TendermintError};
+use crate::utils::{deserialize, serialize};
+use crate::{next_timestamp, recent_headers, validate_block_body, validate_timestamp, MEDIAN_TIME_SPAN};
+use crate::{Block, ChainContext, Commit, CommitSig, ConsensusEngine, OutMessage, PeerId, PrivValidatorKey, Step, Target};
+
+// messages kept for the next height while the current one is being committed
+const MAX_FUTURE_MESSAGES: usize = 10_000;
+// how long to wait for a peer's committed block before asking again
+const SYNC_RETRY: Duration = Duration::from_secs(1);
+
+/// How long each step of a round waits, growing by `delta` every round so that
+/// slow validators eventually catch up.
+#[derive(Debug, Clone)]
+pub struct TendermintTimeouts {
+    pub propose: Duration,
+    pub prevote: Duration,
+    pub precommit: Duration,
+    pub delta: Duration,
+}
+
+impl TendermintTimeouts {
+    fn timeout(&self, step: RoundStep, round: i32) -> Duration {
+        let base = match step {
+            RoundStep::Propose => self.propose,
+            RoundStep::Prevote => self.prevote,
+            RoundStep::Precommit | RoundStep::Commit => self.precommit,
+        };
+        base + self.delta * round as u32
+    }
+}
+
+impl Default for TendermintTimeouts {
+    fn default() -> Self {
+        Self {
+            propose: Duration::from_millis(3_000),
+            prevote: Duration::from_millis(1_000),
+            precommit: Duration::from_millis(1_000),
+            delta: Duration::from_millis(500),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RoundStep {
+    Propose,
+    Prevote,
+    Precommit,
+    /// The height is decided, waiting for the app to commit the block.
+    Commit,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Timeout {
+    height: i64,
+    round: i32,
+    step: RoundStep,
+}
+
+/// Tendermint BFT: in every round the proposer of the round proposes a block, the
+/// validators prevote for it and, once a block has +2/3 prevotes (a polka), lock on it
+/// and precommit it. +2/3 precommits commit the block, which is final. A validator only
+/// prevotes another block than the one it is locked on after seeing a polka for it in
+/// a later round, so two blocks can never both get +2/3 precommits while less than
+/// 1/3 of the power is faulty. Rounds that do not make it time out and the next
+/// proposer takes over.
+///
+/// Follows "The latest gossip on BFT consensus" (Buchman, Kwon, Milosevic), with the
+/// voting power of the validators InitChain and EndBlock set up. A height only starts
+/// once a block is proposed or a message for it comes in, so an idle chain stays quiet.
+/// A node that sees messages of later heights asks the peer for the committed blocks
+/// it missed, one height at a time.
+pub struct TendermintEngine {
+    key: PrivValidatorKey,
+    timeouts: TendermintTimeouts,
+    // chain tip the height is decided on
+    tip: Option<ChainContext>,
+    validators: ValidatorSet,
+    height: i64,
+    round: i32,
+    step: RoundStep,
+    // round 0 starts with the first block or message of the height
+    started: bool,
+    locked: Option<(i32, Block)>,
+    valid: Option<(i32, Block)>,
+    // block from our mempool, proposed whenever it is our turn
+    own_block: Option<Block>,
+    proposals: HashMap<i32, Proposal>,
+    votes: HeightVotes,
+    // rounds in which the one-off rules have fired
+    prevote_timeouts: HashSet<i32>,
+    precommit_timeouts: HashSet<i32>,
+    polkas: HashSet<i32>,
+    timer: Option<(Pin<Box<Sleep>>, Timeout)>,
+    future: Vec<(PeerId, TendermintMessage)>,
+    // precommits that committed the previous block, put into our next block
+    last_commit: Option<Commit>,
+    // a peer at a later height, and the height it is at
+    ahead: Option<(PeerId, i64)>,
+    // height of the committed block last asked for, and when
+    requested: Option<(i64, Instant)>,
+}
+
+impl TendermintEngine {
+    pub fn new(key: PrivValidatorKey, timeouts: TendermintTimeouts) -> Self {
+        Self {
+            key,
+            timeouts,
+            tip: None,
+            validators: ValidatorSet::default(),
+            height: 0,
+            round: 0,
+            step: RoundStep::Propose,
+            started: false,
+            locked: None,
+            valid: None,
+            own_block: None,
+            proposals: HashMap::new(),
+            votes: HeightVotes::default(),
+            prevote_timeouts: HashSet::new(),
+            precommit_timeouts: HashSet::new(),
+            polkas: HashSet::new(),
+            timer: None,
+            future: vec![],
+            last_commit: None,
+            ahead: None,
+            requested: None,
+        }
+    }
+
+    fn is_validator(&self) -> bool {
+        self.validators.get(&self.key.address()).is_some()
+    }
+
+    fn chain_id(&self) -> String {
+        self.tip.as_ref().map(|tip| tip.state.chain_id.clone()).unwrap_or_default()
+    }
+
+    fn new_height(&mut self, ctx: &ChainContext) -> eyre::Result<()> {
+        self.validators = ValidatorSet::new(&ctx.validators(ctx.next_height())?);
+        if self.validators.is_empty() {
+            eyre::bail!("the chain has no ed25519 validators to run the tendermint engine with");
+        }
+        self.height = ctx.next_height();
+        self.round = 0;
+        self.step = RoundStep::Propose;
+        self.started = false;
+        self.locked = None;
+        self.valid = None;
+        self.own_block = None;
+        self.proposals.clear();
+        self.votes = HeightVotes::default();
+        self.prevote_timeouts.clear();
+        self.precommit_timeouts.clear();
+        self.polkas.clear();
+        self.timer = None;
+        if self.last_commit.as_ref().map_or(true, |commit| commit.height != ctx.height) && ctx.height > 0 {
+            self.last_commit = ctx.store.load_commit(ctx.height)?;
+        }
+        if self.ahead.as_ref().map_or(false, |(_, height)| *height <= self.height) {
+            self.ahead = None;
+        }
+        self.tip = Some(ctx.clone());
+        Ok(())
+    }
+
+    fn schedule(&mut self, step: RoundStep) {
+        let timeout = Timeout { height: self.height, round: self.round, step };
+        let duration = self.timeouts.timeout(step, self.round);
+        self.timer = Some((Box::pin(sleep(duration)), timeout));
+    }
+
+    fn start_round(&mut self, round: i32, out: &mut Step) -> eyre::Result<()> {
+        log::debug!("height {} round {} starting", self.height, round);
+        self.round = round;
+        self.step = RoundStep::Propose;
+        self.started = true;
+
+        let proposer = self.validators.proposer(self.height, round).address.clone();
+        if proposer == self.key.address() {
+            self.propose_in_round(out)?;
+        }
+        self.schedule(RoundStep::Propose);
+        Ok(())
+    }
+
+    // Proposes the block we saw a polka for, or else ours, if we have any.
+    fn propose_in_round(&mut self, out: &mut Step) -> eyre::Result<()> {
+        let (block, pol_round) = match (&self.valid, &self.own_block) {
+            (Some((round, block)), _) => (block.clone(), *round),
+            (None, Some(block)) => (block.clone(), -1),
+            (None, None) => {
+                log::debug!("our turn to propose at height {} round {}, waiting for txs", self.height, self.round);
+                return Ok(());
+            }
+        };
+        let mut proposal = Proposal {
+            height: self.height,
+            round: self.round,
+            pol_round,
+            block,
+            signature: vec![],
+        };
+        proposal.sign(&self.chain_id(), &self.key);
+        log::info!("proposing block {} at round {}", self.height, self.round);
+
+        out.extend(Step::broadcast(serialize(&TendermintMessage::Proposal(proposal.clone()))?));
+        self.proposals.insert(self.round, proposal);
+        Ok(())
+    }
+
+    fn vote(&mut self, vote_type: VoteType, block_hash: Option<Vec<u8>>, out: &mut Step) -> eyre::Result<()> {
+        let power = match self.validators.get(&self.key.address()) {
+            Some(validator) => validator.power,
+            // followers only watch
+            None => return Ok(()),
+        };
+        let mut vote = Vote::new(vote_type, self.height, self.round, block_hash, &self.key);
+        vote.sign(&self.chain_id(), &self.key);
+
+        out.extend(Step::broadcast(serialize(&TendermintMessage::Vote(vote.clone()))?));
+        self.votes.add(vote, power);
+        Ok(())
+    }
+
+    // Checks a message of the current height and records it.
+    fn receive(&mut self, message: TendermintMessage) -> eyre::Result<()> {
+        let chain_id = self.chain_id();
+        match message {
+            TendermintMessage::Proposal(proposal) => {
+                let expected = self.validators.proposer(self.height, proposal.round);
+                let proposer = hex::encode(&proposal.block.get_header().proposer);
+                if proposal.pol_round < 0 && proposal.block.get_header().proposer != expected.address {
+                    return Err(TendermintError::WrongProposer {
+                        height: self.height,
+                        round: proposal.round,
+                        proposer,
+                        expected: hex::encode(&expected.address),
+                    }
+                    .into());
+                }
+                // votes name the block by its hash, which has to be the header's
+                if proposal.block.get_hash() != proposal.block.get_header().hash() {
+                    return Err(BlockError::HashMismatch { height: self.height }.into());
+                }
+                if !proposal.verify(&chain_id, &expected.pub_key) {
+                    return Err(TendermintError::InvalidProposalSignature { height: self.height, round: proposal.round }.into());
+                }
+                self.proposals.entry(proposal.round).or_insert(proposal);
+            }
+            TendermintMessage::Vote(vote) => {
+                let validator = self.validators.get(&vote.validator_address).ok_or_else(|| TendermintError::UnknownValidator {
+                    height: self.height,
+                    address: hex::encode(&vote.validator_address),
+                })?;
+                if !vote.verify(&chain_id, &validator.pub_key) {
+                    return Err(TendermintError::InvalidVoteSignature {
+                        height: self.height,
+                        round: vote.round,
+                        address: hex::encode(&vote.validator_address),
+                    }
+                    .into());
+                }
+                let power = validator.power;
+                self.votes.add(vote, power);
+            }
+            // answered in `handle_message`
+            TendermintMessage::GetCommitted(_) | TendermintMessage::Committed(..) => {}
+        }
+        Ok(())
+    }
+
+    // Whether a proposed block may be voted for: it extends our tip and passes validation.
+    fn is_valid(&self, block: &Block) -> bool {
+        let tip = match &self.tip {
+            Some(tip) => tip,
+            None => return false,
+        };
+        let header = block.get_header();
+        if header.height != self.height || header.prev_hash != tip.last_block_hash || header.app_hash != tip.last_app_hash {
+            log::debug!("block {} does not extend our tip", header.height);
+            return false;
+        }
+        if let Err(err) = self.validate_block(tip, block) {
+            log::warn!("invalid block {}: {}", header.height, err);
+            return false;
+        }
+        true
+    }
+
+    fn block_with_hash(&self, hash: &[u8]) -> Option<Block> {
+        self.proposals
+            .values()
+            .map(|proposal| &proposal.block)
+            .chain(self.locked.iter().map(|(_, block)| block))
+            .chain(self.valid.iter().map(|(_, block)| block))
+            .find(|block| block.get_hash() == hash)
+            .cloned()
+    }
+
+    // Runs the rules of the protocol until none of them fires any more.
+    fn process(&mut self, out: &mut Step) -> eyre::Result<()> {
+        if !self.started || self.step == RoundStep::Commit {
+            return Ok(());
+        }
+        loop {
+            let fired = self.try_decide(out)?
+                || self.try_skip_round(out)?
+                || self.on_proposal(out)?
+                || self.on_polka(out)?
+                || self.on_nil_polka(out)?
+                || self.on_any_prevotes()
+                || self.on_any_precommits();
+            if !fired || self.step == RoundStep::Commit {
+                return Ok(());
+            }
+        }
+    }
+
+    // +2/3 precommits for a block in any round decide the height.
+    fn try_decide(&mut self, out: &mut Step) -> eyre::Result<bool> {
+        for round in self.votes.precommit_rounds() {
+            let hash = match self.votes.precommits(round).and_then(|set| set.majority(&self.validators)) {
+                Some(Some(hash)) => hash,
+                _ => continue,
+            };
+            // the proposal may still be on its way
+            let block = match self.block_with_hash(&hash) {
+                Some(block) => block,
+                None => continue,
+            };
+            if !self.is_valid(&block) {
+                // nothing we can do about it but keep the rest of the chain going
+                log::error!("+2/3 of the validators precommitted the invalid block {} at height {}", hex::encode(&hash), self.height);
+                continue;
+            }
+
+            let commit = self.make_commit(round, &hash);
+            if let Some(tip) = &self.tip {
+                tip.store.save_commit(&commit)?;
+            }
+            log::info!(
+                "height {} decided at round {} with {} of {} voting power",
+                self.height, round, commit.signed_power(), commit.total_power(),
+            );
+            self.last_commit = Some(commit);
+            self.step = RoundStep::Commit;
+            self.timer = None;
+            out.committed.push(block);
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    fn make_commit(&self, round: i32, hash: &[u8]) -> Commit {
+        let precommits = self.votes.precommits(round);
+        let signatures = self
+            .validators
+            .validators()
+            .iter()
+            .map(|validator| {
+                let vote = precommits
+                    .and_then(|set| set.get(&validator.address))
+                    .filter(|vote| vote.block_hash.as_deref() == Some(hash));
+                CommitSig {
+                    validator_address: validator.address.clone(),
+                    power: validator.power,
+                    timestamp: vote.map_or(0, |vote| vote.timestamp),
+                    signature: vote.map(|vote| vote.signature.clone()).unwrap_or_default(),
+                }
+            })
+            .collect();
+        Commit {
+            height: self.height,
+            round,
+            block_hash: hash.to_vec(),
+            signatures,
+        }
+    }
+
+    // More than 1/3 of the power is in a later round, so we are behind: join it.
+    fn try_skip_round(&mut self, out: &mut Step) -> eyre::Result<bool> {
+        for round in self.votes.rounds_above(self.round) {
+            if self.validators.is_one_third(self.votes.round_power(round, &self.validators)) {
+                self.start_round(round, out)?;
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // The proposal of the round: prevote it if we are not locked on another block,
+    // or if it comes with a polka more recent than our lock.
+    fn on_proposal(&mut self, out: &mut Step) -> eyre::Result<bool> {
+        if self.step != RoundStep::Propose {
+            return Ok(false);
+        }
+        let proposal = match self.proposals.get(&self.round) {
+            Some(proposal) => proposal.clone(),
+            None => return Ok(false),
+        };
+        let hash = proposal.block.get_hash();
+        let locked_on = |locked: &Option<(i32, Block)>| locked.as_ref().map_or(true, |(_, block)| block.get_hash() == hash);
+
+        let prevote = if proposal.pol_round < 0 {
+            self.is_valid(&proposal.block) && locked_on(&self.locked)
+        } else if proposal.pol_round < self.round && self.has_polka(proposal.pol_round, &hash) {
+            let locked_round = self.locked.as_ref().map_or(-1, |(round, _)| *round);
+            self.is_valid(&proposal.block) && (locked_round <= proposal.pol_round || locked_on(&self.locked))
+        } else {
+            // wait for the polka the proposer claims, or the timeout
+            return Ok(false);
+        };
+
+        self.vote(VoteType::Prevote, if prevote { Some(hash) } else { None }, out)?;
+        self.step = RoundStep::Prevote;
+        Ok(true)
+    }
+
+    fn has_polka(&self, round: i32, hash: &[u8]) -> bool {
+        self.votes
+            .prevotes(round)
+            .map_or(false, |set| self.validators.is_quorum(set.power_for(&Some(hash.to_vec()))))
+    }
+
+    // +2/3 prevotes for the proposal of the round: lock on it and precommit it.
+    fn on_polka(&mut self, out: &mut Step) -> eyre::Result<bool> {
+        if self.step < RoundStep::Prevote || self.polkas.contains(&self.round) {
+            return Ok(false);
+        }
+        let block = match self.proposals.get(&self.round) {
+            Some(proposal) => proposal.block.clone(),
+            None => return Ok(false),
+        };
+        let hash = block.get_hash();
+        if !self.has_polka(self.round, &hash) || !self.is_valid(&block) {
+            return Ok(false);
+        }
+        self.polkas.insert(self.round);
+
+        if self.step == RoundStep::Prevote {
+            self.locked = Some((self.round, block.clone()));
+            self.vote(VoteType::Precommit, Some(hash), out)?;
+            self.step = RoundStep::Precommit;
+        }
+        self.valid = Some((self.round, block));
+        Ok(true)
+    }
+
+    // +2/3 prevotes for nil: precommit nil.
+    fn on_nil_polka(&mut self, out: &mut Step) -> eyre::Result<bool> {
+        if self.step != RoundStep::Prevote {
+            return Ok(false);
+        }
+        let nil_power = self.votes.prevotes(self.round).map_or(0, |set| set.power_for(&None));
+        if !self.validators.is_quorum(nil_power) {
+            return Ok(false);
+        }
+        self.vote(VoteType::Precommit, None, out)?;
+        self.step = RoundStep::Precommit;
+        Ok(true)
+    }
+
+    // +2/3 prevotes for anything: give the votes for one block some time to come in.
+    fn on_any_prevotes(&mut self) -> bool {
+        let power = self.votes.prevotes(self.round).map_or(0, |set| set.total());
+        if self.step != RoundStep::Prevote || !self.validators.is_quorum(power) || !self.prevote_timeouts.insert(self.round) {
+            return false;
+        }
+        self.schedule(RoundStep::Prevote);
+        true
+    }
+
+    // +2/3 precommits for anything: the round is over once the timeout passes.
+    fn on_any_precommits(&mut self) -> bool {
+        let power = self.votes.precommits(self.round).map_or(0, |set| set.total());
+        if !self.validators.is_quorum(power) || !self.precommit_timeouts.insert(self.round) {
+            return false;
+        }
+        self.schedule(RoundStep::Precommit);
+        true
+    }
+
+    // Asks the peer that is ahead for the block of our height, unless we just did.
+    fn request_committed(&mut self, out: &mut Step) -> eyre::Result<()> {
+        let peer = match &self.ahead {
+            Some((peer, height)) if *height > self.height => peer.clone(),
+            _ => return Ok(()),
+        };
+        let pending = self.requested.map_or(false, |(height, at)| height == self.height && at.elapsed() < SYNC_RETRY);
+        if pending || self.step == RoundStep::Commit {
+            return Ok(());
+        }
+        log::info!("behind {} at height {}, asking for the committed block", peer, self.height);
+        self.requested = Some((self.height, Instant::now()));
+        out.messages.push(OutMessage {
+            target: Target::Peer(peer),
+            payload: serialize(&TendermintMessage::GetCommitted(self.height))?,
+        });
+        Ok(())
+    }
+
+    // Answers a peer that is behind with a block we committed and its precommits.
+    fn handle_get_committed(&self, ctx: &ChainContext, from: &PeerId, height: i64) -> eyre::Result<Step> {
+        let (block, commit) = match (ctx.store.load_block(height)?, ctx.store.load_commit(height)?) {
+            (Some(block), Some(commit)) => (block, commit),
+            _ => return Ok(Step::default()),
+        };
+        Ok(Step {
+            messages: vec![OutMessage {
+                target: Target::Peer(from.clone()),
+                payload: serialize(&TendermintMessage::Committed(block, commit))?,
+            }],
+            ..Default::default()
+        })
+    }
+
+    // Commits the block of our height a peer sent with +2/3 precommits for it.
+    fn handle_committed(&mut self, block: Block, commit: Commit, out: &mut Step) -> eyre::Result<()> {
+        let tip = match &self.tip {
+            Some(tip) if block.get_height() == self.height && self.step != RoundStep::Commit => tip.clone(),
+            // an answer we no longer need
+            _ => return Ok(()),
+        };
+        if block.get_header().prev_hash != tip.last_block_hash {
+            return Err(TendermintError::NotOnTip { height: self.height }.into());
+        }
+        self.validate_block(&tip, &block)?;
+        if commit.height != self.height || commit.block_hash != block.get_hash() {
+            return Err(TendermintError::CommitMismatch { height: self.height }.into());
+        }
+        verify_commit(&tip.state.chain_id, &self.validators, &commit, self.height)?;
+
+        log::info!("caught up on block {}", self.height);
+        tip.store.save_commit(&commit)?;
+        self.last_commit = Some(commit);
+        self.step = RoundStep::Commit;
+        self.timer = None;
+        out.committed.push(block);
+        Ok(())
+    }
+
+    fn on_timeout(&mut self, timeout: Timeout, out: &mut Step) -> eyre::Result<()> {
+        if timeout.height != self.height || timeout.round != self.round || self.step == RoundStep::Commit {
+            return Ok(());
+        }
+        match (timeout.step, self.step) {
+            (RoundStep::Propose, RoundStep::Propose) => {
+                log::debug!("height {} round {}: no valid proposal in time, prevoting nil", self.height, self.round);
+                self.vote(VoteType::Prevote, None, out)?;
+                self.step = RoundStep::Prevote;
+            }
+            (RoundStep::Prevote, RoundStep::Prevote) => {
+                self.vote(VoteType::Precommit, None, out)?;
+                self.step = RoundStep::Precommit;
+            }
+            (RoundStep::Precommit, _) => self.start_round(self.round + 1, out)?,
+            _ => return Ok(()),
+        }
+        self.process(out)
+    }
+}
+
+impl ConsensusEngine for TendermintEngine {
+    fn name(&self) -> &'static str {
+        "tendermint"
+    }
+
+    fn start(&mut self, ctx: &ChainContext) -> eyre::Result<Step> {
+        self.new_height(ctx)?;
+        log::info!(
+            "tendermint validator {} starting at height {} with {} validators, {} voting power{}",
+            hex::encode(self.key.address()), self.height, self.validators.validators().len(),
+            self.validators.total_power(), if self.is_validator() { "" } else { ", not one of them" },
+        );
+        Ok(Step::default())
+    }
+
+    /// Once per height: the block is kept and proposed whenever it is our turn.
+    fn can_propose(&self, _ctx: &ChainContext) -> bool {
+        self.tip.is_some() && self.own_block.is_none() && self.is_validator() && self.step != RoundStep::Commit
+    }
+
+    fn propose(&mut self, ctx: &ChainContext, mut block: Block) -> eyre::Result<Step> {
+        // a clock behind the chain's must not make our block invalid
+        let recent = recent_headers(ctx.store.as_ref(), block.get_height(), MEDIAN_TIME_SPAN)?;
+        block.set_timestamp(next_timestamp(block.get_header().timestamp, &recent));
+        if let Some(commit) = &self.last_commit {
+            block.set_last_commit(commit.clone());
+        }
+        block.set_proposer(self.key.address());
+        self.own_block = Some(block);
+
+        let mut out = Step::default();
+        if !self.started {
+            self.start_round(0, &mut out)?;
+        } else if self.step == RoundStep::Propose
+            && !self.proposals.contains_key(&self.round)
+            && self.validators.proposer(self.height, self.round).address == self.key.address()
+        {
+            self.propose_in_round(&mut out)?;
+        }
+        self.process(&mut out)?;
+        Ok(out)
+    }
+
+    /// Checks the hash and txs against the header, the app hash against our tip's, the
+    /// proposer against the validators of the height, the commit of the previous block
+    /// the block carries against the validators of that block, and that its time comes
+    /// after the median time of the blocks before it.
+    fn validate_block(&self, ctx: &ChainContext, block: &Block) -> eyre::Result<()> {
+        validate_block_body(block, &ctx.state.block_params)?;
+
+        let header = block.get_header();
+        if header.app_hash != ctx.last_app_hash {
+            return Err(TendermintError::AppHashMismatch { height: header.height }.into());
+        }
+        let validators = ValidatorSet::new(&ctx.validators(header.height)?);
+        if !header.proposer.is_empty() && validators.get(&header.proposer).is_none() {
+            return Err(TendermintError::UnknownValidator { height: header.height, address: hex::encode(&header.proposer) }.into());
+        }
+
+        if header.height > ctx.state.initial_height {
+            let commit = block.get_last_commit().ok_or(TendermintError::MissingLastCommit { height: header.height })?;
+            if commit.height != header.height - 1 || commit.block_hash != header.prev_hash {
+                return Err(TendermintError::LastCommitMismatch { height: header.height }.into());
+            }
+            let validators = ValidatorSet::new(&ctx.validators(commit.height)?);
+            verify_commit(&ctx.state.chain_id, &validators, commit, header.height)?;
+        }
+
+        let recent = recent_headers(ctx.store.as_ref(), header.height, MEDIAN_TIME_SPAN)?;
+        validate_timestamp(header, &recent)?;
+        Ok(())
+    }
+
+    fn handle_message(&mut self, ctx: &ChainContext, from: &PeerId, payload: &[u8]) -> eyre::Result<Step> {
+        let message: TendermintMessage = deserialize(payload)?;
+        let mut out = Step::default();
+
+        let message = match message {
+            TendermintMessage::GetCommitted(height) => return self.handle_get_committed(ctx, from, height),
+            TendermintMessage::Committed(block, commit) => {
+                self.handle_committed(block, commit, &mut out)?;
+                return Ok(out);
+            }
+            message => message,
+        };
+
+        let height = message.height();
+        // the peer decided our height, we may have missed the votes that did it
+        if height > self.height && self.ahead.as_ref().map_or(true, |(_, ahead)| *ahead < height) {
+            self.ahead = Some((from.clone(), height));
+        }
+        if height == self.height + 1 || (height == self.height && self.step == RoundStep::Commit) {
+            if self.future.len() < MAX_FUTURE_MESSAGES {
+                self.future.push((from.clone(), message));
+            }
+            self.request_committed(&mut out)?;
+            return Ok(out);
+        }
+        if height != self.height {
+            self.request_committed(&mut out)?;
+            return Ok(out);
+        }
+
+        self.receive(message)?;
+        if !self.started {
+            self.start_round(0, &mut out)?;
+        }
+        self.process(&mut out)?;
+        Ok(out)
+    }
+
+    /// Fires the round timeouts, replays the messages of the height that came in while
+    /// the previous one was being committed, and asks for the next committed block
+    /// while a peer is ahead.
+    fn poll_step(&mut self, cx: &mut Context<'_>) -> Poll<eyre::Result<Step>> {
+        let mut out = Step::default();
+        let mut progress = false;
+
+        let fired = match &mut self.timer {
+            Some((timer, _)) => timer.as_mut().poll(cx).is_ready(),
+            None => false,
+        };
+        if fired {
+            let (_, timeout) = self.timer.take().unwrap();
+            if let Err(err) = self.on_timeout(timeout, &mut out) {
+                return Poll::Ready(Err(err));
+            }
+            progress = true;
+        }
+
+        let height = self.height;
+        if self.step != RoundStep::Commit && self.future.iter().any(|(_, message)| message.height() == height) {
+            let (now, later): (Vec<_>, Vec<_>) = self.future.drain(..).partition(|(_, message)| message.height() == height);
+            self.future = later.into_iter().filter(|(_, message)| message.height() > height).collect();
+            for (from, message) in now {
+                if let Err(err) = self.receive(message) {
+                    out.faults.push((from, err));
+                    continue;
+                }
+                if !self.started {
+                    if let Err(err) = self.start_round(0, &mut out) {
+                        return Poll::Ready(Err(err));
+                    }
+                }
+            }
+            if let Err(err) = self.process(&mut out) {
+                return Poll::Ready(Err(err));
+            }
+            progress = true;
+        }
+
+        let requested = self.requested;
+        if let Err(err) = self.request_committed(&mut out) {
+            return Poll::Ready(Err(err));
+        }
+        progress |= self.requested != requested;
+
+        if progress {
+            Poll::Ready(Ok(out))
+        } else {
+            Poll::Pending
+        }
+    }
+
+    fn on_commit(&mut self, ctx: &ChainContext, _block: &Block) -> eyre::Result<()> {
+        self.new_height(ctx)
+    }
+}
+
+// Checks the signatures of a commit against the validators of its height, +2/3 of the
+// power has to be in. Errors name the block at `height`.
+fn verify_commit(chain_id: &str, validators: &ValidatorSet, commit: &Commit, height: i64) -> eyre::Result<()> {
+    let mut signed = 0;
+    for sig in commit.signatures.iter().filter(|sig| !sig.signature.is_empty()) {
+        // only the validators of the committed height count
+        let validator = match validators.get(&sig.validator_address) {
+            Some(validator) => validator,
+            None => continue,
+        };
+        let vote = Vote::from_commit_sig(commit.height, commit.round, &commit.block_hash, sig);
+        if !vote.verify(chain_id, &validator.pub_key) {
+            return Err(TendermintError::InvalidCommitSignature { height, address: hex::encode(&sig.validator_address) }.into());
+        }
+        signed += validator.power;
+    }
+    if !validators.is_quorum(signed) {
+        return Err(TendermintError::InsufficientCommitPower { height, signed, total: validators.total_power() }.into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::{BlockParamsRecord, ChainState, MemBlockStore, ValidatorUpdateRecord};
+
+    fn record(key: &PrivValidatorKey) -> ValidatorUpdateRecord {
+        ValidatorUpdateRecord { pub_key_type: "ed25519".to_string(), pub_key: key.public_key(), power: 10 }
+    }
+
+    fn context(key: &PrivValidatorKey) -> ChainContext {
+        let validator = record(key);
+        ChainContext {
+            store: Arc::new(MemBlockStore::new()),
+            state: ChainState {
+                chain_id: "test-chain".to_string(),
+                initial_height: 1,
+                validators: vec![validator],
+                block_params: BlockParamsRecord { max_bytes: -1, max_gas: -1 },
+                ..Default::default()
+            },
+            height: 0,
+            last_block_hash: vec![],
+            last_app_hash: b"app hash".to_vec(),
+        }
+    }
+
+    fn block(key: &PrivValidatorKey, ctx: &ChainContext, txs: Vec<Vec<u8>>) -> Block {
+        let now = chrono::Utc::now().timestamp_nanos();
+        let mut block = Block::new(ctx.next_height(), ctx.last_block_hash.clone(), ctx.last_app_hash.clone(), now, 0, txs);
+        block.set_proposer(key.address());
+        block
+    }
+
+    // a block of other txs under the hash of `block`
+    fn forged(key: &PrivValidatorKey, ctx: &ChainContext, block: &Block) -> Block {
+        let mut json = serde_json::to_value(self::block(key, ctx, vec![b"forged tx".to_vec()])).unwrap();
+        json["hash"] = serde_json::json!(block.get_hash());
+        serde_json::from_value(json).unwrap()
+    }
+
+    #[test]
+    fn accepts_a_block_on_our_tip() {
+        let key = PrivValidatorKey::generate();
+        let ctx = context(&key);
+        let engine = TendermintEngine::new(key.clone(), TendermintTimeouts::default());
+        engine.validate_block(&ctx, &block(&key, &ctx, vec![b"tx".to_vec()])).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_hash_copied_from_another_block() {
+        let key = PrivValidatorKey::generate();
+        let ctx = context(&key);
+        let engine = TendermintEngine::new(key.clone(), TendermintTimeouts::default());
+        let forged = forged(&key, &ctx, &block(&key, &ctx, vec![b"tx".to_vec()]));
+        let err = engine.validate_block(&ctx, &forged).unwrap_err();
+        assert!(matches!(err.downcast_ref::<BlockError>(), Some(BlockError::HashMismatch { height: 1 })));
+    }
+
+    #[test]
+    fn rejects_a_block_on_another_app_hash() {
+        let key = PrivValidatorKey::generate();
+        let mut ctx = context(&key);
+        let block = block(&key, &ctx, vec![]);
+        ctx.last_app_hash = b"other app hash".to_vec();
+        let engine = TendermintEngine::new(key, TendermintTimeouts::default());
+        let err = engine.validate_block(&ctx, &block).unwrap_err();
+        assert!(matches!(err.downcast_ref::<TendermintError>(), Some(TendermintError::AppHashMismatch { height: 1 })));
+    }
+
+    #[test]
+    fn rejects_a_proposal_of_a_forged_block() {
+        let key = PrivValidatorKey::generate();
+        let ctx = context(&key);
+        let mut engine = TendermintEngine::new(key.clone(), TendermintTimeouts::default());
+        engine.start(&ctx).unwrap();
+
+        let mut proposal = Proposal {
+            height: 1,
+            round: 0,
+            pol_round: -1,
+            block: forged(&key, &ctx, &block(&key, &ctx, vec![b"tx".to_vec()])),
+            signature: vec![],
+        };
+        proposal.sign(&ctx.state.chain_id, &key);
+        let payload = serialize(&TendermintMessage::Proposal(proposal)).unwrap();
+        let err = engine.handle_message(&ctx, &"peer".to_string(), &payload).unwrap_err();
+        assert!(matches!(err.downcast_ref::<BlockError>(), Some(BlockError::HashMismatch { height: 1 })));
+    }
+
+    // the precommit of `signer` for `block`, as a commit
+    fn commit(ctx: &ChainContext, block: &Block, signer: &PrivValidatorKey) -> Commit {
+        let mut precommit = Vote::new(VoteType::Precommit, block.get_height(), 0, Some(block.get_hash()), signer);
+        precommit.sign(&ctx.state.chain_id, signer);
+        Commit {
+            height: block.get_height(),
+            round: 0,
+            block_hash: block.get_hash(),
+            signatures: vec![CommitSig {
+                validator_address: signer.address(),
+                power: 10,
+                timestamp: precommit.timestamp,
+                signature: precommit.signature,
+            }],
+        }
+    }
+
+    // the chain after block 1, committed by `signer`, with `old` voting on block 1
+    // and `new` from block 2 on
+    fn after_block_1(old: &PrivValidatorKey, new: &PrivValidatorKey, signer: &PrivValidatorKey) -> (ChainContext, Block) {
+        let mut ctx = context(new);
+        let block_1 = block(old, &ctx, vec![]);
+        ctx.store.save_block(&block_1).unwrap();
+        ctx.store.save_validators(1, &[record(old)]).unwrap();
+        ctx.store.save_validators(2, &[record(new)]).unwrap();
+        ctx.height = 1;
+        ctx.last_block_hash = block_1.get_hash();
+
+        let commit = commit(&ctx, &block_1, signer);
+        let mut block_2 = block(new, &ctx, vec![]);
+        block_2.set_timestamp(block_1.get_header().timestamp + 1_000_000_000);
+        block_2.set_last_commit(commit);
+        (ctx, block_2)
+    }
+
+    #[test]
+    fn checks_the_last_commit_against_the_validators_of_its_height() {
+        let (old, new) = (PrivValidatorKey::generate(), PrivValidatorKey::generate());
+        let (ctx, block) = after_block_1(&old, &new, &old);
+        let engine = TendermintEngine::new(new.clone(), TendermintTimeouts::default());
+        engine.validate_block(&ctx, &block).unwrap();
+
+        let (ctx, block) = after_block_1(&old, &new, &new);
+        let err = engine.validate_block(&ctx, &block).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TendermintError>(),
+            Some(TendermintError::InsufficientCommitPower { height: 2, signed: 0, .. })
+        ));
+    }
+
+    #[test]
+    fn votes_with_the_validators_of_the_next_height() {
+        let (old, new) = (PrivValidatorKey::generate(), PrivValidatorKey::generate());
+        let (ctx, _) = after_block_1(&old, &new, &old);
+        let mut engine = TendermintEngine::new(old.clone(), TendermintTimeouts::default());
+        engine.start(&ctx).unwrap();
+        assert!(!engine.is_validator());
+        assert_eq!(engine.validators.proposer(2, 0).address, new.address());
+    }
+
+    #[test]
+    fn answers_requests_for_committed_blocks() {
+        let key = PrivValidatorKey::generate();
+        let ctx = context(&key);
+        let block = block(&key, &ctx, vec![b"tx".to_vec()]);
+        let commit = commit(&ctx, &block, &key);
+        ctx.store.save_block(&block).unwrap();
+        ctx.store.save_commit(&commit).unwrap();
+
+        let mut engine = TendermintEngine::new(key, TendermintTimeouts::default());
+        let payload = serialize(&TendermintMessage::GetCommitted(1)).unwrap();
+        let step = engine.handle_message(&ctx, &"peer".to_string(), &payload).unwrap();
+        assert_eq!(step.messages.len(), 1);
+        assert_eq!(step.messages[0].target, Target::Peer("peer".to_string()));
+        match deserialize::<TendermintMessage>(&step.messages[0].payload).unwrap() {
+            TendermintMessage::Committed(sent, sent_commit) => assert_eq!((sent, sent_commit), (block, commit)),
+            message => panic!("unexpected answer {:?}", message),
+        }
+    }
+
+    #[test]
+    fn asks_the_peer_ahead_for_the_block_it_missed() {
+        let key = PrivValidatorKey::generate();
+        let ctx = context(&key);
+        let mut engine = TendermintEngine::new(key.clone(), TendermintTimeouts::default());
+        engine.start(&ctx).unwrap();
+
+        let vote = Vote::new(VoteType::Prevote, 5, 0, None, &key);
+        let payload = serialize(&TendermintMessage::Vote(vote)).unwrap();
+        let step = engine.handle_message(&ctx, &"peer".to_string(), &payload).unwrap();
+        assert_eq!(step.messages.len(), 1);
+        assert_eq!(step.messages[0].target, Target::Peer("peer".to_string()));
+        assert!(matches!(deserialize::<TendermintMessage>(&step.messages[0].payload).unwrap(), TendermintMessage::GetCommitted(1)));
+
+        // the request is out, no need to ask again right away
+        let step = engine.handle_message(&ctx, &"peer".to_string(), &payload).unwrap();
+        assert!(step.messages.is_empty());
+    }
+
+    #[test]
+    fn catches_up_on_a_committed_block() {
+        let (validator, follower) = (PrivValidatorKey::generate(), PrivValidatorKey::generate());
+        let ctx = context(&validator);
+        let block = block(&validator, &ctx, vec![b"tx".to_vec()]);
+        let mut engine = TendermintEngine::new(follower.clone(), TendermintTimeouts::default());
+        engine.start(&ctx).unwrap();
+
+        // precommitted by someone outside the validator set
+        let payload = serialize(&TendermintMessage::Committed(block.clone(), commit(&ctx, &block, &follower))).unwrap();
+        let err = engine.handle_message(&ctx, &"peer".to_string(), &payload).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TendermintError>(),
+            Some(TendermintError::InsufficientCommitPower { height: 1, signed: 0, .. })
+        ));
+
+        let payload = serialize(&TendermintMessage::Committed(block.clone(), commit(&ctx, &block, &validator))).unwrap();
+        let step = engine.handle_message(&ctx, &"peer".to_string(), &payload).unwrap();
+        assert_eq!(step.committed, vec![block]);
+        assert_eq!(ctx.store.load_commit(1).unwrap().map(|commit| commit.height), Some(1));
+    }
+}
@@ -0,0 +1,9 @@
+mod engine;
+mod types;
+mod validators;
+mod votes;
+
+pub use engine::*;
+pub use types::*;
+pub use validators::*;
+pub use votes::*;
@@ -0,0 +1,119 @@
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{ed25519_sign_verify, serialize};
+use crate::{Block, Commit, CommitSig, PrivValidatorKey};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum VoteType {
+    Prevote,
+    Precommit,
+}
+
+/// A signed prevote or precommit. A vote without a block hash is a vote for nil.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Vote {
+    pub vote_type: VoteType,
+    pub height: i64,
+    pub round: i32,
+    pub block_hash: Option<Vec<u8>>,
+    // unix nanoseconds
+    pub timestamp: i64,
+    pub validator_address: Vec<u8>,
+    pub signature: Vec<u8>,
+}
+
+impl Vote {
+    pub fn new(vote_type: VoteType, height: i64, round: i32, block_hash: Option<Vec<u8>>, key: &PrivValidatorKey) -> Self {
+        Self {
+            vote_type,
+            height,
+            round,
+            block_hash,
+            timestamp: chrono::Utc::now().timestamp_nanos(),
+            validator_address: key.address(),
+            signature: vec![],
+        }
+    }
+
+    /// What the validator signs, bound to the chain so votes cannot be replayed on another one.
+    pub fn sign_bytes(&self, chain_id: &str) -> Vec<u8> {
+        serialize(&(
+            chain_id,
+            self.vote_type,
+            self.height,
+            self.round,
+            &self.block_hash,
+            self.timestamp,
+            &self.validator_address,
+        ))
+        .unwrap()
+    }
+
+    pub fn sign(&mut self, chain_id: &str, key: &PrivValidatorKey) {
+        self.signature = key.sign(&self.sign_bytes(chain_id));
+    }
+
+    pub fn verify(&self, chain_id: &str, public_key: &[u8]) -> bool {
+        ed25519_sign_verify(public_key, &self.signature, &self.sign_bytes(chain_id))
+    }
+
+    /// The precommit a commit entry was made from, to check its signature.
+    pub fn from_commit_sig(height: i64, round: i32, block_hash: &[u8], sig: &CommitSig) -> Self {
+        Self {
+            vote_type: VoteType::Precommit,
+            height,
+            round,
+            block_hash: Some(block_hash.to_vec()),
+            timestamp: sig.timestamp,
+            validator_address: sig.validator_address.clone(),
+            signature: sig.signature.clone(),
+        }
+    }
+}
+
+/// A block proposed for a round. `pol_round` is the round in which the block got
+/// +2/3 prevotes when the proposer re-proposes it, -1 otherwise.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Proposal {
+    pub height: i64,
+    pub round: i32,
+    pub pol_round: i32,
+    pub block: Block,
+    pub signature: Vec<u8>,
+}
+
+impl Proposal {
+    /// Covers the hash recomputed from the header, not the one the block carries.
+    pub fn sign_bytes(&self, chain_id: &str) -> Vec<u8> {
+        serialize(&(chain_id, self.height, self.round, self.pol_round, self.block.get_header().hash())).unwrap()
+    }
+
+    pub fn sign(&mut self, chain_id: &str, key: &PrivValidatorKey) {
+        self.signature = key.sign(&self.sign_bytes(chain_id));
+    }
+
+    pub fn verify(&self, chain_id: &str, public_key: &[u8]) -> bool {
+        ed25519_sign_verify(public_key, &self.signature, &self.sign_bytes(chain_id))
+    }
+}
+
+/// Messages the validators exchange. A node that fell behind asks its peers for the
+/// blocks it missed, which come with the precommits that committed them.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum TendermintMessage {
+    Proposal(Proposal),
+    Vote(Vote),
+    GetCommitted(i64),
+    Committed(Block, Commit),
+}
+
+impl TendermintMessage {
+    pub fn height(&self) -> i64 {
+        match self {
+            TendermintMessage::Proposal(proposal) => proposal.height,
+            TendermintMessage::Vote(vote) => vote.height,
+            TendermintMessage::GetCommitted(height) => *height,
+            TendermintMessage::Committed(block, _) => block.get_height(),
+        }
+    }
+}
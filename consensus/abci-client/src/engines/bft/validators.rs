@@ -0,0 +1,75 @@
+use crate::ValidatorUpdateRecord;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Validator {
+    pub address: Vec<u8>,
+    pub pub_key: Vec<u8>,
+    pub power: i64,
+}
+
+/// Validators voting on a height, with the power InitChain and EndBlock gave them.
+#[derive(Debug, Clone, Default)]
+pub struct ValidatorSet {
+    // sorted by address
+    validators: Vec<Validator>,
+    total_power: i64,
+}
+
+impl ValidatorSet {
+    /// The ed25519 validators among `records`, the only key type votes are signed with.
+    pub fn new(records: &[ValidatorUpdateRecord]) -> Self {
+        let mut validators: Vec<Validator> = records
+            .iter()
+            .filter(|validator| validator.power > 0)
+            .filter(|validator| {
+                if validator.pub_key_type != "ed25519" {
+                    log::warn!("skipping {} validator, votes are signed with ed25519", validator.pub_key_type);
+                    return false;
+                }
+                true
+            })
+            .map(|validator| Validator {
+                address: validator.address(),
+                pub_key: validator.pub_key.clone(),
+                power: validator.power,
+            })
+            .collect();
+        validators.sort_by(|a, b| a.address.cmp(&b.address));
+        let total_power = validators.iter().map(|validator| validator.power).sum();
+
+        Self { validators, total_power }
+    }
+
+    pub fn validators(&self) -> &[Validator] {
+        &self.validators
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    pub fn total_power(&self) -> i64 {
+        self.total_power
+    }
+
+    pub fn get(&self, address: &[u8]) -> Option<&Validator> {
+        self.validators.iter().find(|validator| validator.address == address)
+    }
+
+    /// Whether `power` is more than 2/3 of the total.
+    pub fn is_quorum(&self, power: i64) -> bool {
+        power * 3 > self.total_power * 2
+    }
+
+    /// Whether `power` is more than 1/3 of the total, so at least one honest validator is in.
+    pub fn is_one_third(&self, power: i64) -> bool {
+        power * 3 > self.total_power
+    }
+
+    /// Proposer of a round: the validators take turns by address order, moving on
+    /// with every height and every round.
+    pub fn proposer(&self, height: i64, round: i32) -> &Validator {
+        let index = (height as u64 + round as u64) % self.validators.len() as u64;
+        &self.validators[index as usize]
+    }
+}
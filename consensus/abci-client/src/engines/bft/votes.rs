@@ -0,0 +1,109 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::{ValidatorSet, Vote, VoteType};
+
+/// Votes of one type in one round, tallied by the voters' power.
+#[derive(Debug, Default)]
+pub struct VoteSet {
+    votes: BTreeMap<Vec<u8>, Vote>,
+    // power behind each block hash, None for nil
+    power: HashMap<Option<Vec<u8>>, i64>,
+    total: i64,
+}
+
+impl VoteSet {
+    /// Adds a checked vote. Returns false if the validator already voted in this set;
+    /// a different vote from it is an equivocation and is reported, not counted.
+    pub fn add(&mut self, vote: Vote, power: i64) -> bool {
+        if let Some(existing) = self.votes.get(&vote.validator_address) {
+            if existing.block_hash != vote.block_hash {
+                log::warn!(
+                    "validator {} equivocated at height {} round {}: {:?} and {:?}",
+                    hex::encode(&vote.validator_address), vote.height, vote.round,
+                    existing.block_hash.as_ref().map(hex::encode), vote.block_hash.as_ref().map(hex::encode),
+                );
+            }
+            return false;
+        }
+        *self.power.entry(vote.block_hash.clone()).or_default() += power;
+        self.total += power;
+        self.votes.insert(vote.validator_address.clone(), vote);
+        true
+    }
+
+    /// Power of the votes for `block_hash`, None for nil.
+    pub fn power_for(&self, block_hash: &Option<Vec<u8>>) -> i64 {
+        self.power.get(block_hash).copied().unwrap_or(0)
+    }
+
+    /// Power of all the votes, whatever they are for.
+    pub fn total(&self) -> i64 {
+        self.total
+    }
+
+    /// The value that got more than 2/3 of the power, if any.
+    pub fn majority(&self, validators: &ValidatorSet) -> Option<Option<Vec<u8>>> {
+        self.power
+            .iter()
+            .find(|(_, power)| validators.is_quorum(**power))
+            .map(|(block_hash, _)| block_hash.clone())
+    }
+
+    pub fn get(&self, address: &[u8]) -> Option<&Vote> {
+        self.votes.get(address)
+    }
+}
+
+/// Every vote received for the height being decided.
+#[derive(Debug, Default)]
+pub struct HeightVotes {
+    sets: BTreeMap<(i32, VoteType), VoteSet>,
+}
+
+impl HeightVotes {
+    pub fn add(&mut self, vote: Vote, power: i64) -> bool {
+        self.sets.entry((vote.round, vote.vote_type)).or_default().add(vote, power)
+    }
+
+    pub fn get(&self, round: i32, vote_type: VoteType) -> Option<&VoteSet> {
+        self.sets.get(&(round, vote_type))
+    }
+
+    pub fn prevotes(&self, round: i32) -> Option<&VoteSet> {
+        self.get(round, VoteType::Prevote)
+    }
+
+    pub fn precommits(&self, round: i32) -> Option<&VoteSet> {
+        self.get(round, VoteType::Precommit)
+    }
+
+    /// Power of the validators that voted anything in `round`.
+    pub fn round_power(&self, round: i32, validators: &ValidatorSet) -> i64 {
+        validators
+            .validators()
+            .iter()
+            .filter(|validator| {
+                [VoteType::Prevote, VoteType::Precommit].iter().any(|vote_type| {
+                    self.get(round, *vote_type).map_or(false, |set| set.get(&validator.address).is_some())
+                })
+            })
+            .map(|validator| validator.power)
+            .sum()
+    }
+
+    /// Rounds above `round` that have votes.
+    pub fn rounds_above(&self, round: i32) -> Vec<i32> {
+        let mut rounds: Vec<i32> = self.sets.keys().map(|(r, _)| *r).filter(|r| *r > round).collect();
+        rounds.dedup();
+        rounds
+    }
+
+    /// Rounds that have precommits.
+    pub fn precommit_rounds(&self) -> Vec<i32> {
+        self.sets
+            .iter()
+            .filter(|((_, vote_type), _)| *vote_type == VoteType::Precommit)
+            .map(|((round, _), _)| *round)
+            .collect()
+    }
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use crate::{Block, BlockStore, ChainState, ValidatorUpdateRecord};
+
+/// Id of a peer, the hex address of its node key.
+pub type PeerId = String;
+
+/// Who an engine message goes to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    All,
+    /// Every peer but one, to relay a message without sending it back to where it came from.
+    AllExcept(PeerId),
+    Peer(PeerId),
+}
+
+/// A message an engine wants delivered to its peers, serialized by the engine itself.
+#[derive(Debug, Clone)]
+pub struct OutMessage {
+    pub target: Target,
+    pub payload: Vec<u8>,
+}
+
+/// What came out of feeding an event to a `ConsensusEngine`.
+#[derive(Debug, Default)]
+pub struct Step {
+    /// Blocks the engine decided on, in height order, to be executed by the app.
+    pub committed: Vec<Block>,
+    /// Blocks that joined the engine's chain without being final yet, in height order.
+    /// Their txs are taken out of the mempool.
+    pub pending: Vec<Block>,
+    /// Pending blocks that left the engine's chain, their txs go back to the mempool.
+    pub orphaned: Vec<Block>,
+    /// Messages to send to peers.
+    pub messages: Vec<OutMessage>,
+    /// Peers whose messages broke the rules, with what was wrong, to be penalized.
+    pub faults: Vec<(PeerId, eyre::Report)>,
+}
+
+impl Step {
+    pub fn commit(block: Block) -> Self {
+        Self {
+            committed: vec![block],
+            ..Default::default()
+        }
+    }
+
+    pub fn broadcast(payload: Vec<u8>) -> Self {
+        Self {
+            messages: vec![OutMessage { target: Target::All, payload }],
+            ..Default::default()
+        }
+    }
+
+    /// Passes a peer's message on to the other peers, which is how blocks spread
+    /// beyond the proposer's own peers.
+    pub fn relay(from: &PeerId, payload: Vec<u8>) -> Self {
+        Self {
+            messages: vec![OutMessage { target: Target::AllExcept(from.clone()), payload }],
+            ..Default::default()
+        }
+    }
+
+    pub fn extend(&mut self, other: Step) {
+        self.committed.extend(other.committed);
+        // a block that joined and left the chain within the step never touched the mempool
+        for block in other.orphaned {
+            let hash = block.get_hash();
+            match self.pending.iter().position(|pending| pending.get_hash() == hash) {
+                Some(i) => {
+                    self.pending.remove(i);
+                }
+                None => self.orphaned.push(block),
+            }
+        }
+        self.pending.extend(other.pending);
+        self.messages.extend(other.messages);
+        self.faults.extend(other.faults);
+    }
+}
+
+/// Snapshot of the committed chain handed to the engine with every event.
+#[derive(Clone)]
+pub struct ChainContext {
+    pub store: Arc<dyn BlockStore>,
+    pub state: ChainState,
+    /// Height of the last block the app committed, 0 before the first block.
+    pub height: i64,
+    pub last_block_hash: Vec<u8>,
+    pub last_app_hash: Vec<u8>,
+}
+
+impl ChainContext {
+    /// Height of the next block, the first one is at the genesis initial height.
+    pub fn next_height(&self) -> i64 {
+        if self.height == 0 {
+            self.state.initial_height
+        } else {
+            self.height + 1
+        }
+    }
+
+    /// Validators voting on `height`. A store without the set of the height, like the
+    /// ones of chains run without an app, gets the set of the state.
+    pub fn validators(&self, height: i64) -> eyre::Result<Vec<ValidatorUpdateRecord>> {
+        Ok(self.store.load_validators(height)?.unwrap_or_else(|| self.state.validators.clone()))
+    }
+}
+
+/// How the nodes agree on the next block. The ABCI side (mempool, BeginBlock..Commit,
+/// block store) is driven by `Engine` and shared by every consensus engine.
+///
+/// Engines do no IO of their own: they get the events and hand back a `Step` with the
+/// blocks they committed and the messages for their peers. Work running in the
+/// background, like mining or timeouts, is reported through `poll_step`.
+pub trait ConsensusEngine: Send {
+    fn name(&self) -> &'static str;
+
+    /// Called once the app is synced with the block store, before any other event.
+    fn start(&mut self, _ctx: &ChainContext) -> eyre::Result<Step> {
+        Ok(Step::default())
+    }
+
+    /// Whether this node may propose the next block now.
+    fn can_propose(&self, _ctx: &ChainContext) -> bool {
+        true
+    }
+
+    /// Starts agreeing on a block built from the mempool on top of the context's tip.
+    /// The engine fills in its consensus fields before committing or sending it.
+    fn propose(&mut self, ctx: &ChainContext, block: Block) -> eyre::Result<Step>;
+
+    /// Gives up on the proposal in progress so a better one can be made. Returns false
+    /// when there is nothing to cancel or the proposal has already left the node.
+    fn cancel_proposal(&mut self) -> bool {
+        false
+    }
+
+    /// Checks the consensus fields of a block received from a peer or read back from
+    /// the block store.
+    fn validate_block(&self, ctx: &ChainContext, block: &Block) -> eyre::Result<()>;
+
+    /// Handles a message from a peer.
+    fn handle_message(&mut self, ctx: &ChainContext, from: &PeerId, payload: &[u8]) -> eyre::Result<Step>;
+
+    /// Polls the work the engine runs in the background. It is polled again after every
+    /// other event, so work that only ever results from events needs no waker.
+    fn poll_step(&mut self, _cx: &mut Context<'_>) -> Poll<eyre::Result<Step>> {
+        Poll::Pending
+    }
+
+    /// Period of `tick`, for engines that count time in ticks instead of running
+    /// timers of their own, which keeps them deterministic on an in-memory network.
+    fn tick_interval(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Called every `tick_interval`.
+    fn tick(&mut self, _ctx: &ChainContext) -> eyre::Result<Step> {
+        Ok(Step::default())
+    }
+
+    /// Height and hash of the head of the engine's chain. Engines that only commit
+    /// blocks once they are final may be ahead of the committed chain.
+    fn tip(&self, ctx: &ChainContext) -> (i64, Vec<u8>) {
+        (ctx.height, ctx.last_block_hash.clone())
+    }
+
+    /// How many blocks below its own the block whose app hash a block header carries is.
+    fn app_hash_lag(&self) -> i64 {
+        1
+    }
+
+    /// Called after the app committed a block, whoever proposed it.
+    fn on_commit(&mut self, _ctx: &ChainContext, _block: &Block) -> eyre::Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::{HashSet, VecDeque};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hbbft::honey_badger::{Batch, EncryptionSchedule, HoneyBadger, Message};
+use hbbft::{NetworkInfo, Target as HbTarget};
+use rand::rngs::OsRng;
+use serde::{Deserialize, Serialize};
+
+use crate::mempool::tx_hash;
+use crate::utils::{deserialize, serialize};
+use crate::{next_timestamp, recent_headers, MEDIAN_TIME_SPAN};
+use crate::{Block, ChainContext, ConsensusEngine, HbbftKeyShare, HbbftValidators, OutMessage, PeerId, Step, Target};
+
+/// What every validator puts into an epoch: the txs it drained from its mempool
+/// and the time it built them at.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct HbContribution {
+    pub timestamp: i64,
+    pub txs: Vec<Vec<u8>>,
+}
+
+type HbStep = hbbft::honey_badger::Step<HbContribution, PeerId>;
+
+/// HoneyBadgerBFT: asynchronous BFT agreement on batches of txs among the fixed
+/// validator set of the genesis file.
+///
+/// Every epoch each validator contributes its pending txs threshold-encrypted, the
+/// validators agree on a common subset of contributions (reliable broadcast + binary
+/// agreement), decrypt them together and the union becomes one block. The engine
+/// relies on `hbbft` for the protocol, and maps its epochs to block heights.
+pub struct HbbftEngine {
+    netinfo: Arc<NetworkInfo<PeerId>>,
+    // built in `start`, once the epoch to resume from is known
+    hb: Option<HoneyBadger<HbContribution, PeerId>>,
+    // agreed batches waiting for the previous block to be committed by the app
+    batches: VecDeque<Batch<HbContribution, PeerId>>,
+    // chain tip the next block is built on
+    tip: Option<ChainContext>,
+}
+
+impl HbbftEngine {
+    pub fn new(our_id: PeerId, key_share: HbbftKeyShare, validators: &HbbftValidators) -> eyre::Result<Self> {
+        if !validators.validators.contains(&our_id) {
+            eyre::bail!("node {} is not one of the hbbft validators of the genesis file", our_id);
+        }
+        if key_share.node_id != our_id {
+            eyre::bail!("hbbft key share belongs to node {}, not {}", key_share.node_id, our_id);
+        }
+        let netinfo = NetworkInfo::new(
+            our_id,
+            key_share.secret_key_share.0,
+            validators.public_key_set.clone(),
+            validators.validators.iter().cloned(),
+        );
+        Ok(Self::with_netinfo(netinfo))
+    }
+
+    /// Engine for a node of a network set up by the caller, e.g. `NetworkInfo::generate_map`.
+    pub fn with_netinfo(netinfo: NetworkInfo<PeerId>) -> Self {
+        Self {
+            netinfo: Arc::new(netinfo),
+            hb: None,
+            batches: VecDeque::new(),
+            tip: None,
+        }
+    }
+
+    fn hb(&mut self) -> eyre::Result<&mut HoneyBadger<HbContribution, PeerId>> {
+        self.hb.as_mut().ok_or_else(|| eyre::eyre!("hbbft engine is not started"))
+    }
+
+    // Keeps the agreed batches for `poll_step` and turns the hbbft messages into ours.
+    fn convert_step(&mut self, step: HbStep) -> eyre::Result<Step> {
+        if !step.fault_log.is_empty() {
+            log::warn!("hbbft faults: {:?}", step.fault_log);
+        }
+        self.batches.extend(step.output);
+
+        let mut out = Step::default();
+        for msg in step.messages {
+            let payload = serialize(&msg.message)?;
+            let targets: Vec<Target> = match msg.target {
+                HbTarget::Nodes(ids) => ids.into_iter().map(Target::Peer).collect(),
+                HbTarget::AllExcept(excluded) if excluded.is_empty() => vec![Target::All],
+                HbTarget::AllExcept(excluded) => self
+                    .netinfo
+                    .all_ids()
+                    .filter(|id| *id != self.netinfo.our_id() && !excluded.contains(*id))
+                    .cloned()
+                    .map(Target::Peer)
+                    .collect(),
+            };
+            for target in targets {
+                out.messages.push(OutMessage { target, payload: payload.clone() });
+            }
+        }
+        Ok(out)
+    }
+
+    // The block for an agreed batch: the contributions' txs in validator order, without
+    // duplicates, at the median of the contributed timestamps so every node builds the
+    // very same block, moved past the median time of the blocks before it if need be.
+    fn batch_block(tip: &ChainContext, batch: &Batch<HbContribution, PeerId>) -> eyre::Result<Block> {
+        let mut seen = HashSet::new();
+        let mut txs = vec![];
+        let mut timestamps = vec![];
+        for (_, contribution) in batch.contributions() {
+            timestamps.push(contribution.timestamp);
+            for tx in &contribution.txs {
+                if seen.insert(tx_hash(tx)) {
+                    txs.push(tx.clone());
+                }
+            }
+        }
+        timestamps.sort_unstable();
+        let timestamp = timestamps.get(timestamps.len() / 2).copied().unwrap_or_default();
+        let recent = recent_headers(tip.store.as_ref(), tip.next_height(), MEDIAN_TIME_SPAN)?;
+
+        Ok(Block::new(
+            tip.next_height(),
+            tip.last_block_hash.clone(),
+            tip.last_app_hash.clone(),
+            next_timestamp(timestamp, &recent),
+            0,
+            txs,
+        ))
+    }
+}
+
+impl ConsensusEngine for HbbftEngine {
+    fn name(&self) -> &'static str {
+        "hbbft"
+    }
+
+    fn start(&mut self, ctx: &ChainContext) -> eyre::Result<Step> {
+        // one epoch per block since the initial height
+        let epoch = (ctx.next_height() - ctx.state.initial_height) as u64;
+        log::info!(
+            "hbbft node {} starting at epoch {} with {} validators",
+            self.netinfo.our_id(), epoch, self.netinfo.num_nodes(),
+        );
+        self.hb = Some(
+            HoneyBadger::builder(self.netinfo.clone())
+                .epoch(epoch)
+                .encryption_schedule(EncryptionSchedule::Always)
+                .build(),
+        );
+        self.tip = Some(ctx.clone());
+        Ok(Step::default())
+    }
+
+    fn can_propose(&self, _ctx: &ChainContext) -> bool {
+        // one contribution per epoch
+        self.hb.as_ref().map_or(false, |hb| !hb.has_input())
+    }
+
+    fn propose(&mut self, _ctx: &ChainContext, block: Block) -> eyre::Result<Step> {
+        let contribution = HbContribution {
+            timestamp: block.get_header().timestamp,
+            txs: block.get_txs().to_vec(),
+        };
+        let step = self.hb()?.propose(&contribution, &mut OsRng)?;
+        self.convert_step(step)
+    }
+
+    fn validate_block(&self, _ctx: &ChainContext, block: &Block) -> eyre::Result<()> {
+        let header = block.get_header();
+        if header.bits != 0 || header.nonce != 0 {
+            eyre::bail!("hbbft block {} carries proof of work fields", header.height);
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, _ctx: &ChainContext, from: &PeerId, payload: &[u8]) -> eyre::Result<Step> {
+        let message: Message<PeerId> = deserialize(payload)?;
+        let mut out = Step::default();
+
+        // an epoch only completes once enough validators contributed, so join the ones
+        // started by others with an empty contribution
+        let hb = self.hb()?;
+        if message.epoch() == hb.next_epoch() && !hb.has_input() {
+            let now = chrono::Utc::now().timestamp_nanos();
+            let step = hb.propose(&HbContribution { timestamp: now, txs: vec![] }, &mut OsRng)?;
+            out.extend(self.convert_step(step)?);
+        }
+
+        let step = self.hb()?.handle_message(from, message)?;
+        out.extend(self.convert_step(step)?);
+        Ok(out)
+    }
+
+    /// Hands out the agreed batches as blocks, one at a time as each needs the app
+    /// hash of the block before it.
+    fn poll_step(&mut self, _cx: &mut Context<'_>) -> Poll<eyre::Result<Step>> {
+        let (tip, batch) = match (&self.tip, self.batches.front()) {
+            (Some(tip), Some(batch)) => (tip, batch),
+            _ => return Poll::Pending,
+        };
+        let block = match Self::batch_block(tip, batch) {
+            Ok(block) => block,
+            Err(err) => return Poll::Ready(Err(err)),
+        };
+        log::info!("hbbft epoch {} agreed on {} txs", batch.epoch(), block.get_txs().len());
+        self.batches.pop_front();
+        // wait for the app to commit this block before building the next one
+        self.tip = None;
+        Poll::Ready(Ok(Step::commit(block)))
+    }
+
+    fn on_commit(&mut self, ctx: &ChainContext, _block: &Block) -> eyre::Result<()> {
+        self.tip = Some(ctx.clone());
+        Ok(())
+    }
+}
@@ -0,0 +1,77 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use eyre::WrapErr;
+use hbbft::crypto::serde_impl::SerdeSecret;
+use hbbft::crypto::{PublicKeySet, SecretKeySet, SecretKeyShare};
+use serde::{Deserialize, Serialize};
+
+use crate::PeerId;
+
+/// Public part of the HoneyBadgerBFT setup, shared by every node through the genesis file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct HbbftValidators {
+    /// Node ids of the validators. The key share of the validator at index `i`, in
+    /// sorted order, is share `i` of `public_key_set`.
+    pub validators: Vec<PeerId>,
+    pub public_key_set: PublicKeySet,
+}
+
+/// Secret key share of one validator, kept in `<home>/config/hbbft_key.json`.
+#[derive(Serialize, Deserialize)]
+pub struct HbbftKeyShare {
+    pub node_id: PeerId,
+    pub secret_key_share: SerdeSecret<SecretKeyShare>,
+}
+
+impl HbbftValidators {
+    /// Max number of faulty validators tolerated, `f` with `n >= 3f + 1`.
+    pub fn max_faulty(&self) -> usize {
+        (self.validators.len().max(1) - 1) / 3
+    }
+}
+
+impl HbbftKeyShare {
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read(path).wrap_err_with(|| format!("failed to read hbbft key share {:?}", path))?;
+        let key = serde_json::from_slice(&content)
+            .wrap_err_with(|| format!("failed to parse hbbft key share {:?}", path))?;
+        Ok(key)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+/// Trusted dealer: creates a threshold key set for `node_ids` that tolerates
+/// `(n - 1) / 3` faulty validators, and hands out one share per node.
+pub fn deal_hbbft_keys(node_ids: &[PeerId]) -> (HbbftValidators, BTreeMap<PeerId, HbbftKeyShare>) {
+    let mut validators = node_ids.to_vec();
+    validators.sort();
+    validators.dedup();
+
+    let max_faulty = (validators.len().max(1) - 1) / 3;
+    let sk_set = SecretKeySet::random(max_faulty, &mut rand::thread_rng());
+
+    let shares = validators
+        .iter()
+        .enumerate()
+        .map(|(i, node_id)| {
+            let share = HbbftKeyShare {
+                node_id: node_id.clone(),
+                secret_key_share: SerdeSecret(sk_set.secret_key_share(i)),
+            };
+            (node_id.clone(), share)
+        })
+        .collect();
+
+    let public = HbbftValidators {
+        validators,
+        public_key_set: sk_set.public_keys(),
+    };
+    (public, shares)
+}
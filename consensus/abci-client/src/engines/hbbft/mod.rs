@@ -1,3 +1,5 @@
 mod hb;
+mod keys;
 
 pub use hb::*;
+pub use keys::*;
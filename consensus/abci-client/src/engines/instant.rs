@@ -0,0 +1,25 @@
+use crate::{Block, ChainContext, ConsensusEngine, PeerId, Step};
+
+/// No consensus at all: every proposed block is committed on the spot.
+/// Useful for a single node in development, where only the app logic matters.
+#[derive(Debug, Default)]
+pub struct InstantEngine;
+
+impl ConsensusEngine for InstantEngine {
+    fn name(&self) -> &'static str {
+        "instant"
+    }
+
+    fn propose(&mut self, _ctx: &ChainContext, block: Block) -> eyre::Result<Step> {
+        Ok(Step::commit(block))
+    }
+
+    fn validate_block(&self, _ctx: &ChainContext, _block: &Block) -> eyre::Result<()> {
+        Ok(())
+    }
+
+    fn handle_message(&mut self, _ctx: &ChainContext, from: &PeerId, _payload: &[u8]) -> eyre::Result<Step> {
+        log::warn!("instant engine ignores messages, got one from {}", from);
+        Ok(Step::default())
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, HashSet, VecDeque};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use futures::task::noop_waker_ref;
+
+use crate::{Block, BlockStore, ChainContext, ChainState, ConsensusEngine, MemBlockStore, PeerId, Step, Target};
+
+// a node of the in-memory network, with its own copy of the chain
+struct MemNode {
+    engine: Box<dyn ConsensusEngine>,
+    ctx: ChainContext,
+}
+
+/// In-process network of consensus engines talking through in-memory queues, to run
+/// N nodes of an engine without an app, sockets or timers. Committed blocks are
+/// only stored, no app executes them. Nodes can be taken down, which drops every
+/// message to and from them, and restarted on their block store.
+pub struct MemNetwork {
+    nodes: BTreeMap<PeerId, MemNode>,
+    // messages in flight: from, to, payload
+    queue: VecDeque<(PeerId, PeerId, Vec<u8>)>,
+    down: HashSet<PeerId>,
+}
+
+impl MemNetwork {
+    pub fn new() -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            queue: VecDeque::new(),
+            down: HashSet::new(),
+        }
+    }
+
+    /// Adds a node on an empty chain and starts its engine.
+    pub fn add_node(&mut self, id: PeerId, engine: Box<dyn ConsensusEngine>, state: ChainState) -> eyre::Result<()> {
+        let ctx = ChainContext {
+            store: Arc::new(MemBlockStore::new()),
+            state,
+            height: 0,
+            last_block_hash: vec![],
+            last_app_hash: vec![],
+        };
+        self.nodes.insert(id.clone(), MemNode { engine, ctx });
+
+        let step = {
+            let node = self.nodes.get_mut(&id).unwrap();
+            node.engine.start(&node.ctx)?
+        };
+        self.apply_step(&id, step)
+    }
+
+    /// Replaces the node's engine, as after a crash: the new engine starts on what the
+    /// node's block store holds.
+    pub fn restart(&mut self, id: &PeerId, engine: Box<dyn ConsensusEngine>) -> eyre::Result<()> {
+        let step = {
+            let node = self.node(id)?;
+            node.ctx.height = node.ctx.store.height()?;
+            node.ctx.last_block_hash = match node.ctx.store.load_block(node.ctx.height)? {
+                Some(block) => block.get_hash(),
+                None => vec![],
+            };
+            node.engine = engine;
+            node.engine.start(&node.ctx)?
+        };
+        self.apply_step(id, step)
+    }
+
+    /// Takes the node off the network, or brings it back.
+    pub fn set_down(&mut self, id: &PeerId, down: bool) {
+        if down {
+            self.down.insert(id.clone());
+        } else {
+            self.down.remove(id);
+        }
+    }
+
+    pub fn node_ids(&self) -> Vec<PeerId> {
+        self.nodes.keys().cloned().collect()
+    }
+
+    /// Builds a block of `txs` on the node's tip and proposes it, if the engine lets it.
+    pub fn propose(&mut self, id: &PeerId, txs: Vec<Vec<u8>>, timestamp: i64) -> eyre::Result<bool> {
+        if self.down.contains(id) {
+            return Ok(false);
+        }
+        let step = {
+            let node = self.node(id)?;
+            if !node.engine.can_propose(&node.ctx) {
+                return Ok(false);
+            }
+            let block = Block::new(
+                node.ctx.next_height(),
+                node.ctx.last_block_hash.clone(),
+                node.ctx.last_app_hash.clone(),
+                timestamp,
+                0,
+                txs,
+            );
+            node.engine.propose(&node.ctx, block)?
+        };
+        self.apply_step(id, step)?;
+        Ok(true)
+    }
+
+    /// Ticks every engine that is up once, see `ConsensusEngine::tick`.
+    pub fn tick(&mut self) -> eyre::Result<()> {
+        for id in self.node_ids() {
+            if self.down.contains(&id) {
+                continue;
+            }
+            let step = {
+                let node = self.node(&id)?;
+                node.engine.tick(&node.ctx)?
+            };
+            self.apply_step(&id, step)?;
+        }
+        Ok(())
+    }
+
+    /// Delivers messages and polls the engines until nothing moves any more.
+    pub fn run(&mut self) -> eyre::Result<()> {
+        loop {
+            if let Some((from, to, payload)) = self.queue.pop_front() {
+                if self.down.contains(&from) || self.down.contains(&to) {
+                    continue;
+                }
+                let step = {
+                    let node = self.node(&to)?;
+                    node.engine.handle_message(&node.ctx, &from, &payload)?
+                };
+                self.apply_step(&to, step)?;
+                continue;
+            }
+            if !self.poll_all()? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Height of the node's last committed block.
+    pub fn height(&self, id: &PeerId) -> i64 {
+        self.nodes.get(id).map_or(0, |node| node.ctx.height)
+    }
+
+    /// Block the node committed at `height`.
+    pub fn block(&self, id: &PeerId, height: i64) -> eyre::Result<Option<Block>> {
+        match self.nodes.get(id) {
+            Some(node) => Ok(node.ctx.store.load_block(height)?),
+            None => Ok(None),
+        }
+    }
+
+    fn node(&mut self, id: &PeerId) -> eyre::Result<&mut MemNode> {
+        self.nodes.get_mut(id).ok_or_else(|| eyre::eyre!("unknown node {}", id))
+    }
+
+    // Polls every engine once, returns whether any of them made progress.
+    fn poll_all(&mut self) -> eyre::Result<bool> {
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let mut progress = false;
+        for id in self.node_ids() {
+            if self.down.contains(&id) {
+                continue;
+            }
+            let poll = self.node(&id)?.engine.poll_step(&mut cx);
+            if let Poll::Ready(step) = poll {
+                self.apply_step(&id, step?)?;
+                progress = true;
+            }
+        }
+        Ok(progress)
+    }
+
+    fn apply_step(&mut self, id: &PeerId, step: Step) -> eyre::Result<()> {
+        for message in step.messages {
+            match message.target {
+                Target::All => {
+                    for to in self.nodes.keys().filter(|to| *to != id) {
+                        self.queue.push_back((id.clone(), to.clone(), message.payload.clone()));
+                    }
+                }
+                Target::AllExcept(excluded) => {
+                    for to in self.nodes.keys().filter(|to| *to != id && **to != excluded) {
+                        self.queue.push_back((id.clone(), to.clone(), message.payload.clone()));
+                    }
+                }
+                Target::Peer(to) => self.queue.push_back((id.clone(), to, message.payload)),
+            }
+        }
+
+        for (from, err) in step.faults {
+            log::warn!("node {} dropped a message from {}: {}", id, from, err);
+        }
+        let node = self.node(id)?;
+        for block in step.committed {
+            if block.get_height() != node.ctx.next_height() {
+                eyre::bail!("node {} committed block {} at height {}", id, block.get_height(), node.ctx.height);
+            }
+            node.ctx.store.save_block(&block)?;
+            node.ctx.height = block.get_height();
+            node.ctx.last_block_hash = block.get_hash();
+            node.engine.on_commit(&node.ctx, &block)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for MemNetwork {
+    fn default() -> Self {
+        Self::new()
+    }
+}
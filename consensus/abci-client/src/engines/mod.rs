@@ -1,2 +1,64 @@
 pub mod pow;
-pub mod hbbft;
\ No newline at end of file
+pub mod bft;
+pub mod hbbft;
+pub mod poa;
+pub mod raft;
+mod consensus;
+mod instant;
+mod memnet;
+
+pub use consensus::*;
+pub use instant::*;
+pub use memnet::*;
+pub use self::hbbft::*;
+pub use poa::*;
+
+use crate::bft::TendermintEngine;
+use crate::pow::{Miner, PowEngine, Retarget};
+use crate::raft::{RaftEngine, SledRaftStorage};
+use crate::{EngineKind, Genesis, NodeConfig, NodeKey, PrivValidatorKey};
+
+/// Builds the consensus engine selected by `consensus.engine`.
+pub fn consensus_engine(config: &NodeConfig, genesis: &Genesis, node_key: &NodeKey) -> eyre::Result<Box<dyn ConsensusEngine>> {
+    let engine: Box<dyn ConsensusEngine> = match config.consensus.engine {
+        EngineKind::Pow => Box::new(PowEngine::new(
+            Retarget::new(
+                config.consensus.difficulty,
+                config.target_block_time(),
+                config.consensus.retarget_window,
+            ),
+            Miner::new(config.consensus.mining_threads),
+            config.consensus.confirmation_depth,
+        )),
+        EngineKind::Instant => Box::new(InstantEngine),
+        EngineKind::Hbbft => {
+            let validators = genesis
+                .hbbft
+                .as_ref()
+                .ok_or_else(|| eyre::eyre!("the genesis file has no hbbft validators, run `pow-node hbbft-keys` first"))?;
+            let key_share = HbbftKeyShare::load(config.hbbft_key_file())?;
+            Box::new(HbbftEngine::new(node_key.id(), key_share, validators)?)
+        }
+        EngineKind::Poa => {
+            let authorities = genesis
+                .poa
+                .clone()
+                .ok_or_else(|| eyre::eyre!("the genesis file has no poa authorities, run `pow-node poa-authorities` first"))?;
+            Box::new(PoaEngine::new(node_key.clone(), authorities)?)
+        }
+        EngineKind::Tendermint => {
+            let key = PrivValidatorKey::load(config.priv_validator_key_file())?;
+            Box::new(TendermintEngine::new(key, config.tendermint_timeouts()))
+        }
+        EngineKind::Raft => {
+            let storage = SledRaftStorage::new(config.raft_dir())?;
+            Box::new(RaftEngine::new(
+                node_key.id(),
+                config.consensus.raft_members.clone(),
+                config.raft_timeouts(),
+                Box::new(storage),
+            )?)
+        }
+    };
+    Ok(engine)
+}
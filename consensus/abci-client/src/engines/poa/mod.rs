@@ -0,0 +1,3 @@
+mod poa;
+
+pub use self::poa::*;
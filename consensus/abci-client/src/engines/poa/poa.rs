@@ -0,0 +1,296 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::time::{sleep, Sleep};
+
+use crate::error::PoaError;
+use crate::utils::{deserialize, serialize};
+use crate::{recent_headers, validate_block_body, validate_timestamp, MEDIAN_TIME_SPAN};
+use crate::{Block, ChainContext, ConsensusEngine, NodeKey, PeerId, Step};
+
+/// Default length of a turn, in milliseconds.
+pub const SLOT_MS: u64 = 2_000;
+
+/// Authorities of the PoA engine, listed in the genesis file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PoaAuthorities {
+    /// Length of a turn, in milliseconds.
+    pub slot_ms: u64,
+    /// Authorities in turn order.
+    pub authorities: Vec<PoaAuthority>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PoaAuthority {
+    /// Hex address of the node key, the node id.
+    pub address: String,
+    /// Public key of the node key.
+    #[serde(with = "hex::serde")]
+    pub pub_key: Vec<u8>,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Messages PoA nodes exchange.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum PoaMessage {
+    /// A block the sender proposed in its turn.
+    Block(Block),
+}
+
+/// Proof of authority, round-robin: time is cut in slots of `slot_ms`, and slot `s`
+/// belongs to authority `s % n`. The owner of a slot signs a block with its node key
+/// and commits it right away; a slot whose owner is offline simply passes, so the
+/// next authority takes over once the slot is over.
+pub struct PoaEngine {
+    node_key: NodeKey,
+    authorities: PoaAuthorities,
+    // slot the clock is in, moved forward by the timer in `poll_step`
+    slot: u64,
+    timer: Option<Pin<Box<Sleep>>>,
+}
+
+impl PoaEngine {
+    pub fn new(node_key: NodeKey, authorities: PoaAuthorities) -> eyre::Result<Self> {
+        if authorities.authorities.is_empty() {
+            eyre::bail!("the genesis file lists no poa authorities");
+        }
+        if authorities.slot_ms == 0 {
+            eyre::bail!("poa slot_ms must be greater than 0");
+        }
+        if !authorities.authorities.iter().any(|authority| authority.address == node_key.id()) {
+            log::info!("node {} is not a poa authority, it only follows the chain", node_key.id());
+        }
+        Ok(Self {
+            node_key,
+            authorities,
+            slot: 0,
+            timer: None,
+        })
+    }
+
+    fn slot_of(&self, timestamp: i64) -> u64 {
+        (timestamp.max(0) as u64) / (self.authorities.slot_ms * 1_000_000)
+    }
+
+    fn slot_start(&self, slot: u64) -> i64 {
+        (slot * self.authorities.slot_ms * 1_000_000) as i64
+    }
+
+    /// Authority whose turn it is in `slot`.
+    pub fn authority_of(&self, slot: u64) -> &PoaAuthority {
+        let authorities = &self.authorities.authorities;
+        &authorities[(slot % authorities.len() as u64) as usize]
+    }
+
+    // slot of the tip, 0 before the first block
+    fn tip_slot(&self, ctx: &ChainContext) -> eyre::Result<u64> {
+        match ctx.store.load_block(ctx.height)? {
+            Some(tip) => Ok(self.slot_of(tip.get_header().timestamp)),
+            None => Ok(0),
+        }
+    }
+
+    // (Re)arms the timer for the start of the slot after the current one.
+    fn schedule_next_slot(&mut self) {
+        let now = now();
+        self.slot = self.slot_of(now);
+        let next = self.slot_start(self.slot + 1);
+        let wait = Duration::from_nanos((next - now).max(0) as u64);
+        self.timer = Some(Box::pin(sleep(wait)));
+    }
+}
+
+impl ConsensusEngine for PoaEngine {
+    fn name(&self) -> &'static str {
+        "poa"
+    }
+
+    fn start(&mut self, _ctx: &ChainContext) -> eyre::Result<Step> {
+        log::info!(
+            "poa node {} starting with {} authorities, {}ms slots",
+            self.node_key.id(), self.authorities.authorities.len(), self.authorities.slot_ms,
+        );
+        self.schedule_next_slot();
+        Ok(Step::default())
+    }
+
+    /// Only in our own slot, once per slot.
+    fn can_propose(&self, ctx: &ChainContext) -> bool {
+        let tip_slot = match self.tip_slot(ctx) {
+            Ok(slot) => slot,
+            Err(err) => {
+                log::warn!("failed to load the tip block: {}", err);
+                return false;
+            }
+        };
+        self.authority_of(self.slot).address == self.node_key.id() && self.slot > tip_slot
+    }
+
+    fn propose(&mut self, _ctx: &ChainContext, mut block: Block) -> eyre::Result<Step> {
+        // the slot may have ended while the block was built, keep it in ours
+        let start = self.slot_start(self.slot);
+        let end = self.slot_start(self.slot + 1) - 1;
+        let timestamp = block.get_header().timestamp.max(start).min(end);
+        block.set_timestamp(timestamp);
+        block.sign(&self.node_key);
+
+        log::info!("proposing block {} in slot {}", block.get_height(), self.slot);
+        let mut step = Step::broadcast(serialize(&PoaMessage::Block(block.clone()))?);
+        step.committed.push(block);
+        Ok(step)
+    }
+
+    /// Checks the hash and txs against the header, the app hash against our tip's, the
+    /// signature, that the block is in a slot of its proposer, that the slot comes after
+    /// the parent's and has started, and that its time comes after the median time of
+    /// the blocks before it.
+    fn validate_block(&self, ctx: &ChainContext, block: &Block) -> eyre::Result<()> {
+        // the signature only covers the block hash, which has to be the header's
+        validate_block_body(block, &ctx.state.block_params)?;
+
+        let header = block.get_header();
+        // blocks are committed right away, so they carry the app hash of the block before
+        if header.app_hash != ctx.last_app_hash {
+            return Err(PoaError::AppHashMismatch { height: header.height }.into());
+        }
+        let proposer = hex::encode(&header.proposer);
+        let authority = self
+            .authorities
+            .authorities
+            .iter()
+            .find(|authority| authority.address == proposer)
+            .ok_or_else(|| PoaError::UnknownProposer { height: header.height, proposer: proposer.clone() })?;
+        if !block.verify_signature(&authority.pub_key) {
+            return Err(PoaError::InvalidSignature { height: header.height, proposer }.into());
+        }
+
+        let slot = self.slot_of(header.timestamp);
+        let expected = &self.authority_of(slot).address;
+        if *expected != proposer {
+            return Err(PoaError::WrongTurn { height: header.height, slot, proposer, expected: expected.clone() }.into());
+        }
+        if slot > self.slot_of(now()) {
+            return Err(PoaError::FutureSlot { height: header.height, slot }.into());
+        }
+        if let Some(parent) = ctx.store.load_block(header.height - 1)? {
+            let parent_slot = self.slot_of(parent.get_header().timestamp);
+            if slot <= parent_slot {
+                return Err(PoaError::SlotNotIncreasing { height: header.height, slot, parent_slot }.into());
+            }
+        }
+        let recent = recent_headers(ctx.store.as_ref(), header.height, MEDIAN_TIME_SPAN)?;
+        validate_timestamp(header, &recent)?;
+        Ok(())
+    }
+
+    fn handle_message(&mut self, ctx: &ChainContext, from: &PeerId, payload: &[u8]) -> eyre::Result<Step> {
+        let PoaMessage::Block(block) = deserialize(payload)?;
+
+        // only blocks extending our tip for now
+        if block.get_height() != ctx.next_height() || block.get_header().prev_hash != ctx.last_block_hash {
+            log::debug!("ignoring block {} from {}, it does not extend our tip", block.get_height(), from);
+            return Ok(Step::default());
+        }
+        self.validate_block(ctx, &block)?;
+        let mut step = Step::relay(from, payload.to_vec());
+        step.committed.push(block);
+        Ok(step)
+    }
+
+    /// Wakes the node up at every slot, so the authority whose turn begins proposes.
+    fn poll_step(&mut self, cx: &mut Context<'_>) -> Poll<eyre::Result<Step>> {
+        let timer = match &mut self.timer {
+            Some(timer) => timer,
+            None => return Poll::Pending,
+        };
+        match timer.as_mut().poll(cx) {
+            Poll::Ready(()) => {
+                self.schedule_next_slot();
+                Poll::Ready(Ok(Step::default()))
+            }
+            Poll::Pending => Poll::Pending,
+        }
+    }
+}
+
+// unix time in nanoseconds
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards").as_nanos() as i64
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::error::BlockError;
+    use crate::{BlockParamsRecord, ChainState, MemBlockStore};
+
+    fn engine(node_key: &NodeKey) -> PoaEngine {
+        let authority = PoaAuthority {
+            address: node_key.id(),
+            pub_key: node_key.public_key().to_vec(),
+            name: String::new(),
+        };
+        PoaEngine::new(node_key.clone(), PoaAuthorities { slot_ms: SLOT_MS, authorities: vec![authority] }).unwrap()
+    }
+
+    fn context() -> ChainContext {
+        ChainContext {
+            store: Arc::new(MemBlockStore::new()),
+            state: ChainState {
+                initial_height: 1,
+                block_params: BlockParamsRecord { max_bytes: -1, max_gas: -1 },
+                ..Default::default()
+            },
+            height: 0,
+            last_block_hash: vec![],
+            last_app_hash: b"app hash".to_vec(),
+        }
+    }
+
+    fn signed_block(node_key: &NodeKey, app_hash: &[u8], txs: Vec<Vec<u8>>) -> Block {
+        let mut block = Block::new(1, vec![], app_hash.to_vec(), now(), 0, txs);
+        block.sign(node_key);
+        block
+    }
+
+    #[test]
+    fn accepts_a_block_signed_by_the_authority() {
+        let node_key = NodeKey::generate();
+        let ctx = context();
+        let block = signed_block(&node_key, &ctx.last_app_hash, vec![b"tx".to_vec()]);
+        engine(&node_key).validate_block(&ctx, &block).unwrap();
+    }
+
+    #[test]
+    fn rejects_a_signature_copied_onto_other_txs() {
+        let node_key = NodeKey::generate();
+        let ctx = context();
+        let signed = signed_block(&node_key, &ctx.last_app_hash, vec![b"tx".to_vec()]);
+
+        // a forged header with the hash and signature of the authority's real block
+        let mut forged = Block::new(1, vec![], ctx.last_app_hash.clone(), now(), 0, vec![b"forged tx".to_vec()]);
+        forged.set_proposer(node_key.address());
+        let mut json = serde_json::to_value(&forged).unwrap();
+        json["hash"] = serde_json::json!(signed.get_hash());
+        json["signature"] = serde_json::json!(signed.get_signature());
+        let forged: Block = serde_json::from_value(json).unwrap();
+
+        let err = engine(&node_key).validate_block(&ctx, &forged).unwrap_err();
+        assert!(matches!(err.downcast_ref::<BlockError>(), Some(BlockError::HashMismatch { .. })));
+    }
+
+    #[test]
+    fn rejects_a_block_on_another_app_hash() {
+        let node_key = NodeKey::generate();
+        let ctx = context();
+        let block = signed_block(&node_key, b"other app hash", vec![]);
+        let err = engine(&node_key).validate_block(&ctx, &block).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PoaError>(), Some(PoaError::AppHashMismatch { height: 1 })));
+    }
+}
@@ -0,0 +1,150 @@
+use std::time::Duration;
+
+use crate::BlockHeader;
+
+/// Default difficulty of the first blocks, in leading zero bits.
+pub const DIFFICULTY: usize = 10;
+/// Default number of previous blocks the block time is averaged over.
+pub const RETARGET_WINDOW: usize = 30;
+/// Lowest difficulty a block can have, in leading zero bits.
+pub const MIN_BITS: usize = 1;
+/// Highest difficulty a block can have, in leading zero bits.
+pub const MAX_BITS: usize = 255;
+
+// fraction bits of the fixed point work ratio of the retarget
+const WORK_SHIFT: u32 = 32;
+// 2^0.5 and 2^-0.5 in that fixed point, rounded down
+const SQRT_2: u128 = 6_074_000_999;
+const SQRT_1_2: u128 = 3_037_000_499;
+// longest target block time the retarget works with, so the products fit in a u128
+const MAX_TARGET_NANOS: u128 = 1 << 58;
+
+/// Per-block difficulty retargeting with a linearly weighted moving average (LWMA)
+/// of the recent solve times, moving the block time toward `target_block_time`.
+///
+/// The difficulty is the number of leading zero bits of the block hash, so the expected
+/// work of a block is `2^bits` hashes and a retarget can only double or halve it. To keep
+/// the chain from oscillating, a single block changes the difficulty by at most one bit.
+#[derive(Debug, Clone)]
+pub struct Retarget {
+    // difficulty until there are enough blocks to average over
+    initial_bits: usize,
+    target_block_time: Duration,
+    window: usize,
+}
+
+impl Retarget {
+    pub fn new(initial_bits: usize, target_block_time: Duration, window: usize) -> Self {
+        Self {
+            initial_bits,
+            target_block_time,
+            window: window.max(2),
+        }
+    }
+
+    /// Number of previous headers `next_bits` looks at.
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// Expected difficulty of the block following `recent`, the headers of the previous
+    /// blocks in height order (at most `window` of them are used).
+    pub fn next_bits(&self, recent: &[BlockHeader]) -> usize {
+        let recent = &recent[recent.len().saturating_sub(self.window)..];
+        let last = match recent.last() {
+            Some(last) if recent.len() >= 2 => last,
+            _ => return self.initial_bits,
+        };
+
+        // integer arithmetic only: every node has to come up with the same bits
+        let target = self.target_block_time.as_nanos().clamp(1, MAX_TARGET_NANOS);
+
+        // solve times weighted 1..n, the newest block counting the most; each one
+        // clamped to [1ns, 6T] so a single bad timestamp cannot swing the average.
+        // The work of a block is counted relative to the last one's, in units of
+        // 2^-WORK_SHIFT, and blocks more than WORK_SHIFT bits apart are clamped.
+        let mut weighted_time = 0u128;
+        let mut weights = 0u128;
+        let mut work = 0u128;
+        for (i, pair) in recent.windows(2).enumerate() {
+            let solve_time = (pair[1].timestamp as i128 - pair[0].timestamp as i128).clamp(1, 6 * target as i128) as u128;
+            let weight = (i + 1) as u128;
+            weighted_time += solve_time * weight;
+            weights += weight;
+            let shift = (pair[1].bits as i64 - last.bits as i64).clamp(-(WORK_SHIFT as i64), WORK_SHIFT as i64);
+            work += 1u128 << (WORK_SHIFT as i64 + shift);
+        }
+        let lwma = weighted_time / weights;
+        let avg_work = work / (recent.len() - 1) as u128;
+
+        // next difficulty = average work * T / LWMA, relative to the last block's; it
+        // moves a bit once it is more than half a bit away, above 2^0.5 or below 2^-0.5
+        let ratio = avg_work * target / lwma;
+        let bits = if ratio > SQRT_2 {
+            last.bits + 1
+        } else if ratio <= SQRT_1_2 {
+            last.bits.saturating_sub(1)
+        } else {
+            last.bits
+        };
+        bits.max(MIN_BITS).min(MAX_BITS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const T: i64 = 1_000_000_000;
+
+    fn retarget() -> Retarget {
+        Retarget::new(DIFFICULTY, Duration::from_nanos(T as u64), RETARGET_WINDOW)
+    }
+
+    // `count` headers at `bits`, each `solve_time` after the one before
+    fn headers(count: usize, bits: usize, solve_time: i64) -> Vec<BlockHeader> {
+        (0..count)
+            .map(|i| BlockHeader { height: i as i64 + 1, timestamp: i as i64 * solve_time, bits, ..Default::default() })
+            .collect()
+    }
+
+    #[test]
+    fn first_blocks_use_the_initial_bits() {
+        assert_eq!(retarget().next_bits(&[]), DIFFICULTY);
+        assert_eq!(retarget().next_bits(&headers(1, 20, T)), DIFFICULTY);
+    }
+
+    #[test]
+    fn steady_at_the_target_block_time() {
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, 12, T)), 12);
+        // a block time within half a bit of the target keeps the difficulty
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, 12, T * 13 / 10)), 12);
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, 12, T * 8 / 10)), 12);
+    }
+
+    #[test]
+    fn moves_one_bit_per_block() {
+        // far too fast or too slow still moves a single bit
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, 12, T / 100)), 13);
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, 12, T * 100)), 11);
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, 12, T / 2)), 13);
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, 12, T * 2)), 11);
+    }
+
+    #[test]
+    fn clamps_solve_times() {
+        // one block time going backwards counts as 1ns, one far in the future as 6T,
+        // neither enough to move the difficulty on its own
+        let mut recent = headers(RETARGET_WINDOW, 12, T);
+        recent.last_mut().unwrap().timestamp = 0;
+        assert_eq!(retarget().next_bits(&recent), 12);
+        recent.last_mut().unwrap().timestamp = i64::MAX;
+        assert_eq!(retarget().next_bits(&recent), 12);
+    }
+
+    #[test]
+    fn stays_within_the_bit_bounds() {
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, MIN_BITS, T * 100)), MIN_BITS);
+        assert_eq!(retarget().next_bits(&headers(RETARGET_WINDOW, MAX_BITS, T / 100)), MAX_BITS);
+    }
+}
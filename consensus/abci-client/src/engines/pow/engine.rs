@@ -0,0 +1,516 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::{BlockError, PowError};
+use crate::pow::{BlockTree, Miner, MiningJob, Retarget, MAX_BITS, MIN_BITS};
+use crate::utils::{deserialize, serialize};
+use crate::{next_timestamp, validate_block_body, validate_timestamp, MEDIAN_TIME_SPAN};
+use crate::{Block, BlockHeader, ChainContext, ConsensusEngine, OrphanPool, OutMessage, PeerId, Step, Target};
+
+/// Messages PoW nodes exchange.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum PowMessage {
+    /// A block the sender mined, or one a peer asked for.
+    Block(Block),
+    /// Asks for the block with this hash, the missing parent of an orphan.
+    GetBlock(Vec<u8>),
+}
+
+/// Proof of work on the heaviest chain. Mined blocks, local or from peers, go into a
+/// block tree, and a block of the chain with the most work is committed once
+/// `confirmation_depth` blocks are on top of it, as the app cannot roll a block back.
+/// Blocks arriving before their parent wait in an orphan pool while the parent is asked
+/// for. Orphans on top of a block that just got final wait for the app to execute it,
+/// as the app hash they carry is only known then.
+pub struct PowEngine {
+    retarget: Retarget,
+    miner: Miner,
+    tree: BlockTree,
+    orphans: OrphanPool,
+    // block being mined, with the job searching for its nonce
+    mining: Option<(Block, MiningJob)>,
+    // blocks whose orphans are attached once the app caught up with the final blocks
+    deferred: Vec<Vec<u8>>,
+    // chain the app last committed, to attach the deferred orphans on
+    committed: Option<ChainContext>,
+}
+
+impl PowEngine {
+    pub fn new(retarget: Retarget, miner: Miner, confirmation_depth: u64) -> Self {
+        Self {
+            retarget,
+            miner,
+            tree: BlockTree::new(confirmation_depth),
+            orphans: OrphanPool::default(),
+            mining: None,
+            deferred: vec![],
+            committed: None,
+        }
+    }
+
+    /// Difficulty the block at `height` on top of `parent` must have, retargeted from
+    /// the blocks of its branch.
+    pub fn expected_bits(&self, ctx: &ChainContext, height: i64, parent: &[u8]) -> eyre::Result<usize> {
+        let recent = self.ancestors(ctx, height, parent, self.retarget.window())?;
+        Ok(self.retarget.next_bits(&recent))
+    }
+
+    // Headers of up to `count` blocks below a block at `height` on top of `parent`, oldest
+    // first: from the block tree while the branch is not final, then from the block store.
+    fn ancestors(&self, ctx: &ChainContext, height: i64, parent: &[u8], count: usize) -> eyre::Result<Vec<BlockHeader>> {
+        if height < ctx.state.initial_height {
+            return Err(BlockError::HeightMismatch { height, expected: ctx.state.initial_height }.into());
+        }
+        // the first block has no parent
+        if height == ctx.state.initial_height && !parent.is_empty() {
+            return Err(BlockError::UnknownParent { height }.into());
+        }
+
+        let from = (height - count as i64).max(ctx.state.initial_height);
+        let mut headers = vec![];
+        let mut hash = parent.to_vec();
+        for h in (from..height).rev() {
+            let header = match self.tree.get(&hash) {
+                Some(block) => block.get_header().clone(),
+                None => ctx
+                    .store
+                    .load_block_by_hash(&hash)?
+                    .ok_or(BlockError::UnknownParent { height: h + 1 })?
+                    .get_header()
+                    .clone(),
+            };
+            if header.height != h {
+                return Err(BlockError::HeightMismatch { height: h + 1, expected: header.height + 1 }.into());
+            }
+            hash = header.prev_hash.clone();
+            headers.push(header);
+        }
+        headers.reverse();
+        Ok(headers)
+    }
+
+    // App hash after the final block at `height`, the app hash before the first block
+    // for heights below it.
+    fn app_hash_after(ctx: &ChainContext, height: i64) -> eyre::Result<Vec<u8>> {
+        if height == ctx.height {
+            return Ok(ctx.last_app_hash.clone());
+        }
+        if height < ctx.state.initial_height {
+            return Ok(ctx.state.genesis_app_hash.clone());
+        }
+        let results = ctx
+            .store
+            .load_results(height)?
+            .ok_or_else(|| eyre::eyre!("no app hash recorded for block {}", height))?;
+        Ok(results.app_hash)
+    }
+
+    // height of a block on top of one at `parent_height`
+    fn next_height(ctx: &ChainContext, parent_height: i64) -> i64 {
+        if parent_height == 0 {
+            ctx.state.initial_height
+        } else {
+            parent_height + 1
+        }
+    }
+
+    // Lowest difficulty a block at `height` can have: the retarget moves the difficulty
+    // by at most one bit per block from the head of the best chain.
+    fn min_bits(&self, ctx: &ChainContext, height: i64) -> eyre::Result<usize> {
+        let (tip_height, tip_hash) = self.tree.tip();
+        let tip = match self.tree.get(&tip_hash) {
+            Some(block) => Some(block.get_header().bits),
+            None => ctx.store.load_block_by_hash(&tip_hash)?.map(|block| block.get_header().bits),
+        };
+        let steps = (height - tip_height).max(0) as usize;
+        Ok(tip.map_or(MIN_BITS, |bits| bits.saturating_sub(steps).max(MIN_BITS)))
+    }
+
+    fn handle_block(&mut self, ctx: &ChainContext, from: &PeerId, block: Block, payload: &[u8]) -> eyre::Result<Step> {
+        let hash = block.get_hash();
+        // a block we already have ends its gossip here
+        if self.tree.contains(&hash) || self.orphans.contains(&hash) {
+            return Ok(Step::default());
+        }
+        if !self.tree.contains(&block.get_header().prev_hash) {
+            return self.add_orphan(ctx, from, block, payload.len());
+        }
+        self.validate_block(ctx, &block)?;
+
+        let mut step = Step::relay(from, payload.to_vec());
+        step.extend(self.add_block(block));
+        step.extend(self.attach_orphans(ctx, hash)?);
+        Ok(step)
+    }
+
+    // Keeps a block with an unknown parent until the parent arrives, and asks the peer
+    // for the first block missing below it. Only what can be checked without the parent
+    // is checked here, enough to make filling the pool cost work.
+    fn add_orphan(&mut self, ctx: &ChainContext, from: &PeerId, block: Block, size: usize) -> eyre::Result<Step> {
+        let header = block.get_header();
+        // forks below the last final block are never followed
+        if header.height <= self.tree.root_height() + 1 {
+            log::debug!("ignoring block {} from {}, it forks below the last final block", header.height, from);
+            return Ok(Step::default());
+        }
+        validate_block_body(&block, &ctx.state.block_params)?;
+        if header.bits > MAX_BITS {
+            return Err(PowError::DifficultyTooHigh { height: header.height, bits: header.bits, max: MAX_BITS }.into());
+        }
+        let min = self.min_bits(ctx, header.height)?;
+        if header.bits < min {
+            return Err(PowError::DifficultyTooLow { height: header.height, bits: header.bits, min }.into());
+        }
+        if !header.meets_difficulty() {
+            return Err(PowError::InsufficientWork { height: header.height, nonce: header.nonce, bits: header.bits }.into());
+        }
+
+        let height = header.height;
+        let hash = block.get_hash();
+        if !self.orphans.insert(block, from.clone(), size, Instant::now()) {
+            log::debug!("ignoring block {} from {}, it does not fit in the orphan pool", height, from);
+            return Ok(Step::default());
+        }
+        let missing = self.orphans.missing_ancestor(&hash);
+        log::debug!(
+            "block {} from {} is an orphan ({} waiting), asking for block {}",
+            height, from, self.orphans.len(), hex::encode(&missing),
+        );
+        Ok(Step {
+            messages: vec![OutMessage {
+                target: Target::Peer(from.clone()),
+                payload: serialize(&PowMessage::GetBlock(missing))?,
+            }],
+            ..Default::default()
+        })
+    }
+
+    // Attaches the orphans descending from a block just added to the tree. An orphan that
+    // turns out invalid is dropped along with its descendants. Once a block got final in
+    // the step, the rest waits in `deferred` for the app to execute it.
+    fn attach_orphans(&mut self, ctx: &ChainContext, parent: Vec<u8>) -> eyre::Result<Step> {
+        let mut step = Step::default();
+        let mut parents = vec![parent];
+        while let Some(parent) = parents.pop() {
+            if self.tree.root_height() > ctx.height {
+                self.deferred.push(parent);
+                self.deferred.append(&mut parents);
+                break;
+            }
+            for (block, from) in self.orphans.take_children(&parent) {
+                // a sibling attached before may have made the parent's branch final
+                if !self.tree.contains(&block.get_header().prev_hash) {
+                    log::debug!("dropping orphan block {} from {}, it forks below the last final block", block.get_height(), from);
+                    continue;
+                }
+                if let Err(err) = self.validate_block(ctx, &block) {
+                    log::warn!("dropping orphan block {} from {}: {}", block.get_height(), from, err);
+                    continue;
+                }
+                log::debug!("attaching orphan block {} from {}", block.get_height(), from);
+                parents.push(block.get_hash());
+                step.extend(Step::relay(&from, serialize(&PowMessage::Block(block.clone()))?));
+                step.extend(self.add_block(block));
+            }
+        }
+        Ok(step)
+    }
+
+    // Answers a peer missing a block with the block from the tree or the store.
+    fn handle_get_block(&self, ctx: &ChainContext, from: &PeerId, hash: &[u8]) -> eyre::Result<Step> {
+        let block = match self.tree.get(hash) {
+            Some(block) => Some(block.clone()),
+            None => ctx.store.load_block_by_hash(hash)?,
+        };
+        let block = match block {
+            Some(block) => block,
+            None => return Ok(Step::default()),
+        };
+        Ok(Step {
+            messages: vec![OutMessage {
+                target: Target::Peer(from.clone()),
+                payload: serialize(&PowMessage::Block(block))?,
+            }],
+            ..Default::default()
+        })
+    }
+
+    // Puts a checked block in the tree, and reports how the best chain moved.
+    fn add_block(&mut self, block: Block) -> Step {
+        let height = block.get_height();
+        let update = self.tree.insert(block);
+        if update.extended.is_empty() {
+            log::debug!("block {} is on a side branch, {} blocks wait to be final", height, self.tree.len());
+            return Step::default();
+        }
+        if !update.orphaned.is_empty() {
+            log::info!(
+                "reorg: {} blocks replaced by {} blocks of a heavier branch",
+                update.orphaned.len(), update.extended.len(),
+            );
+        }
+
+        // whatever we are mining no longer extends the best chain
+        self.cancel_proposal();
+        Step {
+            committed: update.finalized,
+            pending: update.extended,
+            orphaned: update.orphaned,
+            ..Default::default()
+        }
+    }
+}
+
+impl ConsensusEngine for PowEngine {
+    fn name(&self) -> &'static str {
+        "pow"
+    }
+
+    fn start(&mut self, ctx: &ChainContext) -> eyre::Result<Step> {
+        log::info!("mining on {} threads, blocks are final {} blocks deep", self.miner.threads(), self.tree.depth());
+        self.tree.reset(ctx.height, ctx.last_block_hash.clone());
+        self.orphans.clear();
+        self.deferred.clear();
+        self.committed = Some(ctx.clone());
+        Ok(Step::default())
+    }
+
+    fn can_propose(&self, _ctx: &ChainContext) -> bool {
+        self.mining.is_none()
+    }
+
+    /// Mines the block on top of the best chain rather than the last final block.
+    fn propose(&mut self, ctx: &ChainContext, mut block: Block) -> eyre::Result<Step> {
+        let (tip_height, tip_hash) = self.tree.tip();
+        let height = Self::next_height(ctx, tip_height);
+        let app_hash = Self::app_hash_after(ctx, height - self.app_hash_lag())?;
+        block.set_parent(height, tip_hash.clone(), app_hash);
+
+        let count = self.retarget.window().max(MEDIAN_TIME_SPAN);
+        let recent = self.ancestors(ctx, height, &tip_hash, count)?;
+        // a clock behind the chain's must not make our block invalid
+        block.set_timestamp(next_timestamp(block.get_header().timestamp, &recent));
+        let bits = self.retarget.next_bits(&recent);
+        block.set_bits(bits);
+
+        // 达到目标难度值，然后打包
+        log::debug!("mining block {} at {} bits", height, bits);
+        let job = self.miner.mine(block.get_header().pow_data(), bits);
+        self.mining = Some((block, job));
+
+        Ok(Step::default())
+    }
+
+    fn cancel_proposal(&mut self) -> bool {
+        match self.mining.take() {
+            Some((_, job)) => {
+                job.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks a block before anything is executed: it follows a known block at the right
+    /// height, its txs match the header and fit the block limits, its time is after the
+    /// median time of the blocks before it, it carries the difficulty the retarget expects
+    /// on its branch with a nonce meeting it, and the app hash of the block `app_hash_lag`
+    /// below it.
+    fn validate_block(&self, ctx: &ChainContext, block: &Block) -> eyre::Result<()> {
+        let header = block.get_header();
+        let count = self.retarget.window().max(MEDIAN_TIME_SPAN);
+        let recent = self.ancestors(ctx, header.height, &header.prev_hash, count)?;
+
+        validate_block_body(block, &ctx.state.block_params)?;
+        validate_timestamp(header, &recent)?;
+
+        let expected = self.retarget.next_bits(&recent);
+        if header.bits != expected {
+            return Err(PowError::DifficultyMismatch { height: header.height, bits: header.bits, expected }.into());
+        }
+        if !header.meets_difficulty() {
+            return Err(PowError::InsufficientWork { height: header.height, nonce: header.nonce, bits: header.bits }.into());
+        }
+        let app_hash_height = header.height - self.app_hash_lag();
+        if header.app_hash != Self::app_hash_after(ctx, app_hash_height)? {
+            return Err(PowError::AppHashMismatch { height: header.height, app_hash_height }.into());
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, ctx: &ChainContext, from: &PeerId, payload: &[u8]) -> eyre::Result<Step> {
+        match deserialize(payload)? {
+            PowMessage::Block(block) => self.handle_block(ctx, from, block, payload),
+            PowMessage::GetBlock(hash) => self.handle_get_block(ctx, from, &hash),
+        }
+    }
+
+    /// Attaches the orphans deferred until the app executed the blocks that got final,
+    /// and hands out the blocks we mined.
+    fn poll_step(&mut self, cx: &mut Context<'_>) -> Poll<eyre::Result<Step>> {
+        let caught_up = self.committed.clone().filter(|ctx| ctx.height >= self.tree.root_height());
+        if let Some(ctx) = caught_up.filter(|_| !self.deferred.is_empty()) {
+            let mut step = Step::default();
+            for parent in std::mem::take(&mut self.deferred) {
+                match self.attach_orphans(&ctx, parent) {
+                    Ok(attached) => step.extend(attached),
+                    Err(err) => return Poll::Ready(Err(err)),
+                }
+            }
+            return Poll::Ready(Ok(step));
+        }
+
+        let (block, job) = match &mut self.mining {
+            Some(mining) => mining,
+            None => return Poll::Pending,
+        };
+        let result = match Pin::new(&mut job.result).poll(cx) {
+            Poll::Ready(result) => result,
+            Poll::Pending => return Poll::Pending,
+        };
+        let height = block.get_height();
+        let (mut block, _) = self.mining.take().unwrap();
+
+        let mined = match result {
+            Ok(mined) => mined,
+            Err(_) => return Poll::Ready(Err(eyre::eyre!("mining block {} stopped without a nonce", height))),
+        };
+        log::info!(
+            "mined block {} with nonce {} in {:?}: {} hashes, {:.0} H/s",
+            height, mined.nonce, mined.elapsed, mined.hashes, mined.hashrate(),
+        );
+
+        block.set_nonce(mined.nonce);
+        debug_assert_eq!(block.get_hash(), mined.hash);
+
+        let mut step = Step::broadcast(serialize(&PowMessage::Block(block.clone()))?);
+        step.extend(self.add_block(block));
+        Poll::Ready(Ok(step))
+    }
+
+    fn tip(&self, _ctx: &ChainContext) -> (i64, Vec<u8>) {
+        self.tree.tip()
+    }
+
+    /// A block can only carry the app hash of a final block.
+    fn app_hash_lag(&self) -> i64 {
+        1 + self.tree.depth() as i64
+    }
+
+    fn on_commit(&mut self, ctx: &ChainContext, _block: &Block) -> eyre::Result<()> {
+        self.committed = Some(ctx.clone());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use futures::task::noop_waker_ref;
+
+    use super::*;
+    use crate::pow::RETARGET_WINDOW;
+    use crate::{BlockParamsRecord, BlockResults, ChainState, MemBlockStore};
+
+    fn engine() -> PowEngine {
+        PowEngine::new(Retarget::new(MIN_BITS, Duration::from_secs(1), RETARGET_WINDOW), Miner::new(1), 0)
+    }
+
+    fn context() -> ChainContext {
+        ChainContext {
+            store: Arc::new(MemBlockStore::new()),
+            state: ChainState {
+                initial_height: 1,
+                block_params: BlockParamsRecord { max_bytes: -1, max_gas: -1 },
+                ..Default::default()
+            },
+            height: 0,
+            last_block_hash: vec![],
+            last_app_hash: app_hash(0),
+        }
+    }
+
+    fn app_hash(height: i64) -> Vec<u8> {
+        format!("app hash {}", height).into_bytes()
+    }
+
+    // `count` blocks mined on top of each other from the first one
+    fn chain(count: i64) -> Vec<Block> {
+        let start = chrono::Utc::now().timestamp_nanos() - 60_000_000_000;
+        let mut blocks: Vec<Block> = vec![];
+        for height in 1..=count {
+            let parent = blocks.last().map(|block| block.get_hash()).unwrap_or_default();
+            let timestamp = start + height * 1_000_000_000;
+            let mut block = Block::new(height, parent, app_hash(height - 1), timestamp, MIN_BITS, vec![vec![height as u8]]);
+            while !block.get_header().meets_difficulty() {
+                block.set_nonce(block.get_header().nonce + 1);
+            }
+            blocks.push(block);
+        }
+        blocks
+    }
+
+    fn deliver(engine: &mut PowEngine, ctx: &ChainContext, block: &Block) -> eyre::Result<Step> {
+        let payload = serialize(&PowMessage::Block(block.clone())).unwrap();
+        engine.handle_message(ctx, &"peer".to_string(), &payload)
+    }
+
+    // what the driver does with a committed block, the app hash standing in for the app's
+    fn commit(engine: &mut PowEngine, ctx: &mut ChainContext, block: &Block) {
+        ctx.store.save_block(block).unwrap();
+        let results = BlockResults { height: block.get_height(), app_hash: app_hash(block.get_height()), ..Default::default() };
+        ctx.store.save_results(&results).unwrap();
+        ctx.height = block.get_height();
+        ctx.last_block_hash = block.get_hash();
+        ctx.last_app_hash = results.app_hash;
+        engine.on_commit(ctx, block).unwrap();
+    }
+
+    #[test]
+    fn attaches_a_chain_of_orphans_once_their_parent_arrives() {
+        let mut engine = engine();
+        let mut ctx = context();
+        engine.start(&ctx).unwrap();
+        let blocks = chain(4);
+
+        for block in &blocks[1..] {
+            let step = deliver(&mut engine, &ctx, block).unwrap();
+            assert!(step.committed.is_empty());
+            assert_eq!(step.messages.len(), 1, "asks for the missing parent");
+        }
+
+        let mut committed = deliver(&mut engine, &ctx, &blocks[0]).unwrap().committed;
+        let mut cx = Context::from_waker(noop_waker_ref());
+        while let Some(block) = committed.pop() {
+            assert!(committed.is_empty());
+            commit(&mut engine, &mut ctx, &block);
+            if let Poll::Ready(step) = engine.poll_step(&mut cx) {
+                committed = step.unwrap().committed;
+            }
+        }
+
+        assert_eq!(ctx.height, 4);
+        for block in &blocks {
+            assert_eq!(ctx.store.load_block(block.get_height()).unwrap().unwrap().get_hash(), block.get_hash());
+        }
+        assert!(engine.orphans.is_empty());
+    }
+
+    #[test]
+    fn rejects_orphans_above_the_max_difficulty() {
+        let mut engine = engine();
+        let ctx = context();
+        engine.start(&ctx).unwrap();
+
+        let mut block = chain(2).pop().unwrap();
+        block.set_bits(MAX_BITS + 45);
+        let err = deliver(&mut engine, &ctx, &block).unwrap_err();
+        assert!(matches!(err.downcast_ref::<PowError>(), Some(PowError::DifficultyTooHigh { height: 2, .. })));
+    }
+}
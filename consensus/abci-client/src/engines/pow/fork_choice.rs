@@ -0,0 +1,281 @@
+use std::collections::{HashMap, HashSet};
+use std::ops::Shl;
+
+use bigint::U256;
+
+use crate::Block;
+
+/// Default number of blocks a PoW block must be buried under before it is final.
+pub const CONFIRMATION_DEPTH: u64 = 0;
+
+/// Work a block at `bits` difficulty proves, the expected number of hashes: `2^bits`.
+pub fn block_work(bits: usize) -> U256 {
+    U256::from(1u64).shl(bits)
+}
+
+struct TreeNode {
+    block: Block,
+    // work of the chain from genesis up to and including the block
+    work: U256,
+}
+
+/// How the best chain changed after a block was added to the tree.
+#[derive(Debug, Default)]
+pub struct TreeUpdate {
+    /// Blocks that left the best chain in a reorg, in height order.
+    pub orphaned: Vec<Block>,
+    /// Blocks that joined the best chain, in height order.
+    pub extended: Vec<Block>,
+    /// Blocks of the best chain now buried deep enough to be final, in height order.
+    pub finalized: Vec<Block>,
+}
+
+/// The blocks above the last final block, every branch of them. The branch with the most
+/// work is the best chain, and its blocks become final once `depth` blocks are on top of
+/// them; the branches that do not descend from a final block are dropped.
+pub struct BlockTree {
+    depth: i64,
+    // the last final block, which every block of the tree descends from
+    root_height: i64,
+    root_hash: Vec<u8>,
+    root_work: U256,
+    nodes: HashMap<Vec<u8>, TreeNode>,
+    // head of the best chain, the root while the tree is empty
+    best: Vec<u8>,
+}
+
+impl BlockTree {
+    pub fn new(depth: u64) -> Self {
+        Self {
+            depth: depth as i64,
+            root_height: 0,
+            root_hash: vec![],
+            root_work: U256::zero(),
+            nodes: HashMap::new(),
+            best: vec![],
+        }
+    }
+
+    /// Drops every block and starts over from a final block.
+    pub fn reset(&mut self, height: i64, hash: Vec<u8>) {
+        self.root_height = height;
+        self.root_hash = hash.clone();
+        self.root_work = U256::zero();
+        self.nodes.clear();
+        self.best = hash;
+    }
+
+    /// Height of the last final block.
+    pub fn root_height(&self) -> i64 {
+        self.root_height
+    }
+
+    pub fn depth(&self) -> u64 {
+        self.depth as u64
+    }
+
+    /// Whether `hash` is the last final block or a block above it.
+    pub fn contains(&self, hash: &[u8]) -> bool {
+        hash == self.root_hash.as_slice() || self.nodes.contains_key(hash)
+    }
+
+    /// A block above the last final block.
+    pub fn get(&self, hash: &[u8]) -> Option<&Block> {
+        self.nodes.get(hash).map(|node| &node.block)
+    }
+
+    /// Height of a block of the tree, the last final block included.
+    pub fn height_of(&self, hash: &[u8]) -> Option<i64> {
+        if hash == self.root_hash.as_slice() {
+            return Some(self.root_height);
+        }
+        self.get(hash).map(|block| block.get_height())
+    }
+
+    /// Height and hash of the head of the best chain.
+    pub fn tip(&self) -> (i64, Vec<u8>) {
+        (self.height_of(&self.best).unwrap_or(self.root_height), self.best.clone())
+    }
+
+    /// Number of blocks waiting to be final or orphaned.
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+
+    /// Adds a block whose parent is in the tree. The best chain switches to the block if
+    /// it carries more work, a tie keeps the chain that was seen first.
+    pub fn insert(&mut self, block: Block) -> TreeUpdate {
+        let parent = block.get_header().prev_hash.clone();
+        debug_assert!(self.contains(&parent));
+        let (work, overflow) = self.work(&parent).overflowing_add(block_work(block.get_header().bits));
+        let work = if overflow { U256::max_value() } else { work };
+
+        let hash = block.get_hash();
+        self.nodes.insert(hash.clone(), TreeNode { block, work });
+
+        let mut update = TreeUpdate::default();
+        if work <= self.work(&self.best) {
+            return update;
+        }
+
+        let old_branch = self.branch(&self.best);
+        let new_branch = self.branch(&hash);
+        let common = old_branch.iter().zip(&new_branch).take_while(|(old, new)| old == new).count();
+        update.orphaned = old_branch[common..].iter().map(|hash| self.nodes[hash].block.clone()).collect();
+        update.extended = new_branch[common..].iter().map(|hash| self.nodes[hash].block.clone()).collect();
+        self.best = hash;
+        update.finalized = self.finalize();
+        update
+    }
+
+    fn work(&self, hash: &[u8]) -> U256 {
+        if hash == self.root_hash.as_slice() {
+            return self.root_work;
+        }
+        self.nodes.get(hash).map_or(U256::zero(), |node| node.work)
+    }
+
+    // hashes of the blocks from just above the root up to `hash`, in height order
+    fn branch(&self, hash: &[u8]) -> Vec<Vec<u8>> {
+        let mut branch = vec![];
+        let mut hash = hash.to_vec();
+        while let Some(node) = self.nodes.get(&hash) {
+            let parent = node.block.get_header().prev_hash.clone();
+            branch.push(hash);
+            hash = parent;
+        }
+        branch.reverse();
+        branch
+    }
+
+    // Moves the root up the best chain to `depth` blocks below its head, and drops the
+    // branches that do not descend from the new root.
+    fn finalize(&mut self) -> Vec<Block> {
+        let (tip_height, _) = self.tip();
+        let final_count = (tip_height - self.depth - self.root_height).max(0) as usize;
+        if final_count == 0 {
+            return vec![];
+        }
+
+        let branch = self.branch(&self.best);
+        let mut finalized = vec![];
+        for hash in &branch[..final_count] {
+            let node = self.nodes.remove(hash).unwrap();
+            self.root_height = node.block.get_height();
+            self.root_hash = hash.clone();
+            self.root_work = node.work;
+            finalized.push(node.block);
+        }
+
+        // parents come before their children in height order
+        let mut heights: Vec<(i64, Vec<u8>)> = self.nodes.iter().map(|(hash, node)| (node.block.get_height(), hash.clone())).collect();
+        heights.sort();
+        let mut kept: HashSet<Vec<u8>> = HashSet::new();
+        kept.insert(self.root_hash.clone());
+        for (_, hash) in heights {
+            if kept.contains(&self.nodes[&hash].block.get_header().prev_hash) {
+                kept.insert(hash);
+            }
+        }
+        let before = self.nodes.len();
+        self.nodes.retain(|hash, _| kept.contains(hash));
+        if self.nodes.len() < before {
+            log::debug!("discarded {} blocks of orphaned branches", before - self.nodes.len());
+        }
+
+        finalized
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a block on `parent`, told apart from its siblings by `tag`
+    fn child(parent: &[u8], height: i64, bits: usize, tag: &str) -> Block {
+        Block::new(height, parent.to_vec(), vec![], height, bits, vec![tag.as_bytes().to_vec()])
+    }
+
+    fn tree(depth: u64) -> BlockTree {
+        let mut tree = BlockTree::new(depth);
+        tree.reset(0, b"genesis".to_vec());
+        tree
+    }
+
+    fn hashes(blocks: &[Block]) -> Vec<Vec<u8>> {
+        blocks.iter().map(|block| block.get_hash()).collect()
+    }
+
+    #[test]
+    fn extends_the_best_chain() {
+        let mut tree = tree(10);
+        let a1 = child(b"genesis", 1, 4, "a");
+        let a2 = child(&a1.get_hash(), 2, 4, "a");
+
+        assert_eq!(hashes(&tree.insert(a1.clone()).extended), hashes(&[a1]));
+        let update = tree.insert(a2.clone());
+        assert_eq!(hashes(&update.extended), hashes(&[a2.clone()]));
+        assert!(update.orphaned.is_empty() && update.finalized.is_empty());
+        assert_eq!(tree.tip(), (2, a2.get_hash()));
+    }
+
+    #[test]
+    fn reorgs_to_the_heavier_branch() {
+        let mut tree = tree(10);
+        let a1 = child(b"genesis", 1, 4, "a");
+        let a2 = child(&a1.get_hash(), 2, 4, "a");
+        tree.insert(a1.clone());
+        tree.insert(a2.clone());
+
+        // as much work as the best chain: the chain seen first stays
+        let b1 = child(b"genesis", 1, 4, "b");
+        let b2 = child(&b1.get_hash(), 2, 4, "b");
+        assert!(tree.insert(b1.clone()).extended.is_empty());
+        assert!(tree.insert(b2.clone()).extended.is_empty());
+        assert_eq!(tree.tip(), (2, a2.get_hash()));
+
+        let b3 = child(&b2.get_hash(), 3, 4, "b");
+        let update = tree.insert(b3.clone());
+        assert_eq!(hashes(&update.orphaned), hashes(&[a1, a2]));
+        assert_eq!(hashes(&update.extended), hashes(&[b1, b2, b3.clone()]));
+        assert_eq!(tree.tip(), (3, b3.get_hash()));
+    }
+
+    #[test]
+    fn a_heavier_block_beats_a_longer_chain() {
+        let mut tree = tree(10);
+        let a1 = child(b"genesis", 1, 4, "a");
+        let a2 = child(&a1.get_hash(), 2, 4, "a");
+        let a3 = child(&a2.get_hash(), 3, 4, "a");
+        for block in [&a1, &a2, &a3] {
+            tree.insert(block.clone());
+        }
+
+        // 2^6 hashes against 3 * 2^4
+        let b1 = child(b"genesis", 1, 6, "b");
+        let update = tree.insert(b1.clone());
+        assert_eq!(hashes(&update.orphaned), hashes(&[a1, a2, a3]));
+        assert_eq!(tree.tip(), (1, b1.get_hash()));
+    }
+
+    #[test]
+    fn finalizes_buried_blocks_and_drops_the_branches_beside_them() {
+        let mut tree = tree(1);
+        let a1 = child(b"genesis", 1, 4, "a");
+        let b1 = child(b"genesis", 1, 4, "b");
+        assert!(tree.insert(a1.clone()).finalized.is_empty());
+        tree.insert(b1.clone());
+
+        let a2 = child(&a1.get_hash(), 2, 4, "a");
+        let update = tree.insert(a2);
+        assert_eq!(hashes(&update.finalized), hashes(&[a1.clone()]));
+        assert_eq!(tree.root_height(), 1);
+        assert!(tree.contains(&a1.get_hash()));
+        assert!(!tree.contains(&b1.get_hash()));
+        assert_eq!(tree.len(), 1);
+    }
+}
@@ -0,0 +1,190 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tokio::sync::oneshot;
+
+use crate::pow::ProofOfWork;
+
+// hashes a worker computes between two looks at the cancel flag
+const BATCH: usize = 1_024;
+
+/// Nonce found by a `MiningJob`, with how much work it took.
+#[derive(Debug, Clone)]
+pub struct MiningResult {
+    pub nonce: usize,
+    pub hash: Vec<u8>,
+    /// Hashes computed by all the workers together.
+    pub hashes: u64,
+    pub elapsed: Duration,
+}
+
+impl MiningResult {
+    /// Hashes per second over the whole job.
+    pub fn hashrate(&self) -> f64 {
+        hashrate(self.hashes, self.elapsed)
+    }
+}
+
+/// A block being mined on the `Miner` threads.
+///
+/// `result` resolves with the winning nonce, or with an error once the job was
+/// cancelled (or, in theory, the nonce space exhausted). Dropping the job cancels it.
+pub struct MiningJob {
+    pub result: oneshot::Receiver<MiningResult>,
+    cancel: Arc<AtomicBool>,
+    hashes: Arc<AtomicU64>,
+    started: Instant,
+}
+
+impl MiningJob {
+    /// Stops the workers, they pick up the next job within a few thousand hashes.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+
+    /// Hashes per second so far.
+    pub fn hashrate(&self) -> f64 {
+        hashrate(self.hashes.load(Ordering::Relaxed), self.started.elapsed())
+    }
+}
+
+impl Drop for MiningJob {
+    fn drop(&mut self) {
+        self.cancel();
+    }
+}
+
+// one worker's share of a job: the nonces `start, start + stride, start + 2 * stride, ..`
+struct Work {
+    data: Arc<Vec<u8>>,
+    pow: ProofOfWork,
+    start: usize,
+    stride: usize,
+    cancel: Arc<AtomicBool>,
+    hashes: Arc<AtomicU64>,
+    started: Instant,
+    // taken by the worker that finds the nonce
+    found: Arc<Mutex<Option<oneshot::Sender<MiningResult>>>>,
+}
+
+/// Pool of mining threads, kept off the async runtime so the engine keeps serving
+/// txs and queries while a block is mined. Every job splits the nonce space across
+/// all threads, and a job is mined until one thread finds a nonce or it is cancelled.
+pub struct Miner {
+    workers: Vec<Sender<Work>>,
+}
+
+impl Miner {
+    /// Starts `threads` mining threads, one per available CPU when `threads` is 0.
+    pub fn new(threads: usize) -> Self {
+        let threads = if threads == 0 {
+            thread::available_parallelism().map(|n| n.get()).unwrap_or(1)
+        } else {
+            threads
+        };
+
+        let workers = (0..threads)
+            .map(|i| {
+                let (tx_work, rx_work) = channel();
+                thread::Builder::new()
+                    .name(format!("pow-miner-{}", i))
+                    .spawn(move || worker(rx_work))
+                    .expect("failed to spawn mining thread");
+                tx_work
+            })
+            .collect();
+
+        Self { workers }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Starts looking for a nonce that solves `bits` for `data` on all the threads.
+    pub fn mine(&self, data: Vec<u8>, bits: usize) -> MiningJob {
+        let (tx_result, rx_result) = oneshot::channel();
+        let data = Arc::new(data);
+        let pow = ProofOfWork::new(bits);
+        let cancel = Arc::new(AtomicBool::new(false));
+        let hashes = Arc::new(AtomicU64::new(0));
+        let found = Arc::new(Mutex::new(Some(tx_result)));
+        let started = Instant::now();
+
+        for (i, worker) in self.workers.iter().enumerate() {
+            let work = Work {
+                data: data.clone(),
+                pow,
+                start: i,
+                stride: self.workers.len(),
+                cancel: cancel.clone(),
+                hashes: hashes.clone(),
+                started,
+                found: found.clone(),
+            };
+            if worker.send(work).is_err() {
+                log::warn!("mining thread {} is gone", i);
+            }
+        }
+
+        MiningJob {
+            result: rx_result,
+            cancel,
+            hashes,
+            started,
+        }
+    }
+}
+
+fn worker(rx_work: Receiver<Work>) {
+    // the pool is gone once the miner is dropped
+    while let Ok(work) = rx_work.recv() {
+        mine(work);
+    }
+}
+
+fn mine(work: Work) {
+    let mut nonce = work.start;
+    loop {
+        if work.cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        for i in 0..BATCH {
+            let hash = ProofOfWork::hash(&work.data, nonce);
+            if work.pow.meets_target(&hash) {
+                // stop the other workers of this job
+                work.cancel.store(true, Ordering::Relaxed);
+                let hashes = work.hashes.fetch_add(i as u64 + 1, Ordering::Relaxed) + i as u64 + 1;
+                if let Some(tx_result) = work.found.lock().unwrap().take() {
+                    let _ = tx_result.send(MiningResult {
+                        nonce,
+                        hash: hash.to_vec(),
+                        hashes,
+                        elapsed: work.started.elapsed(),
+                    });
+                }
+                return;
+            }
+            nonce = match nonce.checked_add(work.stride) {
+                Some(next) => next,
+                None => {
+                    work.hashes.fetch_add(i as u64 + 1, Ordering::Relaxed);
+                    return;
+                }
+            };
+        }
+        work.hashes.fetch_add(BATCH as u64, Ordering::Relaxed);
+    }
+}
+
+fn hashrate(hashes: u64, elapsed: Duration) -> f64 {
+    let secs = elapsed.as_secs_f64();
+    if secs > 0.0 {
+        hashes as f64 / secs
+    } else {
+        0.0
+    }
+}
@@ -1,3 +1,11 @@
 mod pow;
+mod difficulty;
+mod miner;
+mod engine;
+mod fork_choice;
 
-pub use pow::*;
\ No newline at end of file
+pub use pow::*;
+pub use difficulty::*;
+pub use miner::*;
+pub use engine::*;
+pub use fork_choice::*;
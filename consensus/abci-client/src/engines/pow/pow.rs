@@ -1,48 +1,45 @@
-use anyhow::Result;
-use futures::future::Select;
 use std::ops::Shl;
 use bigint::U256;
-use crate::{utils::{serialize, hash_to_u8, hash_to_str}, Transaction};
-const MAX_NONCE: usize = usize::MAX;
-use tendermint_rpc::endpoint::broadcast::tx_commit::Request;
+use crate::utils::{serialize, hash_to_u8};
+
+/// The PoW puzzle: find a nonce so that the hash of `(data, nonce)` is below `2^(256 - bits)`.
+/// Mining itself runs on the `Miner` threads.
+#[derive(Clone, Copy)]
 pub struct ProofOfWork {
     target: U256,
 }
 
 impl ProofOfWork {
+    /// No hash meets a difficulty of more than 256 bits.
     pub fn new(bits: usize) -> Self {
-        let mut target = U256::from(1 as usize);
-        target = target.shl(256 - bits);
+        let target = match 256usize.checked_sub(bits) {
+            Some(shift) => U256::from(1 as usize).shl(shift),
+            None => U256::zero(),
+        };
 
         Self {
             target
         }
     }
 
-    pub fn run(&self, trans: String) -> (usize, String){
-        let mut nonce = 0;
-        let mut hash: String = String::from("");
-        while nonce < MAX_NONCE {
-            if let pre_hash = Self::prepare_data(trans.clone(), nonce) {
-                let mut hash_u: [u8; 32] = [0; 32];
-                hash_to_u8(&pre_hash,&mut hash_u);
-                let pre_hash_int = U256::from(hash_u);
+    /// Whether `nonce` solves the puzzle for `data`, i.e. the hash is below the target.
+    pub fn validate(&self, data: &[u8], nonce: usize) -> bool {
+        self.meets_target(&Self::hash(data, nonce))
+    }
 
-                if pre_hash_int.lt(&(self.target)) {
-                    //TODO: this should be changed
-                    hash = hash_to_str(&pre_hash);
-                    break;
-                }else {
-                    nonce += 1;
-                }
-            }
-        }
-        println!("本次工作量证明共计算了{:?}次哈希", nonce);
-        (nonce, hash)
+    /// Hash of `data` with `nonce`, the value compared against the target.
+    pub fn hash(data: &[u8], nonce: usize) -> [u8; 32] {
+        let mut hash_u: [u8; 32] = [0; 32];
+        hash_to_u8(&Self::prepare_data(data, nonce), &mut hash_u);
+        hash_u
+    }
+
+    pub fn meets_target(&self, hash: &[u8; 32]) -> bool {
+        U256::from(*hash).lt(&self.target)
     }
 
-    fn prepare_data(tx: String, nonce: usize) -> Vec<u8> {
-        let pre_data = (tx, nonce);
+    fn prepare_data(data: &[u8], nonce: usize) -> Vec<u8> {
+        let pre_data = (data, nonce);
         serialize(&pre_data).unwrap()
     }
 }
\ No newline at end of file
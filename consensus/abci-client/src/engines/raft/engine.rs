@@ -0,0 +1,516 @@
+use std::collections::{BTreeSet, HashMap};
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use super::{HardState, RaftEntry, RaftStorage};
+use crate::error::BlockError;
+use crate::utils::{deserialize, serialize};
+use crate::{validate_block_body, Block, ChainContext, ConsensusEngine, OutMessage, PeerId, Step, Target};
+
+// most entries sent in one AppendEntries
+const MAX_ENTRIES_PER_MESSAGE: usize = 64;
+
+/// Raft timing, counted in ticks of `tick`.
+#[derive(Debug, Clone)]
+pub struct RaftTimeouts {
+    pub tick: Duration,
+    /// A follower that hears nothing from a leader for this many ticks, plus a random
+    /// amount up to as many again, starts an election.
+    pub election_ticks: u64,
+    /// The leader sends AppendEntries, empty or not, every this many ticks.
+    pub heartbeat_ticks: u64,
+}
+
+impl Default for RaftTimeouts {
+    fn default() -> Self {
+        Self {
+            tick: Duration::from_millis(100),
+            election_ticks: 10,
+            heartbeat_ticks: 2,
+        }
+    }
+}
+
+/// Messages Raft nodes exchange.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RaftMessage {
+    RequestVote { term: u64, last_log_index: u64, last_log_term: u64 },
+    Vote { term: u64, granted: bool },
+    AppendEntries { term: u64, prev_log_index: u64, prev_log_term: u64, entries: Vec<RaftEntry>, leader_commit: u64 },
+    /// `match_index` is the last entry the follower has in common with the leader on
+    /// success, and a lower bound of it on failure.
+    AppendResponse { term: u64, success: bool, match_index: u64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RaftRole {
+    Follower,
+    Candidate,
+    Leader,
+}
+
+/// Raft, for clusters whose operators all trust each other: it survives crashed
+/// nodes, not lying ones. The elected leader appends batches of txs to its log and
+/// replicates them, and an entry stored by a majority is committed and executed as the
+/// block at height `initial_height + index - 1`.
+///
+/// The members are fixed by the node config. The log and the vote are kept in
+/// `RaftStorage`, so a restarted node picks up where it left off.
+pub struct RaftEngine {
+    id: PeerId,
+    // every member, us included
+    members: BTreeSet<PeerId>,
+    timeouts: RaftTimeouts,
+    storage: Box<dyn RaftStorage>,
+    role: RaftRole,
+    term: u64,
+    voted_for: Option<PeerId>,
+    leader: Option<PeerId>,
+    // log[i] is the entry at index i + 1
+    log: Vec<RaftEntry>,
+    commit_index: u64,
+    // last entry the app committed as a block
+    applied: u64,
+    votes: BTreeSet<PeerId>,
+    next_index: HashMap<PeerId, u64>,
+    match_index: HashMap<PeerId, u64>,
+    elapsed: u64,
+    election_timeout: u64,
+    // chain tip the next block is built on, None while a block is with the app
+    tip: Option<ChainContext>,
+}
+
+impl RaftEngine {
+    pub fn new(id: PeerId, members: Vec<PeerId>, timeouts: RaftTimeouts, storage: Box<dyn RaftStorage>) -> eyre::Result<Self> {
+        let members: BTreeSet<PeerId> = members.into_iter().collect();
+        if !members.contains(&id) {
+            eyre::bail!("node {} is not one of the raft members {:?}", id, members);
+        }
+        Ok(Self {
+            id,
+            members,
+            timeouts,
+            storage,
+            role: RaftRole::Follower,
+            term: 0,
+            voted_for: None,
+            leader: None,
+            log: vec![],
+            commit_index: 0,
+            applied: 0,
+            votes: BTreeSet::new(),
+            next_index: HashMap::new(),
+            match_index: HashMap::new(),
+            elapsed: 0,
+            election_timeout: 0,
+            tip: None,
+        })
+    }
+
+    pub fn role(&self) -> RaftRole {
+        self.role
+    }
+
+    pub fn leader(&self) -> Option<&PeerId> {
+        self.leader.as_ref()
+    }
+
+    fn peers(&self) -> Vec<PeerId> {
+        self.members.iter().filter(|id| **id != self.id).cloned().collect()
+    }
+
+    fn is_majority(&self, count: usize) -> bool {
+        count * 2 > self.members.len()
+    }
+
+    fn last_index(&self) -> u64 {
+        self.log.len() as u64
+    }
+
+    fn last_term(&self) -> u64 {
+        self.term_at(self.last_index())
+    }
+
+    fn term_at(&self, index: u64) -> u64 {
+        if index == 0 {
+            0
+        } else {
+            self.log.get(index as usize - 1).map_or(0, |entry| entry.term)
+        }
+    }
+
+    fn save_hard_state(&self) -> eyre::Result<()> {
+        self.storage.save_hard_state(&HardState {
+            term: self.term,
+            voted_for: self.voted_for.clone(),
+        })?;
+        Ok(())
+    }
+
+    fn reset_election_timer(&mut self) {
+        self.elapsed = 0;
+        let ticks = self.timeouts.election_ticks.max(1);
+        self.election_timeout = rand::thread_rng().gen_range(ticks, 2 * ticks);
+    }
+
+    fn send(&self, to: &PeerId, message: &RaftMessage, out: &mut Step) -> eyre::Result<()> {
+        out.messages.push(OutMessage {
+            target: Target::Peer(to.clone()),
+            payload: serialize(message)?,
+        });
+        Ok(())
+    }
+
+    fn become_follower(&mut self, term: u64, leader: Option<PeerId>) -> eyre::Result<()> {
+        if term > self.term {
+            self.term = term;
+            self.voted_for = None;
+            self.save_hard_state()?;
+        }
+        if self.role != RaftRole::Follower || (leader.is_some() && self.leader != leader) {
+            log::info!("raft node {} follows {:?} in term {}", self.id, leader, self.term);
+        }
+        self.role = RaftRole::Follower;
+        self.leader = leader;
+        self.reset_election_timer();
+        Ok(())
+    }
+
+    fn start_election(&mut self, out: &mut Step) -> eyre::Result<()> {
+        self.term += 1;
+        self.role = RaftRole::Candidate;
+        self.voted_for = Some(self.id.clone());
+        self.leader = None;
+        self.save_hard_state()?;
+        self.votes = BTreeSet::from([self.id.clone()]);
+        self.reset_election_timer();
+        log::info!("raft node {} starts an election for term {}", self.id, self.term);
+
+        if self.is_majority(self.votes.len()) {
+            return self.become_leader(out);
+        }
+        let request = RaftMessage::RequestVote {
+            term: self.term,
+            last_log_index: self.last_index(),
+            last_log_term: self.last_term(),
+        };
+        for peer in self.peers() {
+            self.send(&peer, &request, out)?;
+        }
+        Ok(())
+    }
+
+    fn become_leader(&mut self, out: &mut Step) -> eyre::Result<()> {
+        log::info!("raft node {} is the leader of term {}", self.id, self.term);
+        self.role = RaftRole::Leader;
+        self.leader = Some(self.id.clone());
+        self.next_index = self.peers().into_iter().map(|peer| (peer, self.last_index() + 1)).collect();
+        self.match_index = self.peers().into_iter().map(|peer| (peer, 0)).collect();
+
+        // entries of earlier terms only commit along with one of ours, as in the paper
+        if self.last_index() > self.commit_index {
+            let now = chrono::Utc::now().timestamp_nanos();
+            self.append(vec![], now)?;
+        }
+        self.broadcast_append(out)
+    }
+
+    // Appends a new entry of ours to the log. Entry times only go up, whatever the clock
+    // of the leader that appended the entry before.
+    fn append(&mut self, txs: Vec<Vec<u8>>, timestamp: i64) -> eyre::Result<()> {
+        let last_timestamp = self.log.last().map_or(i64::MIN, |entry| entry.timestamp);
+        let entry = RaftEntry {
+            term: self.term,
+            index: self.last_index() + 1,
+            timestamp: timestamp.max(last_timestamp.saturating_add(1)),
+            txs,
+        };
+        self.storage.append_entries(std::slice::from_ref(&entry))?;
+        self.log.push(entry);
+        Ok(())
+    }
+
+    fn broadcast_append(&mut self, out: &mut Step) -> eyre::Result<()> {
+        self.elapsed = 0;
+        for peer in self.peers() {
+            self.send_append(&peer, out)?;
+        }
+        // a single node commits on its own
+        self.advance_commit();
+        Ok(())
+    }
+
+    fn send_append(&self, peer: &PeerId, out: &mut Step) -> eyre::Result<()> {
+        let next = self.next_index.get(peer).copied().unwrap_or(1).max(1);
+        let prev_log_index = next - 1;
+        let entries = self
+            .log
+            .iter()
+            .skip(prev_log_index as usize)
+            .take(MAX_ENTRIES_PER_MESSAGE)
+            .cloned()
+            .collect();
+        let message = RaftMessage::AppendEntries {
+            term: self.term,
+            prev_log_index,
+            prev_log_term: self.term_at(prev_log_index),
+            entries,
+            leader_commit: self.commit_index,
+        };
+        self.send(peer, &message, out)
+    }
+
+    // Commits the last entry of our term that a majority has.
+    fn advance_commit(&mut self) {
+        for index in (self.commit_index + 1..=self.last_index()).rev() {
+            if self.term_at(index) != self.term {
+                break;
+            }
+            let count = 1 + self.match_index.values().filter(|matched| **matched >= index).count();
+            if self.is_majority(count) {
+                self.commit_index = index;
+                break;
+            }
+        }
+    }
+
+    fn handle_request_vote(&mut self, from: &PeerId, term: u64, last_log_index: u64, last_log_term: u64, out: &mut Step) -> eyre::Result<()> {
+        if term > self.term {
+            self.become_follower(term, None)?;
+        }
+        let up_to_date = (last_log_term, last_log_index) >= (self.last_term(), self.last_index());
+        let granted = term == self.term
+            && self.voted_for.as_ref().map_or(true, |voted_for| voted_for == from)
+            && up_to_date;
+        if granted {
+            self.voted_for = Some(from.clone());
+            self.save_hard_state()?;
+            self.reset_election_timer();
+        }
+        self.send(from, &RaftMessage::Vote { term: self.term, granted }, out)
+    }
+
+    fn handle_vote(&mut self, from: &PeerId, term: u64, granted: bool, out: &mut Step) -> eyre::Result<()> {
+        if term > self.term {
+            return self.become_follower(term, None);
+        }
+        if self.role != RaftRole::Candidate || term != self.term || !granted {
+            return Ok(());
+        }
+        self.votes.insert(from.clone());
+        if self.is_majority(self.votes.len()) {
+            self.become_leader(out)?;
+        }
+        Ok(())
+    }
+
+    // Returns the AppendResponse for the leader.
+    fn handle_append_entries(
+        &mut self,
+        from: &PeerId,
+        term: u64,
+        prev_log_index: u64,
+        prev_log_term: u64,
+        entries: Vec<RaftEntry>,
+        leader_commit: u64,
+    ) -> eyre::Result<RaftMessage> {
+        if term < self.term {
+            return Ok(RaftMessage::AppendResponse { term: self.term, success: false, match_index: 0 });
+        }
+        self.become_follower(term, Some(from.clone()))?;
+
+        if prev_log_index > self.last_index() || self.term_at(prev_log_index) != prev_log_term {
+            // the leader backs off to what we surely have in common
+            return Ok(RaftMessage::AppendResponse {
+                term: self.term,
+                success: false,
+                match_index: self.commit_index.min(prev_log_index.saturating_sub(1)),
+            });
+        }
+
+        let match_index = prev_log_index + entries.len() as u64;
+        let mut new_entries = vec![];
+        for entry in entries {
+            if entry.index <= self.last_index() {
+                if self.term_at(entry.index) == entry.term {
+                    continue;
+                }
+                if entry.index <= self.commit_index {
+                    eyre::bail!("raft leader {} overwrites the committed entry {}", from, entry.index);
+                }
+                self.storage.truncate(entry.index)?;
+                self.log.truncate(entry.index as usize - 1);
+            }
+            new_entries.push(entry);
+        }
+        if !new_entries.is_empty() {
+            self.storage.append_entries(&new_entries)?;
+            self.log.extend(new_entries);
+        }
+
+        if leader_commit > self.commit_index {
+            self.commit_index = leader_commit.min(match_index);
+        }
+        Ok(RaftMessage::AppendResponse { term: self.term, success: true, match_index })
+    }
+
+    fn handle_append_response(&mut self, from: &PeerId, term: u64, success: bool, match_index: u64, out: &mut Step) -> eyre::Result<()> {
+        if term > self.term {
+            return self.become_follower(term, None);
+        }
+        if self.role != RaftRole::Leader || term != self.term {
+            return Ok(());
+        }
+        if success {
+            let matched = self.match_index.entry(from.clone()).or_default();
+            *matched = (*matched).max(match_index);
+            self.next_index.insert(from.clone(), match_index + 1);
+            self.advance_commit();
+            // more to catch up on
+            if match_index < self.last_index() {
+                self.send_append(from, out)?;
+            }
+        } else {
+            let next = self.next_index.get(from).copied().unwrap_or(1);
+            self.next_index.insert(from.clone(), (match_index + 1).min(next.saturating_sub(1)).max(1));
+            self.send_append(from, out)?;
+        }
+        Ok(())
+    }
+
+    // The block of a committed entry, on top of the block of the entry before it.
+    fn entry_block(tip: &ChainContext, entry: &RaftEntry) -> Block {
+        Block::new(
+            tip.next_height(),
+            tip.last_block_hash.clone(),
+            tip.last_app_hash.clone(),
+            entry.timestamp,
+            0,
+            entry.txs.clone(),
+        )
+    }
+}
+
+impl ConsensusEngine for RaftEngine {
+    fn name(&self) -> &'static str {
+        "raft"
+    }
+
+    /// Reads the log back and resumes after the last block the app committed.
+    fn start(&mut self, ctx: &ChainContext) -> eyre::Result<Step> {
+        if let Some(state) = self.storage.load_hard_state()? {
+            self.term = state.term;
+            self.voted_for = state.voted_for;
+        }
+        self.log = self.storage.load_entries()?;
+        if self.log.iter().enumerate().any(|(i, entry)| entry.index != i as u64 + 1) {
+            eyre::bail!("the raft log has gaps, it is corrupted");
+        }
+
+        self.applied = if ctx.height == 0 { 0 } else { (ctx.height - ctx.state.initial_height + 1) as u64 };
+        if self.applied > self.last_index() {
+            eyre::bail!("the block store is at entry {} but the raft log ends at {}", self.applied, self.last_index());
+        }
+        self.commit_index = self.applied;
+        self.tip = Some(ctx.clone());
+        self.reset_election_timer();
+
+        log::info!(
+            "raft node {} starting in term {} with {} members, {} log entries, {} applied",
+            self.id, self.term, self.members.len(), self.last_index(), self.applied,
+        );
+        Ok(Step::default())
+    }
+
+    fn can_propose(&self, _ctx: &ChainContext) -> bool {
+        self.role == RaftRole::Leader
+    }
+
+    fn propose(&mut self, _ctx: &ChainContext, block: Block) -> eyre::Result<Step> {
+        if self.role != RaftRole::Leader {
+            eyre::bail!("raft node {} is not the leader", self.id);
+        }
+        self.append(block.get_txs().to_vec(), block.get_header().timestamp)?;
+        let mut out = Step::default();
+        self.broadcast_append(&mut out)?;
+        Ok(out)
+    }
+
+    /// Checks the hash and txs against the header, and that the block extends our tip.
+    fn validate_block(&self, ctx: &ChainContext, block: &Block) -> eyre::Result<()> {
+        validate_block_body(block, &ctx.state.block_params)?;
+
+        let header = block.get_header();
+        if header.height != ctx.next_height() {
+            return Err(BlockError::HeightMismatch { height: header.height, expected: ctx.next_height() }.into());
+        }
+        if header.prev_hash != ctx.last_block_hash {
+            return Err(BlockError::UnknownParent { height: header.height }.into());
+        }
+        if header.bits != 0 || header.nonce != 0 {
+            eyre::bail!("raft block {} carries proof of work fields", header.height);
+        }
+        Ok(())
+    }
+
+    fn handle_message(&mut self, _ctx: &ChainContext, from: &PeerId, payload: &[u8]) -> eyre::Result<Step> {
+        if !self.members.contains(from) {
+            log::warn!("ignoring raft message from {}, not a member", from);
+            return Ok(Step::default());
+        }
+        let mut out = Step::default();
+        match deserialize(payload)? {
+            RaftMessage::RequestVote { term, last_log_index, last_log_term } => {
+                self.handle_request_vote(from, term, last_log_index, last_log_term, &mut out)?
+            }
+            RaftMessage::Vote { term, granted } => self.handle_vote(from, term, granted, &mut out)?,
+            RaftMessage::AppendEntries { term, prev_log_index, prev_log_term, entries, leader_commit } => {
+                let response = self.handle_append_entries(from, term, prev_log_index, prev_log_term, entries, leader_commit)?;
+                self.send(from, &response, &mut out)?
+            }
+            RaftMessage::AppendResponse { term, success, match_index } => {
+                self.handle_append_response(from, term, success, match_index, &mut out)?
+            }
+        }
+        Ok(out)
+    }
+
+    /// Hands out the committed entries as blocks, one at a time as each needs the app
+    /// hash of the block before it.
+    fn poll_step(&mut self, _cx: &mut Context<'_>) -> Poll<eyre::Result<Step>> {
+        let tip = match &self.tip {
+            Some(tip) if self.applied < self.commit_index => tip,
+            _ => return Poll::Pending,
+        };
+        let entry = &self.log[self.applied as usize];
+        let block = Self::entry_block(tip, entry);
+        // wait for the app to commit this block before building the next one
+        self.tip = None;
+        Poll::Ready(Ok(Step::commit(block)))
+    }
+
+    fn tick_interval(&self) -> Option<Duration> {
+        Some(self.timeouts.tick)
+    }
+
+    fn tick(&mut self, _ctx: &ChainContext) -> eyre::Result<Step> {
+        let mut out = Step::default();
+        self.elapsed += 1;
+        match self.role {
+            RaftRole::Leader if self.elapsed >= self.timeouts.heartbeat_ticks => self.broadcast_append(&mut out)?,
+            RaftRole::Leader => {}
+            _ if self.elapsed >= self.election_timeout => self.start_election(&mut out)?,
+            _ => {}
+        }
+        Ok(out)
+    }
+
+    fn on_commit(&mut self, ctx: &ChainContext, block: &Block) -> eyre::Result<()> {
+        self.applied = (block.get_height() - ctx.state.initial_height + 1) as u64;
+        self.tip = Some(ctx.clone());
+        Ok(())
+    }
+}
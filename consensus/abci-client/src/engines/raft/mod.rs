@@ -0,0 +1,5 @@
+mod engine;
+mod storage;
+
+pub use engine::*;
+pub use storage::*;
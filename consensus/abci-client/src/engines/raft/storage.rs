@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+use sled::Db;
+
+use crate::error::BlockchainError;
+use crate::utils::{deserialize, serialize};
+use crate::PeerId;
+
+pub const RAFT_STATE: &str = "raft_state";
+pub const TABLE_OF_RAFT_ENTRIES: &str = "raft_entries";
+
+/// What a node must remember across restarts not to vote twice in a term.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct HardState {
+    pub term: u64,
+    pub voted_for: Option<PeerId>,
+}
+
+/// A batch of txs the leader put into the log, executed as one block once committed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct RaftEntry {
+    pub term: u64,
+    pub index: u64,
+    // unix nanoseconds, the time of the block
+    pub timestamp: i64,
+    pub txs: Vec<Vec<u8>>,
+}
+
+/// Durable Raft log and hard state, written before a node answers any message.
+pub trait RaftStorage: Send + 'static {
+    fn load_hard_state(&self) -> Result<Option<HardState>, BlockchainError>;
+    fn save_hard_state(&self, state: &HardState) -> Result<(), BlockchainError>;
+    fn load_entries(&self) -> Result<Vec<RaftEntry>, BlockchainError>;
+    fn append_entries(&self, entries: &[RaftEntry]) -> Result<(), BlockchainError>;
+    /// Drops the entries from `index` on.
+    fn truncate(&self, index: u64) -> Result<(), BlockchainError>;
+}
+
+/// On-disk Raft log backed by sled.
+#[derive(Clone)]
+pub struct SledRaftStorage {
+    db: Db,
+}
+
+impl SledRaftStorage {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, BlockchainError> {
+        Ok(Self {
+            db: sled::open(path)?,
+        })
+    }
+
+    // zero padded so that keys sort by index
+    fn get_entry_key(index: u64) -> String {
+        format!("{}:{:020}", TABLE_OF_RAFT_ENTRIES, index)
+    }
+}
+
+impl RaftStorage for SledRaftStorage {
+    fn load_hard_state(&self) -> Result<Option<HardState>, BlockchainError> {
+        let result = self.db.get(RAFT_STATE)?.map(|v| deserialize::<HardState>(&v));
+        result.map_or(Ok(None), |v| v.map(Some))
+    }
+
+    fn save_hard_state(&self, state: &HardState) -> Result<(), BlockchainError> {
+        self.db.insert(RAFT_STATE, serialize(state)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_entries(&self) -> Result<Vec<RaftEntry>, BlockchainError> {
+        let mut entries = vec![];
+        for item in self.db.scan_prefix(format!("{}:", TABLE_OF_RAFT_ENTRIES)) {
+            let (_, v) = item?;
+            entries.push(deserialize::<RaftEntry>(&v)?);
+        }
+        Ok(entries)
+    }
+
+    fn append_entries(&self, entries: &[RaftEntry]) -> Result<(), BlockchainError> {
+        let mut batch = sled::Batch::default();
+        for entry in entries {
+            batch.insert(Self::get_entry_key(entry.index).as_str(), serialize(entry)?);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn truncate(&self, index: u64) -> Result<(), BlockchainError> {
+        let from = Self::get_entry_key(index);
+        let to = Self::get_entry_key(u64::MAX);
+        let mut batch = sled::Batch::default();
+        for item in self.db.range(from.as_str()..=to.as_str()) {
+            let (key, _) = item?;
+            batch.remove(key);
+        }
+        self.db.apply_batch(batch)?;
+        self.db.flush()?;
+        Ok(())
+    }
+}
+
+/// In-memory Raft log, for tests and the in-memory network. Clones share the log,
+/// so a node can be restarted on it.
+#[derive(Clone, Default)]
+pub struct MemRaftStorage {
+    state: Arc<RwLock<Option<HardState>>>,
+    entries: Arc<RwLock<BTreeMap<u64, RaftEntry>>>,
+}
+
+impl MemRaftStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl RaftStorage for MemRaftStorage {
+    fn load_hard_state(&self) -> Result<Option<HardState>, BlockchainError> {
+        Ok(self.state.read().unwrap().clone())
+    }
+
+    fn save_hard_state(&self, state: &HardState) -> Result<(), BlockchainError> {
+        *self.state.write().unwrap() = Some(state.clone());
+        Ok(())
+    }
+
+    fn load_entries(&self) -> Result<Vec<RaftEntry>, BlockchainError> {
+        Ok(self.entries.read().unwrap().values().cloned().collect())
+    }
+
+    fn append_entries(&self, entries: &[RaftEntry]) -> Result<(), BlockchainError> {
+        let mut stored = self.entries.write().unwrap();
+        for entry in entries {
+            stored.insert(entry.index, entry.clone());
+        }
+        Ok(())
+    }
+
+    fn truncate(&self, index: u64) -> Result<(), BlockchainError> {
+        self.entries.write().unwrap().split_off(&index);
+        Ok(())
+    }
+}
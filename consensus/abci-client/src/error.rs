@@ -4,4 +4,196 @@ use thiserror::Error;
 pub enum BlockchainError {
     #[error("Serialize or Deserialize error")]
     SerializeError(#[from] Box<bincode::ErrorKind>),
-}
\ No newline at end of file
+
+    #[error("Failed to access sled db")]
+    SledError(#[from] sled::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum HandshakeError {
+    #[error("app block height {app_height} is higher than the block store height {store_height}, the block store is missing blocks the app has committed")]
+    AppAhead { app_height: i64, store_height: i64 },
+
+    #[error("app hash mismatch at height {height}: app has {app_hash}, block store expects {expected}. The app state does not belong to this chain")]
+    AppHashMismatch { height: i64, app_hash: String, expected: String },
+
+    #[error("block {0} is missing from the block store, cannot replay it")]
+    MissingBlock(i64),
+
+    #[error("genesis chain id {genesis:?} does not match the chain id {stored:?} in the block store")]
+    ChainIdMismatch { genesis: String, stored: String },
+}
+
+#[derive(Debug, Error)]
+pub enum BlockError {
+    #[error("block {height} does not follow a known block")]
+    UnknownParent { height: i64 },
+
+    #[error("block {height} is on top of a block at the wrong height, expected height {expected}")]
+    HeightMismatch { height: i64, expected: i64 },
+
+    #[error("block {height} hash does not match its header")]
+    HashMismatch { height: i64 },
+
+    #[error("block {height} txs do not match the merkle root of its header")]
+    TxsHashMismatch { height: i64 },
+
+    #[error("block {height} holds {bytes} bytes of txs, more than the max of {max_bytes}")]
+    TooLarge { height: i64, bytes: i64, max_bytes: i64 },
+
+    #[error("block {height} declares {gas_wanted} gas wanted, more than the max of {max_gas}")]
+    GasExceeded { height: i64, gas_wanted: i64, max_gas: i64 },
+
+    #[error("block {height} time {timestamp} is not after the median time {median} of the blocks before it")]
+    TimestampBeforeMedian { height: i64, timestamp: i64, median: i64 },
+
+    #[error("block {height} time {timestamp} is more than the allowed drift ahead of our clock")]
+    TimestampTooFarAhead { height: i64, timestamp: i64 },
+}
+
+#[derive(Debug, Error)]
+pub enum PoaError {
+    #[error("block {height} proposer {proposer} is not an authority")]
+    UnknownProposer { height: i64, proposer: String },
+
+    #[error("block {height} is not signed by its proposer {proposer}")]
+    InvalidSignature { height: i64, proposer: String },
+
+    #[error("block {height} is in slot {slot} of {expected}, not of its proposer {proposer}")]
+    WrongTurn { height: i64, slot: u64, proposer: String, expected: String },
+
+    #[error("block {height} is in slot {slot}, not after the slot {parent_slot} of its parent")]
+    SlotNotIncreasing { height: i64, slot: u64, parent_slot: u64 },
+
+    #[error("block {height} is in slot {slot}, which has not started yet")]
+    FutureSlot { height: i64, slot: u64 },
+
+    #[error("block {height} carries another app hash than the one of the block before it")]
+    AppHashMismatch { height: i64 },
+}
+
+#[derive(Debug, Error)]
+pub enum PowError {
+    #[error("block {height} has difficulty {bits}, expected {expected} from the retarget")]
+    DifficultyMismatch { height: i64, bits: usize, expected: usize },
+
+    #[error("block {height} nonce {nonce} does not meet the difficulty of {bits} bits")]
+    InsufficientWork { height: i64, nonce: usize, bits: usize },
+
+    #[error("block {height} carries the wrong app hash of block {app_hash_height}")]
+    AppHashMismatch { height: i64, app_hash_height: i64 },
+
+    #[error("block {height} has difficulty {bits}, below the {min} bits the chain can be at")]
+    DifficultyTooLow { height: i64, bits: usize, min: usize },
+
+    #[error("block {height} has difficulty {bits}, above the {max} bits the chain can be at")]
+    DifficultyTooHigh { height: i64, bits: usize, max: usize },
+}
+
+#[derive(Debug, Error)]
+pub enum TendermintError {
+    #[error("{address} is not a validator at height {height}")]
+    UnknownValidator { height: i64, address: String },
+
+    #[error("vote of {address} at height {height} round {round} has an invalid signature")]
+    InvalidVoteSignature { height: i64, round: i32, address: String },
+
+    #[error("proposal at height {height} round {round} comes from {proposer}, expected {expected}")]
+    WrongProposer { height: i64, round: i32, proposer: String, expected: String },
+
+    #[error("proposal at height {height} round {round} has an invalid signature")]
+    InvalidProposalSignature { height: i64, round: i32 },
+
+    #[error("block {height} has no commit for the previous block")]
+    MissingLastCommit { height: i64 },
+
+    #[error("block {height} carries a commit for another block")]
+    LastCommitMismatch { height: i64 },
+
+    #[error("block {height} carries a commit signed by {signed} of {total} voting power, more than 2/3 is needed")]
+    InsufficientCommitPower { height: i64, signed: i64, total: i64 },
+
+    #[error("block {height} carries a commit with an invalid signature of {address}")]
+    InvalidCommitSignature { height: i64, address: String },
+
+    #[error("block {height} carries another app hash than the one of the block before it")]
+    AppHashMismatch { height: i64 },
+
+    #[error("committed block {height} does not extend our tip")]
+    NotOnTip { height: i64 },
+
+    #[error("commit of block {height} is for another block")]
+    CommitMismatch { height: i64 },
+}
+
+#[derive(Debug, Error)]
+pub enum P2pError {
+    #[error("invalid peer address {0:?}, expected <node id>@<host>:<port>")]
+    InvalidPeerAddress(String),
+
+    #[error("peer is on chain {chain_id:?}, we are on {expected:?}")]
+    ChainIdMismatch { chain_id: String, expected: String },
+
+    #[error("peer {peer} has another genesis file")]
+    GenesisMismatch { peer: String },
+
+    #[error("peer claims id {peer}, which is not the address of its key")]
+    InvalidNodeId { peer: String },
+
+    #[error("dialed {expected}, but {peer} answered")]
+    UnexpectedPeer { expected: String, peer: String },
+
+    #[error("connected to ourselves")]
+    SelfConnection,
+
+    #[error("peer {peer} failed to sign our challenge")]
+    InvalidAuthSignature { peer: String },
+
+    #[error("unexpected message during the handshake")]
+    UnexpectedMessage,
+
+    #[error("connection closed during the handshake")]
+    ConnectionClosed,
+
+    #[error("handshake timed out")]
+    HandshakeTimeout,
+}
+
+#[derive(Debug, Error)]
+pub enum RpcError {
+    #[error("invalid params: {0}")]
+    InvalidParams(String),
+
+    #[error("height {height} must be less than or equal to the current blockchain height {latest}")]
+    HeightTooHigh { height: i64, latest: i64 },
+
+    #[error("height {height} is not available, lowest height is {lowest}")]
+    HeightNotAvailable { height: i64, lowest: i64 },
+
+    #[error("tx ({hash}) not found")]
+    TxNotFound { hash: String },
+
+    #[error("invalid query {query:?}: {reason}")]
+    InvalidQuery { query: String, reason: String },
+
+    #[error("failed to read the block store: {0}")]
+    Storage(#[from] BlockchainError),
+
+    #[error("the node stopped before answering")]
+    NodeStopped,
+
+    #[error("timed out waiting for tx {hash} to be included in a block")]
+    CommitTimeout { hash: String },
+
+    #[error("already subscribed to {query:?}")]
+    AlreadySubscribed { query: String },
+
+    #[error("subscription to {query:?} not found")]
+    SubscriptionNotFound { query: String },
+
+    #[error("max of {max} subscriptions per client reached")]
+    TooManySubscriptions { max: usize },
+
+    #[error("subscription was cancelled (reason: client is not pulling messages fast enough)")]
+    SubscriptionCancelled,
+}
@@ -0,0 +1,247 @@
+use std::fs;
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tendermint_proto::abci::{BlockParams, ConsensusParams, RequestInitChain, ValidatorUpdate};
+use tendermint_proto::google::protobuf::{Duration, Timestamp};
+use tendermint_proto::types::{EvidenceParams, ValidatorParams, VersionParams};
+
+use crate::utils::sha256_digest;
+use crate::{HbbftValidators, PoaAuthorities, ValidatorUpdateRecord};
+
+pub const GENESIS_FILE: &str = "genesis.json";
+
+/// Genesis document, in the same JSON format as CometBFT v0.34's `genesis.json`,
+/// so the file written by e.g. `gaia-rs init` can be used as is.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Genesis {
+    pub genesis_time: DateTime<Utc>,
+    pub chain_id: String,
+    #[serde(with = "stringified", default = "default_initial_height")]
+    pub initial_height: i64,
+    #[serde(default)]
+    pub consensus_params: GenesisConsensusParams,
+    #[serde(default)]
+    pub validators: Vec<GenesisValidator>,
+    #[serde(default)]
+    pub app_hash: String,
+    #[serde(default)]
+    pub app_state: Value,
+    /// Validators and threshold public keys of the hbbft engine, not part of CometBFT's format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hbbft: Option<HbbftValidators>,
+    /// Authorities and slot length of the poa engine, not part of CometBFT's format.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub poa: Option<PoaAuthorities>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct GenesisConsensusParams {
+    #[serde(default)]
+    pub block: GenesisBlockParams,
+    #[serde(default)]
+    pub evidence: GenesisEvidenceParams,
+    #[serde(default)]
+    pub validator: GenesisValidatorParams,
+    #[serde(default)]
+    pub version: GenesisVersionParams,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisBlockParams {
+    #[serde(with = "stringified")]
+    pub max_bytes: i64,
+    #[serde(with = "stringified")]
+    pub max_gas: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisEvidenceParams {
+    #[serde(with = "stringified")]
+    pub max_age_num_blocks: i64,
+    // nanoseconds
+    #[serde(with = "stringified")]
+    pub max_age_duration: i64,
+    #[serde(with = "stringified", default)]
+    pub max_bytes: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisValidatorParams {
+    pub pub_key_types: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct GenesisVersionParams {
+    #[serde(with = "stringified", default)]
+    pub app_version: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisValidator {
+    #[serde(default)]
+    pub address: String,
+    pub pub_key: GenesisPubKey,
+    #[serde(with = "stringified")]
+    pub power: i64,
+    #[serde(default)]
+    pub name: String,
+}
+
+/// Amino JSON encoded public key, e.g. `{"type": "tendermint/PubKeyEd25519", "value": "<base64>"}`.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct GenesisPubKey {
+    #[serde(rename = "type")]
+    pub key_type: String,
+    pub value: String,
+}
+
+impl Default for GenesisBlockParams {
+    fn default() -> Self {
+        Self {
+            max_bytes: 22_020_096,
+            max_gas: -1,
+        }
+    }
+}
+
+impl Default for GenesisEvidenceParams {
+    fn default() -> Self {
+        Self {
+            max_age_num_blocks: 100_000,
+            max_age_duration: 172_800_000_000_000,
+            max_bytes: 1_048_576,
+        }
+    }
+}
+
+impl Default for GenesisValidatorParams {
+    fn default() -> Self {
+        Self {
+            pub_key_types: vec!["ed25519".to_string()],
+        }
+    }
+}
+
+fn default_initial_height() -> i64 {
+    1
+}
+
+impl Genesis {
+    pub fn new(chain_id: &str) -> Self {
+        Self {
+            genesis_time: Utc::now(),
+            chain_id: chain_id.to_string(),
+            initial_height: default_initial_height(),
+            consensus_params: Default::default(),
+            validators: vec![],
+            app_hash: String::new(),
+            app_state: Value::Object(Default::default()),
+            hbbft: None,
+            poa: None,
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read(path).wrap_err_with(|| format!("failed to read genesis file {:?}", path))?;
+        let genesis: Genesis = serde_json::from_slice(&content)
+            .wrap_err_with(|| format!("failed to parse genesis file {:?}", path))?;
+        if genesis.chain_id.is_empty() {
+            eyre::bail!("genesis file {:?} has an empty chain_id", path);
+        }
+        Ok(genesis)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Hash of the genesis document, identifying the chain beyond its chain id.
+    pub fn hash(&self) -> Vec<u8> {
+        sha256_digest(&serde_json::to_vec(self).unwrap())
+    }
+
+    pub fn validator_updates(&self) -> eyre::Result<Vec<ValidatorUpdateRecord>> {
+        self.validators.iter().map(|validator| {
+            let pub_key = base64::decode(&validator.pub_key.value)
+                .wrap_err_with(|| format!("invalid public key for genesis validator {:?}", validator.name))?;
+            let pub_key_type = match validator.pub_key.key_type.as_str() {
+                "tendermint/PubKeyEd25519" => "ed25519",
+                "tendermint/PubKeySecp256k1" => "secp256k1",
+                other => eyre::bail!("unsupported genesis validator key type {:?}", other),
+            };
+            Ok(ValidatorUpdateRecord {
+                pub_key_type: pub_key_type.to_string(),
+                pub_key,
+                power: validator.power,
+            })
+        }).collect()
+    }
+
+    pub fn consensus_params(&self) -> ConsensusParams {
+        let params = &self.consensus_params;
+        ConsensusParams {
+            block: Some(BlockParams {
+                max_bytes: params.block.max_bytes,
+                max_gas: params.block.max_gas,
+            }),
+            evidence: Some(EvidenceParams {
+                max_age_num_blocks: params.evidence.max_age_num_blocks,
+                max_age_duration: Some(Duration {
+                    seconds: params.evidence.max_age_duration / 1_000_000_000,
+                    nanos: (params.evidence.max_age_duration % 1_000_000_000) as i32,
+                }),
+                max_bytes: params.evidence.max_bytes,
+            }),
+            validator: Some(ValidatorParams {
+                pub_key_types: params.validator.pub_key_types.clone(),
+            }),
+            version: Some(VersionParams {
+                app_version: params.version.app_version as u64,
+            }),
+        }
+    }
+
+    /// The `InitChain` request for this genesis, with `app_state` passed as the raw JSON bytes.
+    pub fn to_init_chain_request(&self) -> eyre::Result<RequestInitChain> {
+        Ok(RequestInitChain {
+            time: Some(Timestamp {
+                seconds: self.genesis_time.timestamp(),
+                nanos: self.genesis_time.timestamp_subsec_nanos() as i32,
+            }),
+            chain_id: self.chain_id.clone(),
+            consensus_params: Some(self.consensus_params()),
+            validators: self.validator_updates()?.iter().map(ValidatorUpdate::from).collect(),
+            app_state_bytes: serde_json::to_vec(&self.app_state)?.into(),
+            initial_height: self.initial_height,
+        })
+    }
+}
+
+// CometBFT writes int64 values as JSON strings, but plain numbers are accepted as well.
+mod stringified {
+    use serde::{de::Error, Deserialize, Deserializer, Serializer};
+
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StrOrInt {
+        Str(String),
+        Int(i64),
+    }
+
+    pub fn serialize<S: Serializer>(value: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&value.to_string())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+        match StrOrInt::deserialize(deserializer)? {
+            StrOrInt::Str(s) => s.parse().map_err(D::Error::custom),
+            StrOrInt::Int(i) => Ok(i),
+        }
+    }
+}
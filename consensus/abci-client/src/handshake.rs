@@ -0,0 +1,120 @@
+use tendermint_proto::abci::{RequestInfo, ResponseInitChain};
+
+use crate::{error::HandshakeError, AppInfo, ChainState, Engine};
+
+impl Engine {
+    /// Syncs the app with the consensus block store before any new block is produced,
+    /// in the same way as CometBFT's handshake:
+    ///
+    /// * the app's `last_block_height` / `last_block_app_hash` from `Info` are compared
+    ///   with what the block store recorded for that height,
+    /// * an app at height 0 gets `InitChain` first,
+    /// * blocks the app is missing (crash mid-block, restored from an old backup) are
+    ///   replayed through BeginBlock..Commit, checking every resulting app hash.
+    ///
+    /// A disagreement on the app hash stops the node, as continuing would fork the app state.
+    pub fn handshake(&mut self) -> eyre::Result<()> {
+        let resp_info = self.req_client.info(RequestInfo::default())?;
+        let app_height = resp_info.last_block_height;
+        let app_hash = resp_info.last_block_app_hash;
+        self.app_info = AppInfo {
+            data: resp_info.data,
+            version: resp_info.version,
+            app_version: resp_info.app_version,
+        };
+        let store_height = self.store.height()?;
+
+        log::info!("handshake: app height {}, block store height {}", app_height, store_height);
+
+        if app_height > store_height {
+            return Err(HandshakeError::AppAhead { app_height, store_height }.into());
+        }
+
+        match self.store.load_state()? {
+            Some(state) if state.chain_id != self.genesis.chain_id => {
+                return Err(HandshakeError::ChainIdMismatch {
+                    genesis: self.genesis.chain_id.clone(),
+                    stored: state.chain_id,
+                }.into());
+            }
+            Some(state) => self.set_state(state)?,
+            // the app was initialized before the block store recorded any state
+            None => self.set_state(ChainState::from_genesis(&self.genesis, &ResponseInitChain::default())?)?,
+        }
+
+        self.last_block_height = app_height;
+        self.last_app_hash = app_hash;
+        self.last_block_hash = self
+            .store
+            .load_block(app_height)?
+            .map(|block| block.get_hash())
+            .unwrap_or_default();
+
+        if app_height == 0 {
+            self.init_chain()?;
+        } else if let Some(expected) = self.expected_app_hash(app_height)? {
+            self.check_app_hash(app_height, &expected)?;
+        } else {
+            log::warn!("no app hash recorded for height {}, trusting the app", app_height);
+        }
+
+        // the stored state is the one after the last stored block; replay from the
+        // validators of the app's height, which the store keeps per height
+        let first = (app_height + 1).max(self.state.initial_height);
+        if first <= store_height {
+            if let Some(validators) = self.store.load_validators(first + 1)? {
+                let mut state = self.state.clone();
+                state.validators = validators;
+                self.set_state(state)?;
+            }
+        }
+
+        for height in first..=store_height {
+            let block = self
+                .store
+                .load_block(height)?
+                .ok_or(HandshakeError::MissingBlock(height))?;
+
+            // the block was built on top of this app hash, older app hashes are checked
+            // by the engines that carry them
+            if self.consensus.app_hash_lag() == 1 {
+                self.check_app_hash(height - 1, &block.get_header().app_hash)?;
+            }
+            self.consensus.validate_block(&self.context(), &block)?;
+
+            let recorded = self.store.load_results(height)?;
+
+            log::debug!("replaying block {} with {} txs", height, block.get_txs().len());
+            self.execute_block(&block)?;
+
+            if let Some(recorded) = recorded {
+                self.check_app_hash(height, &recorded.app_hash)?;
+            }
+        }
+
+        log::info!("handshake done, app is at height {}", self.last_block_height);
+
+        Ok(())
+    }
+
+    // The app hash the store expects after `height` was committed: either recorded with
+    // the block's results, or carried in the header of a later block.
+    fn expected_app_hash(&self, height: i64) -> eyre::Result<Option<Vec<u8>>> {
+        if let Some(results) = self.store.load_results(height)? {
+            return Ok(Some(results.app_hash));
+        }
+        let carrier = self.store.load_block(height + self.consensus.app_hash_lag())?;
+        Ok(carrier.map(|block| block.get_header().app_hash.clone()))
+    }
+
+    fn check_app_hash(&self, height: i64, expected: &[u8]) -> Result<(), HandshakeError> {
+        if self.last_app_hash.as_ref() != expected {
+            return Err(HandshakeError::AppHashMismatch {
+                height,
+                app_hash: hex::encode_upper(&self.last_app_hash),
+                expected: hex::encode_upper(expected),
+            });
+        }
+        Ok(())
+    }
+}
@@ -1,14 +1,37 @@
 mod api_server;
 mod abci_engine;
+mod block_builder;
+mod config;
+mod genesis;
+mod handshake;
+mod mempool;
+mod node_key;
+mod priv_validator;
+mod state;
+mod status;
 
 mod engines;
+pub mod p2p;
+pub mod rpc;
+mod blocks;
+mod storage;
 mod wallets;
 mod utils;
 mod error;
 
 pub use api_server::ClientApi;
 pub use abci_engine::Engine;
+pub use block_builder::*;
+pub use config::*;
+pub use genesis::*;
+pub use mempool::*;
+pub use node_key::NodeKey;
+pub use priv_validator::PrivValidatorKey;
+pub use state::*;
+pub use status::*;
 pub use engines::*;
+pub use blocks::*;
+pub use storage::*;
 pub use wallets::*;
 pub use utils::*;
 
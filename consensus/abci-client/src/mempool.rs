@@ -0,0 +1,252 @@
+use std::collections::{HashSet, VecDeque};
+use std::net::SocketAddr;
+
+use bytes::Bytes;
+use tendermint_abci::{Client as AbciClient, ClientBuilder};
+use tendermint_proto::abci::{CheckTxType, RequestCheckTx, ResponseCheckTx};
+use tokio::time::Instant;
+
+use crate::block_builder::BlockLimits;
+use crate::utils::sha256_digest;
+
+/// Maximum number of transactions kept in the mempool, the same default as CometBFT.
+pub const MEMPOOL_SIZE: usize = 5_000;
+
+/// Code returned to the caller when the mempool itself rejects a transaction
+/// before (or instead of) asking the app.
+pub const CODE_MEMPOOL_REJECTED: u32 = 1;
+
+/// A transaction accepted by the app's `CheckTx` and waiting to be put in a block.
+#[derive(Debug, Clone)]
+pub struct MempoolTx {
+    pub tx: Bytes,
+    pub hash: Vec<u8>,
+    pub gas_wanted: i64,
+    pub received_at: Instant,
+}
+
+/// Pool of pending transactions, gated by the ABCI `CheckTx` hook.
+///
+/// The mempool owns its own connection to the app, so checking transactions
+/// never interleaves with the BeginBlock..Commit calls on the consensus connection.
+pub struct Mempool {
+    client: AbciClient,
+    txs: VecDeque<MempoolTx>,
+    hashes: HashSet<Vec<u8>>,
+    // txs reaped into a block that is not committed yet, still counted as duplicates
+    reaped: HashSet<Vec<u8>>,
+    max_txs: usize,
+    txs_bytes: i64,
+    txs_gas: i64,
+}
+
+impl Mempool {
+    pub fn new(app_address: &SocketAddr, max_txs: usize) -> eyre::Result<Self> {
+        let client = ClientBuilder::default().connect(app_address)?;
+        Ok(Self {
+            client,
+            txs: VecDeque::new(),
+            hashes: HashSet::new(),
+            reaped: HashSet::new(),
+            max_txs,
+            txs_bytes: 0,
+            txs_gas: 0,
+        })
+    }
+
+    /// Runs `CheckTx` on the app and queues the transaction if the app accepts it.
+    /// The app's response is returned as is, so callers can forward code/log to the user.
+    ///
+    /// Transactions that could never fit in a block under `limits` are refused up front.
+    pub fn check_tx(&mut self, tx: Bytes, limits: &BlockLimits) -> eyre::Result<ResponseCheckTx> {
+        let hash = tx_hash(&tx);
+
+        if self.hashes.contains(&hash) || self.reaped.contains(&hash) {
+            return Ok(rejected("tx already exists in cache"));
+        }
+        if self.txs.len() >= self.max_txs {
+            return Ok(rejected(&format!("mempool is full: number of txs {} (max: {})", self.txs.len(), self.max_txs)));
+        }
+        if !limits.fits_tx(tx.len()) {
+            return Ok(rejected(&format!("tx too large. Max size is {}, but got {}", limits.max_bytes, tx.len())));
+        }
+
+        let resp = self.client.check_tx(RequestCheckTx {
+            tx: tx.clone(),
+            r#type: CheckTxType::New as i32,
+        })?;
+
+        if resp.code != 0 {
+            return Ok(resp);
+        }
+        if limits.max_gas >= 0 && resp.gas_wanted > limits.max_gas {
+            return Ok(rejected(&format!("gas wanted {} is greater than max gas {}", resp.gas_wanted, limits.max_gas)));
+        }
+
+        self.push(MempoolTx {
+            tx,
+            hash,
+            gas_wanted: resp.gas_wanted,
+            received_at: Instant::now(),
+        });
+
+        Ok(resp)
+    }
+
+    /// Takes transactions from the front of the pool, in arrival order, until adding
+    /// the next one would exceed `max_bytes` or `max_gas`. A negative limit means unlimited.
+    pub fn reap_max_bytes_max_gas(&mut self, max_bytes: i64, max_gas: i64) -> Vec<MempoolTx> {
+        let mut reaped = vec![];
+        let mut total_bytes = 0;
+        let mut total_gas = 0;
+
+        while let Some(mem_tx) = self.txs.front() {
+            let tx_bytes = mem_tx.tx.len() as i64;
+            if max_bytes >= 0 && total_bytes + tx_bytes > max_bytes {
+                break;
+            }
+            if max_gas >= 0 && total_gas + mem_tx.gas_wanted > max_gas {
+                break;
+            }
+            total_bytes += tx_bytes;
+            total_gas += mem_tx.gas_wanted;
+            let mem_tx = self.pop().unwrap();
+            self.reaped.insert(mem_tx.hash.clone());
+            reaped.push(mem_tx);
+        }
+
+        reaped
+    }
+
+    /// Drops the transactions of a committed block from the pool and re-runs `CheckTx`
+    /// for every other pending transaction, as the new app state may have invalidated
+    /// some of them. Returns the evicted transactions together with the app's response.
+    pub fn update(&mut self, committed: &[Vec<u8>]) -> eyre::Result<Vec<(MempoolTx, ResponseCheckTx)>> {
+        let committed: HashSet<Vec<u8>> = committed.iter().map(|tx| tx_hash(tx)).collect();
+        let mut evicted = vec![];
+        let pending: Vec<MempoolTx> = self
+            .txs
+            .drain(..)
+            .filter(|mem_tx| !committed.contains(&mem_tx.hash))
+            .collect();
+        self.hashes.clear();
+        // txs reaped into blocks still waiting to be final stay duplicates
+        self.reaped.retain(|hash| !committed.contains(hash));
+        self.txs_bytes = 0;
+        self.txs_gas = 0;
+
+        for mem_tx in pending {
+            let resp = self.client.check_tx(RequestCheckTx {
+                tx: mem_tx.tx.clone(),
+                r#type: CheckTxType::Recheck as i32,
+            })?;
+            if resp.code == 0 {
+                self.push(mem_tx);
+            } else {
+                evicted.push((mem_tx, resp));
+            }
+        }
+
+        Ok(evicted)
+    }
+
+    /// Puts the transactions of a block that was given up on back at the front of the
+    /// pool, in their original order, so they go in the next block.
+    pub fn requeue(&mut self, txs: Vec<MempoolTx>) {
+        for mem_tx in txs.into_iter().rev() {
+            self.reaped.remove(&mem_tx.hash);
+            self.hashes.insert(mem_tx.hash.clone());
+            self.txs_bytes += mem_tx.tx.len() as i64;
+            self.txs_gas += mem_tx.gas_wanted;
+            self.txs.push_front(mem_tx);
+        }
+    }
+
+    /// Takes the transactions of a block that is not final yet out of the pool. Like
+    /// reaped transactions, they count as duplicates until the block is committed or dropped.
+    pub fn mark_reaped(&mut self, txs: &[Vec<u8>]) {
+        let hashes: HashSet<Vec<u8>> = txs.iter().map(|tx| tx_hash(tx)).collect();
+        if self.txs.iter().any(|mem_tx| hashes.contains(&mem_tx.hash)) {
+            let pending: Vec<MempoolTx> = self.txs.drain(..).collect();
+            self.hashes.clear();
+            self.txs_bytes = 0;
+            self.txs_gas = 0;
+            for mem_tx in pending.into_iter().filter(|mem_tx| !hashes.contains(&mem_tx.hash)) {
+                self.push(mem_tx);
+            }
+        }
+        self.reaped.extend(hashes);
+    }
+
+    /// Puts the transactions of a block that was dropped before it was final back in
+    /// the pool, through `CheckTx` as they may come from a peer. Returns the transactions
+    /// refused this time, together with the response.
+    pub fn restore(&mut self, txs: &[Vec<u8>], limits: &BlockLimits) -> eyre::Result<Vec<(Vec<u8>, ResponseCheckTx)>> {
+        let mut refused = vec![];
+        for tx in txs {
+            let hash = tx_hash(tx);
+            self.reaped.remove(&hash);
+            if self.hashes.contains(&hash) {
+                continue;
+            }
+            let resp = self.check_tx(Bytes::from(tx.clone()), limits)?;
+            if resp.code != 0 {
+                refused.push((tx.clone(), resp));
+            }
+        }
+        Ok(refused)
+    }
+
+    /// Takes the oldest pending transaction out of the pool.
+    pub fn pop(&mut self) -> Option<MempoolTx> {
+        let mem_tx = self.txs.pop_front()?;
+        self.hashes.remove(&mem_tx.hash);
+        self.txs_bytes -= mem_tx.tx.len() as i64;
+        self.txs_gas -= mem_tx.gas_wanted;
+        Some(mem_tx)
+    }
+
+    fn push(&mut self, mem_tx: MempoolTx) {
+        self.hashes.insert(mem_tx.hash.clone());
+        self.txs_bytes += mem_tx.tx.len() as i64;
+        self.txs_gas += mem_tx.gas_wanted;
+        self.txs.push_back(mem_tx);
+    }
+
+    /// Arrival time of the transaction that has been waiting the longest.
+    pub fn oldest_received_at(&self) -> Option<Instant> {
+        self.txs.front().map(|mem_tx| mem_tx.received_at)
+    }
+
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// Total size in bytes of the pending transactions.
+    pub fn size_bytes(&self) -> i64 {
+        self.txs_bytes
+    }
+
+    /// Sum of `gas_wanted` reported by `CheckTx` for the pending transactions.
+    pub fn gas_wanted(&self) -> i64 {
+        self.txs_gas
+    }
+}
+
+/// Transaction hash as used by Tendermint: the SHA256 of the raw tx bytes.
+pub fn tx_hash(tx: &[u8]) -> Vec<u8> {
+    sha256_digest(tx)
+}
+
+/// Builds a `CheckTx` rejection for transactions refused by the node rather than the app.
+pub fn rejected(log: &str) -> ResponseCheckTx {
+    ResponseCheckTx {
+        code: CODE_MEMPOOL_REJECTED,
+        log: log.to_string(),
+        ..Default::default()
+    }
+}
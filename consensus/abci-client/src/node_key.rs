@@ -0,0 +1,59 @@
+use std::fs;
+use std::path::Path;
+
+use eyre::WrapErr;
+use ring::signature::{EcdsaKeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{ecdsa_p256_sha256_sign_digest, new_private_key, write_private_file};
+use crate::hash_pub_key;
+
+/// Identity of the node, used to sign what the node produces and to identify it to peers.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct NodeKey {
+    #[serde(with = "hex::serde")]
+    pkcs8: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    public_key: Vec<u8>,
+}
+
+impl NodeKey {
+    pub fn generate() -> Self {
+        let pkcs8 = new_private_key();
+        let key_pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref()).unwrap();
+        let public_key = key_pair.public_key().as_ref().to_vec();
+
+        Self { pkcs8, public_key }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read(path).wrap_err_with(|| format!("failed to read node key {:?}", path))?;
+        let node_key = serde_json::from_slice(&content)
+            .wrap_err_with(|| format!("failed to parse node key {:?}", path))?;
+        Ok(node_key)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        write_private_file(path, &serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// 20 bytes address of the node, the same hash as the wallet addresses.
+    pub fn address(&self) -> Vec<u8> {
+        hash_pub_key(&self.public_key)
+    }
+
+    /// Hex encoded address, used as the node id.
+    pub fn id(&self) -> String {
+        hex::encode(self.address())
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        ecdsa_p256_sha256_sign_digest(&self.pkcs8, message)
+    }
+}
@@ -0,0 +1,111 @@
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{SinkExt, StreamExt};
+use tokio::net::TcpStream;
+use tokio::time::timeout;
+use tokio_util::codec::{Framed, LengthDelimitedCodec};
+
+use super::{Hello, P2pMessage};
+use crate::error::P2pError;
+use crate::utils::{deserialize, ecdsa_p256_sha256_sign_verify, serialize};
+use crate::{hash_pub_key, NodeKey, PeerId};
+
+/// Largest message a peer may send, enough for a full block.
+pub const MAX_FRAME_BYTES: usize = 32 * 1024 * 1024;
+/// How long a peer has to prove who it is before it is dropped.
+pub const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A TCP connection carrying length prefixed bincode messages.
+pub(crate) type Connection = Framed<TcpStream, LengthDelimitedCodec>;
+
+/// This node as it introduces itself to peers, and what it expects them to share.
+pub(crate) struct LocalNode {
+    pub node_key: NodeKey,
+    pub chain_id: String,
+    pub genesis_hash: Vec<u8>,
+}
+
+impl LocalNode {
+    pub fn id(&self) -> PeerId {
+        self.node_key.id()
+    }
+
+    fn check_hello(&self, hello: &Hello, expected: Option<&PeerId>) -> Result<(), P2pError> {
+        if hex::encode(hash_pub_key(&hello.public_key)) != hello.node_id {
+            return Err(P2pError::InvalidNodeId { peer: hello.node_id.clone() });
+        }
+        if hello.node_id == self.id() {
+            return Err(P2pError::SelfConnection);
+        }
+        if let Some(expected) = expected {
+            if *expected != hello.node_id {
+                return Err(P2pError::UnexpectedPeer { expected: expected.clone(), peer: hello.node_id.clone() });
+            }
+        }
+        if hello.chain_id != self.chain_id {
+            return Err(P2pError::ChainIdMismatch { chain_id: hello.chain_id.clone(), expected: self.chain_id.clone() });
+        }
+        if hello.genesis_hash != self.genesis_hash {
+            return Err(P2pError::GenesisMismatch { peer: hello.node_id.clone() });
+        }
+        Ok(())
+    }
+}
+
+/// Authenticates a fresh connection, in either direction. Both sides send a hello with a
+/// random nonce and then sign the other's nonce with their node key, so a peer can only
+/// claim the id its key hashes to. `expected` is the id a dialed peer must have.
+pub(crate) async fn handshake(node: &LocalNode, stream: TcpStream, expected: Option<&PeerId>) -> eyre::Result<(PeerId, Connection)> {
+    let codec = LengthDelimitedCodec::builder().max_frame_length(MAX_FRAME_BYTES).new_codec();
+    let mut conn = Framed::new(stream, codec);
+    match timeout(HANDSHAKE_TIMEOUT, exchange(node, &mut conn, expected)).await {
+        Ok(peer_id) => Ok((peer_id?, conn)),
+        Err(_) => Err(P2pError::HandshakeTimeout.into()),
+    }
+}
+
+async fn exchange(node: &LocalNode, conn: &mut Connection, expected: Option<&PeerId>) -> eyre::Result<PeerId> {
+    let ours = Hello {
+        node_id: node.id(),
+        public_key: node.node_key.public_key().to_vec(),
+        chain_id: node.chain_id.clone(),
+        genesis_hash: node.genesis_hash.clone(),
+        nonce: rand::random::<[u8; 32]>().to_vec(),
+    };
+    write_message(conn, &P2pMessage::Hello(ours.clone())).await?;
+
+    let theirs = match read_message(conn).await? {
+        Some(P2pMessage::Hello(hello)) => hello,
+        Some(_) => return Err(P2pError::UnexpectedMessage.into()),
+        None => return Err(P2pError::ConnectionClosed.into()),
+    };
+    node.check_hello(&theirs, expected)?;
+
+    let signature = node.node_key.sign(&theirs.auth_message(&ours.node_id));
+    write_message(conn, &P2pMessage::Auth { signature }).await?;
+
+    let signature = match read_message(conn).await? {
+        Some(P2pMessage::Auth { signature }) => signature,
+        Some(_) => return Err(P2pError::UnexpectedMessage.into()),
+        None => return Err(P2pError::ConnectionClosed.into()),
+    };
+    if !ecdsa_p256_sha256_sign_verify(&theirs.public_key, &signature, &ours.auth_message(&theirs.node_id)) {
+        return Err(P2pError::InvalidAuthSignature { peer: theirs.node_id }.into());
+    }
+
+    Ok(theirs.node_id)
+}
+
+pub(crate) async fn write_message(conn: &mut Connection, message: &P2pMessage) -> eyre::Result<()> {
+    conn.send(Bytes::from(serialize(message)?)).await?;
+    Ok(())
+}
+
+/// Next message from the peer, None once it closed the connection.
+pub(crate) async fn read_message(conn: &mut Connection) -> eyre::Result<Option<P2pMessage>> {
+    match conn.next().await {
+        Some(frame) => Ok(Some(deserialize(&frame?)?)),
+        None => Ok(None),
+    }
+}
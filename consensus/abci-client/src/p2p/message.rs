@@ -0,0 +1,39 @@
+use serde::{Deserialize, Serialize};
+
+use crate::PeerId;
+
+/// Everything sent between two nodes, one message per frame.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum P2pMessage {
+    /// First message on a connection, in both directions.
+    Hello(Hello),
+    /// Signature of the peer's nonce, the second message in both directions.
+    Auth { signature: Vec<u8> },
+    /// Payload of the consensus engine, opaque to the network.
+    Consensus(Vec<u8>),
+    /// A tx the sender accepted into its mempool.
+    Tx(Vec<u8>),
+}
+
+/// Who a node is and which chain it is on.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Hello {
+    pub node_id: PeerId,
+    pub public_key: Vec<u8>,
+    pub chain_id: String,
+    pub genesis_hash: Vec<u8>,
+    /// Random bytes the peer has to sign to prove it holds the node key.
+    pub nonce: Vec<u8>,
+}
+
+impl Hello {
+    /// What the node answering this hello signs: the nonce, bound to the signer and the chain.
+    pub fn auth_message(&self, signer: &PeerId) -> Vec<u8> {
+        let mut message = b"pow-abci-p2p".to_vec();
+        message.extend_from_slice(&self.nonce);
+        message.extend_from_slice(signer.as_bytes());
+        message.extend_from_slice(self.chain_id.as_bytes());
+        message.extend_from_slice(&self.genesis_hash);
+        message
+    }
+}
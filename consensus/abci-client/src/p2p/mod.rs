@@ -0,0 +1,6 @@
+mod message;
+mod handshake;
+mod network;
+
+pub use message::*;
+pub use network::*;
@@ -0,0 +1,340 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{channel, unbounded_channel, Receiver, Sender, UnboundedReceiver, UnboundedSender};
+use tokio::sync::Semaphore;
+use tokio::time::{sleep, Instant};
+
+use super::handshake::{handshake, read_message, write_message, Connection, LocalNode};
+use super::P2pMessage;
+use crate::error::P2pError;
+use crate::{NodeKey, PeerId, Target};
+
+const DIAL_BACKOFF_MIN: Duration = Duration::from_secs(1);
+const DIAL_BACKOFF_MAX: Duration = Duration::from_secs(30);
+
+/// Misbehavior score at which a peer is disconnected and banned.
+pub const BAN_SCORE: u32 = 100;
+/// How long a banned peer is refused.
+pub const BAN_DURATION: Duration = Duration::from_secs(600);
+/// Most inbound connections, handshaking or connected, the same default as CometBFT.
+pub const MAX_INBOUND_PEERS: usize = 40;
+/// Most messages waiting to be sent to one peer, more are dropped while it is slow.
+pub const PEER_SEND_QUEUE: usize = 1_000;
+
+/// A peer the node keeps a connection to, written `<node id>@<host>:<port>`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerAddress {
+    pub id: PeerId,
+    pub addr: String,
+}
+
+impl FromStr for PeerAddress {
+    type Err = P2pError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().split_once('@') {
+            Some((id, addr)) if !id.is_empty() && addr.contains(':') => Ok(Self {
+                id: id.to_lowercase(),
+                addr: addr.to_string(),
+            }),
+            _ => Err(P2pError::InvalidPeerAddress(s.to_string())),
+        }
+    }
+}
+
+/// What the network reports to the node.
+#[derive(Debug)]
+pub enum P2pEvent {
+    Connected(PeerId),
+    Disconnected(PeerId),
+    /// A consensus message or a tx from an authenticated peer.
+    Message { from: PeerId, message: P2pMessage },
+}
+
+/// A connected peer, as shown by the RPC.
+#[derive(Debug, Clone)]
+pub struct PeerInfo {
+    pub id: PeerId,
+    /// Whether we dialed the peer.
+    pub outbound: bool,
+    pub remote_addr: Option<SocketAddr>,
+}
+
+// the open connection to a peer, there is at most one per peer
+struct PeerConnection {
+    id: u64,
+    tx: Sender<P2pMessage>,
+    outbound: bool,
+    remote_addr: Option<SocketAddr>,
+}
+
+// what a peer has done wrong so far
+#[derive(Default)]
+struct PeerScore {
+    score: u32,
+    banned_until: Option<Instant>,
+}
+
+struct Shared {
+    node: LocalNode,
+    peers: Mutex<HashMap<PeerId, PeerConnection>>,
+    scores: Mutex<HashMap<PeerId, PeerScore>>,
+    next_connection_id: AtomicU64,
+    events: UnboundedSender<P2pEvent>,
+}
+
+/// Sends messages to the connected peers. Cheap to clone.
+#[derive(Clone)]
+pub struct P2pHandle {
+    shared: Arc<Shared>,
+}
+
+impl P2pHandle {
+    pub fn send(&self, target: &Target, message: P2pMessage) {
+        let peers = self.shared.peers.lock().unwrap();
+        match target {
+            Target::All => peers.iter().for_each(|(id, peer)| peer.send(id, message.clone())),
+            Target::AllExcept(excluded) => peers
+                .iter()
+                .filter(|(id, _)| *id != excluded)
+                .for_each(|(id, peer)| peer.send(id, message.clone())),
+            Target::Peer(id) => match peers.get(id) {
+                Some(peer) => peer.send(id, message),
+                None => log::debug!("peer {} is not connected, dropping message", id),
+            },
+        }
+    }
+
+    /// Adds to the misbehavior score of a peer. Once it reaches `BAN_SCORE` the peer is
+    /// disconnected and refused for `BAN_DURATION`.
+    pub fn penalize(&self, peer_id: &PeerId, score: u32, reason: &str) {
+        let mut scores = self.shared.scores.lock().unwrap();
+        let peer_score = scores.entry(peer_id.clone()).or_default();
+        peer_score.score += score;
+        log::warn!("peer {} penalized by {} to {}: {}", peer_id, score, peer_score.score, reason);
+        if peer_score.score < BAN_SCORE {
+            return;
+        }
+        log::warn!("banning peer {} for {:?}", peer_id, BAN_DURATION);
+        *peer_score = PeerScore {
+            score: 0,
+            banned_until: Some(Instant::now() + BAN_DURATION),
+        };
+        // dropping the sender ends the connection
+        self.shared.peers.lock().unwrap().remove(peer_id);
+        let _ = self.shared.events.send(P2pEvent::Disconnected(peer_id.clone()));
+    }
+
+    /// Ids of the peers connected right now.
+    pub fn peers(&self) -> Vec<PeerId> {
+        self.shared.peers.lock().unwrap().keys().cloned().collect()
+    }
+
+    /// The peers connected right now and their connections.
+    pub fn peer_info(&self) -> Vec<PeerInfo> {
+        self.shared
+            .peers
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, peer)| PeerInfo { id: id.clone(), outbound: peer.outbound, remote_addr: peer.remote_addr })
+            .collect()
+    }
+}
+
+/// Starts accepting peers on `laddr` and dialing the persistent peers, redialing them
+/// whenever their connection drops. Only peers on the same chain with the same genesis
+/// get through the handshake.
+pub async fn start(
+    node_key: NodeKey,
+    chain_id: String,
+    genesis_hash: Vec<u8>,
+    laddr: SocketAddr,
+    persistent_peers: Vec<PeerAddress>,
+) -> eyre::Result<(P2pHandle, UnboundedReceiver<P2pEvent>)> {
+    let listener = TcpListener::bind(laddr).await?;
+    log::info!("p2p listening on {:?}", laddr);
+
+    let (events, rx_events) = unbounded_channel();
+    let shared = Arc::new(Shared {
+        node: LocalNode { node_key, chain_id, genesis_hash },
+        peers: Mutex::new(HashMap::new()),
+        scores: Mutex::new(HashMap::new()),
+        next_connection_id: AtomicU64::new(0),
+        events,
+    });
+
+    tokio::spawn(accept_peers(shared.clone(), listener));
+    for peer in persistent_peers {
+        if peer.id == shared.node.id() {
+            continue;
+        }
+        tokio::spawn(dial_peer(shared.clone(), peer));
+    }
+
+    Ok((P2pHandle { shared }, rx_events))
+}
+
+async fn accept_peers(shared: Arc<Shared>, listener: TcpListener) {
+    // a slot is taken from the handshake until the peer disconnects
+    let slots = Arc::new(Semaphore::new(MAX_INBOUND_PEERS));
+    loop {
+        let (stream, addr) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(err) => {
+                log::warn!("failed to accept a peer: {}", err);
+                continue;
+            }
+        };
+        let slot = match slots.clone().try_acquire_owned() {
+            Ok(slot) => slot,
+            Err(_) => {
+                log::debug!("{} inbound peers already, refusing {}", MAX_INBOUND_PEERS, addr);
+                continue;
+            }
+        };
+        let shared = shared.clone();
+        tokio::spawn(async move {
+            match handshake(&shared.node, stream, None).await {
+                Ok((peer_id, conn)) => run_peer(shared, peer_id, conn, false).await,
+                Err(err) => log::warn!("handshake with {} failed: {}", addr, err),
+            }
+            drop(slot);
+        });
+    }
+}
+
+// keeps a connection to a persistent peer, backing off while it is unreachable
+async fn dial_peer(shared: Arc<Shared>, peer: PeerAddress) {
+    let mut backoff = DIAL_BACKOFF_MIN;
+    loop {
+        if !shared.peers.lock().unwrap().contains_key(&peer.id) {
+            let result = match TcpStream::connect(&peer.addr).await {
+                Ok(stream) => handshake(&shared.node, stream, Some(&peer.id)).await,
+                Err(err) => Err(err.into()),
+            };
+            match result {
+                Ok((peer_id, conn)) => {
+                    backoff = DIAL_BACKOFF_MIN;
+                    run_peer(shared.clone(), peer_id, conn, true).await;
+                }
+                Err(err) => {
+                    log::warn!("failed to connect to {}@{}: {}", peer.id, peer.addr, err);
+                    backoff = (backoff * 2).min(DIAL_BACKOFF_MAX);
+                }
+            }
+        }
+        sleep(backoff).await;
+    }
+}
+
+// moves messages between an authenticated peer and the node until either side drops
+async fn run_peer(shared: Arc<Shared>, peer_id: PeerId, mut conn: Connection, outbound: bool) {
+    let remote_addr = conn.get_ref().peer_addr().ok();
+    let (connection_id, mut rx) = match shared.register(&peer_id, outbound, remote_addr) {
+        Some(registered) => registered,
+        None => {
+            log::debug!("{} is banned or already connected, dropping the new connection", peer_id);
+            return;
+        }
+    };
+
+    loop {
+        tokio::select! {
+            message = rx.recv() => match message {
+                Some(message) => {
+                    if let Err(err) = write_message(&mut conn, &message).await {
+                        log::warn!("failed to send to {}: {}", peer_id, err);
+                        break;
+                    }
+                }
+                // another connection to the peer took over
+                None => break,
+            },
+            // cancel safe, a partly read frame stays buffered in the connection
+            message = read_message(&mut conn) => {
+                let message = match message {
+                    Ok(Some(message)) => message,
+                    Ok(None) => break,
+                    Err(err) => {
+                        log::warn!("dropping peer {}: {}", peer_id, err);
+                        break;
+                    }
+                };
+                match message {
+                    P2pMessage::Consensus(_) | P2pMessage::Tx(_) => {
+                        let _ = shared.events.send(P2pEvent::Message { from: peer_id.clone(), message });
+                    }
+                    P2pMessage::Hello(_) | P2pMessage::Auth { .. } => {
+                        log::warn!("dropping peer {}: {}", peer_id, P2pError::UnexpectedMessage);
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    shared.unregister(&peer_id, connection_id);
+}
+
+impl PeerConnection {
+    fn send(&self, peer_id: &PeerId, message: P2pMessage) {
+        match self.tx.try_send(message) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => log::warn!("peer {} is too slow, dropping a message to it", peer_id),
+            // the connection is closing
+            Err(TrySendError::Closed(_)) => {}
+        }
+    }
+}
+
+impl Shared {
+    // Both nodes may dial each other at once. Each then keeps the connection dialed by
+    // the node with the lower id, so they end up agreeing on one.
+    fn register(
+        &self,
+        peer_id: &PeerId,
+        outbound: bool,
+        remote_addr: Option<SocketAddr>,
+    ) -> Option<(u64, Receiver<P2pMessage>)> {
+        let our_id = self.node.id();
+        let dialed_by_lower = if outbound { our_id < *peer_id } else { *peer_id < our_id };
+
+        if self.is_banned(peer_id) {
+            return None;
+        }
+        let mut peers = self.peers.lock().unwrap();
+        if peers.contains_key(peer_id) && !dialed_by_lower {
+            return None;
+        }
+        let (tx, rx) = channel(PEER_SEND_QUEUE);
+        let id = self.next_connection_id.fetch_add(1, Ordering::Relaxed);
+        if peers.insert(peer_id.clone(), PeerConnection { id, tx, outbound, remote_addr }).is_none() {
+            log::info!("peer {} connected", peer_id);
+            let _ = self.events.send(P2pEvent::Connected(peer_id.clone()));
+        }
+        Some((id, rx))
+    }
+
+    fn is_banned(&self, peer_id: &PeerId) -> bool {
+        let scores = self.scores.lock().unwrap();
+        let banned_until = scores.get(peer_id).and_then(|peer_score| peer_score.banned_until);
+        banned_until.map_or(false, |banned_until| Instant::now() < banned_until)
+    }
+
+    fn unregister(&self, peer_id: &PeerId, connection_id: u64) {
+        let mut peers = self.peers.lock().unwrap();
+        if peers.get(peer_id).map_or(false, |peer| peer.id == connection_id) {
+            peers.remove(peer_id);
+            log::info!("peer {} disconnected", peer_id);
+            let _ = self.events.send(P2pEvent::Disconnected(peer_id.clone()));
+        }
+    }
+}
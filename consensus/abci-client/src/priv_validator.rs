@@ -0,0 +1,77 @@
+use std::fs;
+use std::path::Path;
+
+use eyre::WrapErr;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{ed25519_public_key, ed25519_sign, new_ed25519_seed, sha256_digest};
+use crate::GenesisPubKey;
+
+const PUB_KEY_TYPE: &str = "tendermint/PubKeyEd25519";
+const PRIV_KEY_TYPE: &str = "tendermint/PrivKeyEd25519";
+
+/// Ed25519 key the node votes with as a validator, in the same JSON format as
+/// CometBFT's `priv_validator_key.json`, so the key of an existing validator can be reused.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct PrivValidatorKey {
+    pub address: String,
+    pub pub_key: GenesisPubKey,
+    // base64 of the 32 bytes seed followed by the public key
+    pub priv_key: GenesisPubKey,
+}
+
+impl PrivValidatorKey {
+    pub fn generate() -> Self {
+        let seed = new_ed25519_seed();
+        let public_key = ed25519_public_key(&seed);
+        let address = hex::encode_upper(&sha256_digest(&public_key)[..20]);
+
+        Self {
+            address,
+            pub_key: GenesisPubKey {
+                key_type: PUB_KEY_TYPE.to_string(),
+                value: base64::encode(&public_key),
+            },
+            priv_key: GenesisPubKey {
+                key_type: PRIV_KEY_TYPE.to_string(),
+                value: base64::encode([seed, public_key].concat()),
+            },
+        }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        let path = path.as_ref();
+        let content = fs::read(path).wrap_err_with(|| format!("failed to read validator key {:?}", path))?;
+        let key: PrivValidatorKey = serde_json::from_slice(&content)
+            .wrap_err_with(|| format!("failed to parse validator key {:?}", path))?;
+        if key.priv_key.key_type != PRIV_KEY_TYPE {
+            eyre::bail!("unsupported validator key type {:?} in {:?}, only ed25519 is", key.priv_key.key_type, path);
+        }
+        let priv_key = base64::decode(&key.priv_key.value)
+            .wrap_err_with(|| format!("invalid private key in {:?}", path))?;
+        if priv_key.len() != 64 {
+            eyre::bail!("invalid private key in {:?}, expected 64 bytes", path);
+        }
+        Ok(key)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> eyre::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    pub fn public_key(&self) -> Vec<u8> {
+        base64::decode(&self.pub_key.value).unwrap_or_default()
+    }
+
+    /// Tendermint address of the validator, the first 20 bytes of the public key's sha256.
+    pub fn address(&self) -> Vec<u8> {
+        sha256_digest(&self.public_key())[..20].to_vec()
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        // checked in `load`
+        let priv_key = base64::decode(&self.priv_key.value).unwrap();
+        ed25519_sign(&priv_key[..32], message)
+    }
+}
@@ -0,0 +1,255 @@
+use base64::encode;
+use chrono::{SecondsFormat, TimeZone, Utc};
+use serde_json::{json, Value};
+use tendermint_proto::abci::{ResponseCheckTx, ResponseDeliverTx, ResponseQuery};
+use tendermint_proto::crypto::ProofOps;
+
+use crate::error::RpcError;
+use crate::{Block, BlockResults, ChainState, Commit, EndBlockResult, EventRecord, TxResult, ValidatorUpdateRecord, BLOCK_PROTOCOL};
+
+// Builders of the JSON shapes of CometBFT v0.34's RPC: int64 values as strings, hashes
+// as upper case hex, bytes as base64 and times as RFC 3339.
+
+/// A JSON-RPC response with the result of a method, or its error as CometBFT reports them.
+pub fn response_json(id: &Value, result: Result<Value, RpcError>) -> Value {
+    match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(err) => {
+            let (code, message) = match err {
+                RpcError::InvalidParams(_) | RpcError::InvalidQuery { .. } => (-32602, "Invalid params"),
+                _ => (-32603, "Internal error"),
+            };
+            json!({
+                "jsonrpc": "2.0",
+                "error": { "code": code, "message": message, "data": err.to_string() },
+                "id": id,
+            })
+        }
+    }
+}
+
+/// Unix nanoseconds as an RFC 3339 time.
+pub fn rfc3339(nanos: i64) -> String {
+    Utc.timestamp_nanos(nanos).to_rfc3339_opts(SecondsFormat::Nanos, true)
+}
+
+/// Blocks are not split in parts here, so the part set is always empty.
+pub fn block_id_json(hash: &[u8]) -> Value {
+    json!({
+        "hash": hex::encode_upper(hash),
+        "parts": { "total": 0, "hash": "" },
+    })
+}
+
+/// Hashes the node does not track (validators, consensus params, results, evidence)
+/// are left empty.
+pub fn header_json(block: &Block, state: &ChainState) -> Value {
+    let header = block.get_header();
+    // the same fallback as the header sent to the app in BeginBlock
+    let proposer = if header.proposer.is_empty() { state.proposer_address() } else { header.proposer.clone() };
+    json!({
+        "version": { "block": BLOCK_PROTOCOL.to_string(), "app": state.app_version.to_string() },
+        "chain_id": state.chain_id,
+        "height": header.height.to_string(),
+        "time": rfc3339(header.timestamp),
+        "last_block_id": block_id_json(&header.prev_hash),
+        "last_commit_hash": hex::encode_upper(&header.last_commit_hash),
+        "data_hash": hex::encode_upper(&header.txs_hash),
+        "validators_hash": "",
+        "next_validators_hash": "",
+        "consensus_hash": "",
+        "app_hash": hex::encode_upper(&header.app_hash),
+        "last_results_hash": "",
+        "evidence_hash": "",
+        "proposer_address": hex::encode_upper(&proposer),
+    })
+}
+
+/// Votes committing the block at `height`, no signatures for engines without votes.
+pub fn commit_json(commit: Option<&Commit>, height: i64, block_hash: &[u8]) -> Value {
+    let signatures: Vec<Value> = commit.map_or(vec![], |commit| {
+        commit
+            .signatures
+            .iter()
+            .map(|sig| {
+                if sig.signature.is_empty() {
+                    // BlockIDFlagAbsent
+                    json!({ "block_id_flag": 1, "validator_address": "", "timestamp": rfc3339(0), "signature": null })
+                } else {
+                    // BlockIDFlagCommit
+                    json!({
+                        "block_id_flag": 2,
+                        "validator_address": hex::encode_upper(&sig.validator_address),
+                        "timestamp": rfc3339(sig.timestamp),
+                        "signature": encode(&sig.signature),
+                    })
+                }
+            })
+            .collect()
+    });
+    json!({
+        "height": height.to_string(),
+        "round": commit.map_or(0, |commit| commit.round),
+        "block_id": block_id_json(block_hash),
+        "signatures": signatures,
+    })
+}
+
+pub fn block_json(block: &Block, state: &ChainState) -> Value {
+    let header = block.get_header();
+    json!({
+        "header": header_json(block, state),
+        "data": { "txs": block.get_txs().iter().map(encode).collect::<Vec<_>>() },
+        "evidence": { "evidence": [] },
+        "last_commit": commit_json(block.get_last_commit(), header.height - 1, &header.prev_hash),
+    })
+}
+
+/// Header, id and size of a block, as listed by `blockchain`.
+pub fn block_meta_json(block: &Block, state: &ChainState) -> Value {
+    let size: usize = block.get_txs().iter().map(|tx| tx.len()).sum();
+    json!({
+        "block_id": block_id_json(&block.get_hash()),
+        "block_size": size.to_string(),
+        "header": header_json(block, state),
+        "num_txs": block.get_txs().len().to_string(),
+    })
+}
+
+pub fn events_json(events: &[EventRecord]) -> Value {
+    let events: Vec<Value> = events
+        .iter()
+        .map(|event| {
+            json!({
+                "type": event.r#type,
+                "attributes": event.attributes.iter().map(|attribute| json!({
+                    "key": encode(&attribute.key),
+                    "value": encode(&attribute.value),
+                    "index": attribute.index,
+                })).collect::<Vec<_>>(),
+            })
+        })
+        .collect();
+    Value::Array(events)
+}
+
+pub fn tx_result_json(result: &TxResult) -> Value {
+    json!({
+        "code": result.code,
+        "data": encode(&result.data),
+        "log": result.log,
+        "info": result.info,
+        "gas_wanted": result.gas_wanted.to_string(),
+        "gas_used": result.gas_used.to_string(),
+        "events": events_json(&result.events),
+        "codespace": result.codespace,
+    })
+}
+
+pub fn pub_key_json(pub_key_type: &str, pub_key: &[u8]) -> Value {
+    let amino_type = match pub_key_type {
+        "secp256k1" => "tendermint/PubKeySecp256k1",
+        _ => "tendermint/PubKeyEd25519",
+    };
+    json!({ "type": amino_type, "value": encode(pub_key) })
+}
+
+pub fn validator_json(validator: &ValidatorUpdateRecord) -> Value {
+    json!({
+        "address": hex::encode_upper(validator.address()),
+        "pub_key": pub_key_json(&validator.pub_key_type, &validator.pub_key),
+        "voting_power": validator.power.to_string(),
+        "proposer_priority": "0",
+    })
+}
+
+pub fn end_block_json(end_block: &EndBlockResult) -> Value {
+    json!({
+        "validator_updates": end_block.validator_updates.iter().map(|update| json!({
+            "pub_key": pub_key_json(&update.pub_key_type, &update.pub_key),
+            "power": update.power.to_string(),
+        })).collect::<Vec<_>>(),
+        "consensus_param_updates": end_block.block_params_update.as_ref().map(|params| json!({
+            "block": { "max_bytes": params.max_bytes.to_string(), "max_gas": params.max_gas.to_string() },
+        })),
+        "events": events_json(&end_block.events),
+    })
+}
+
+pub fn block_results_json(results: &BlockResults) -> Value {
+    let end_block = end_block_json(&results.end_block);
+    json!({
+        "height": results.height.to_string(),
+        "txs_results": results.deliver_txs.iter().map(tx_result_json).collect::<Vec<_>>(),
+        "begin_block_events": events_json(&results.begin_block_events),
+        "end_block_events": end_block["events"],
+        "validator_updates": end_block["validator_updates"],
+        "consensus_param_updates": end_block["consensus_param_updates"],
+    })
+}
+
+/// Merkle proof of a query result, `null` when the app returns none.
+pub fn proof_ops_json(proof_ops: Option<&ProofOps>) -> Value {
+    match proof_ops {
+        Some(proof_ops) => json!({
+            "ops": proof_ops.ops.iter().map(|op| json!({
+                "type": op.r#type,
+                "key": encode(&op.key),
+                "data": encode(&op.data),
+            })).collect::<Vec<_>>(),
+        }),
+        None => Value::Null,
+    }
+}
+
+pub fn query_response_json(resp: &ResponseQuery) -> Value {
+    json!({
+        "response": {
+            "code": resp.code,
+            "log": resp.log,
+            "info": resp.info,
+            "index": resp.index.to_string(),
+            "key": encode(&resp.key),
+            "value": encode(&resp.value),
+            "proofOps": proof_ops_json(resp.proof_ops.as_ref()),
+            "height": resp.height.to_string(),
+            "codespace": resp.codespace,
+        },
+    })
+}
+
+/// Result of `broadcast_tx_sync`, or of `broadcast_tx_async` with no CheckTx result yet.
+pub fn broadcast_tx_json(hash: &[u8], check_tx: Option<&ResponseCheckTx>) -> Value {
+    json!({
+        "code": check_tx.map_or(0, |resp| resp.code),
+        "data": encode(check_tx.map_or(&[][..], |resp| &resp.data[..])),
+        "log": check_tx.map_or("", |resp| resp.log.as_str()),
+        "codespace": check_tx.map_or("", |resp| resp.codespace.as_str()),
+        "hash": hex::encode_upper(hash),
+    })
+}
+
+pub fn check_tx_json(resp: &ResponseCheckTx) -> Value {
+    json!({
+        "code": resp.code,
+        "data": encode(&resp.data),
+        "log": resp.log,
+        "info": resp.info,
+        "gas_wanted": resp.gas_wanted.to_string(),
+        "gas_used": resp.gas_used.to_string(),
+        "events": events_json(&resp.events.iter().map(EventRecord::from).collect::<Vec<_>>()),
+        "codespace": resp.codespace,
+    })
+}
+
+/// Result of `broadcast_tx_commit`, with an empty DeliverTx result and height 0 for a
+/// tx that never made it into a block.
+pub fn broadcast_tx_commit_json(hash: &[u8], check_tx: &ResponseCheckTx, delivered: Option<(i64, ResponseDeliverTx)>) -> Value {
+    let (height, deliver_tx) = delivered.unwrap_or_default();
+    json!({
+        "check_tx": check_tx_json(check_tx),
+        "deliver_tx": tx_result_json(&TxResult::from(&deliver_tx)),
+        "hash": hex::encode_upper(hash),
+        "height": height.to_string(),
+    })
+}
@@ -0,0 +1,398 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use base64::encode;
+use serde_json::{json, Value};
+
+use super::{add_events, block_json, block_id_json, block_meta_json, block_results_json, commit_json, header_json};
+use super::{pub_key_json, rfc3339, tx_result_json, validator_json, EventMap, Query};
+use crate::error::RpcError;
+use crate::p2p::P2pHandle;
+use crate::{Block, BlockStore, ChainState, ChainStatus, Genesis, PrivValidatorKey, QueryInfo, TxResult, BLOCK_PROTOCOL};
+
+/// CometBFT version whose RPC the node serves. Clients such as tendermint-rs pick the
+/// protocol they speak from the version in `status`.
+pub const COMETBFT_VERSION: &str = "0.34.27";
+/// P2P protocol version reported in `status`, the one of CometBFT v0.34.
+pub const P2P_PROTOCOL: u64 = 8;
+/// Default page size of the paginated methods.
+pub const DEFAULT_PER_PAGE: usize = 30;
+/// Largest page size of the paginated methods.
+pub const MAX_PER_PAGE: usize = 100;
+/// Most block metas `blockchain` returns at once.
+pub const MAX_BLOCKCHAIN_METAS: i64 = 20;
+
+/// Who the node is, as reported in `status` and `net_info`.
+#[derive(Debug, Clone)]
+pub struct NodeInfo {
+    pub id: String,
+    pub moniker: String,
+    pub p2p_laddr: SocketAddr,
+    pub rpc_laddr: SocketAddr,
+}
+
+/// Read access to the node's chain for the RPC methods that do not go through the app.
+#[derive(Clone)]
+pub struct RpcNode {
+    pub store: Arc<dyn BlockStore>,
+    pub genesis: Genesis,
+    pub node_info: NodeInfo,
+    /// Validator key of the node, for the engines that have one.
+    pub validator: Option<PrivValidatorKey>,
+    /// The peers, None for a node on its own.
+    pub p2p: Option<P2pHandle>,
+}
+
+impl RpcNode {
+    fn state(&self) -> Result<ChainState, RpcError> {
+        Ok(self.store.load_state()?.unwrap_or_default())
+    }
+
+    fn lowest_height(&self) -> i64 {
+        self.genesis.initial_height
+    }
+
+    // the requested height, the last final block if none
+    fn height(&self, status: &ChainStatus, requested: Option<i64>) -> Result<i64, RpcError> {
+        let latest = status.finalized_height;
+        let height = requested.unwrap_or(latest);
+        if height > latest {
+            return Err(RpcError::HeightTooHigh { height, latest });
+        }
+        if height < self.lowest_height() {
+            return Err(RpcError::HeightNotAvailable { height, lowest: self.lowest_height() });
+        }
+        Ok(height)
+    }
+
+    fn load_block(&self, height: i64) -> Result<Block, RpcError> {
+        self.store
+            .load_block(height)?
+            .ok_or(RpcError::HeightNotAvailable { height, lowest: self.lowest_height() })
+    }
+
+    pub fn status(&self, status: &ChainStatus) -> Result<Value, RpcError> {
+        let state = self.state()?;
+        let latest = self.store.load_block(status.finalized_height)?;
+        let earliest = self.store.load_block(self.lowest_height())?;
+        let validator_info = match &self.validator {
+            Some(key) => {
+                let power = state
+                    .validators
+                    .iter()
+                    .find(|validator| validator.address() == key.address())
+                    .map_or(0, |validator| validator.power);
+                json!({
+                    "address": hex::encode_upper(key.address()),
+                    "pub_key": pub_key_json("ed25519", &key.public_key()),
+                    "voting_power": power.to_string(),
+                })
+            }
+            None => json!({ "address": "", "pub_key": null, "voting_power": "0" }),
+        };
+        let node = &self.node_info;
+        Ok(json!({
+            "node_info": {
+                "protocol_version": {
+                    "p2p": P2P_PROTOCOL.to_string(),
+                    "block": BLOCK_PROTOCOL.to_string(),
+                    "app": status.app_info.app_version.to_string(),
+                },
+                "id": node.id,
+                "listen_addr": format!("tcp://{}", node.p2p_laddr),
+                "network": self.genesis.chain_id,
+                "version": COMETBFT_VERSION,
+                "channels": "",
+                "moniker": node.moniker,
+                "other": { "tx_index": "on", "rpc_address": format!("tcp://{}", node.rpc_laddr) },
+            },
+            // the latest block is the last final one, which the app has executed, as in
+            // Tendermint; the tip may be further ahead
+            "sync_info": {
+                "latest_block_hash": hex::encode_upper(&status.finalized_hash),
+                "latest_app_hash": hex::encode_upper(&status.app_hash),
+                "latest_block_height": status.finalized_height.to_string(),
+                "latest_block_time": rfc3339(latest.as_ref().map_or(0, |block| block.get_header().timestamp)),
+                "earliest_block_hash": hex::encode_upper(earliest.as_ref().map_or(vec![], |block| block.get_hash())),
+                "earliest_app_hash": hex::encode_upper(earliest.as_ref().map_or(vec![], |block| block.get_header().app_hash.clone())),
+                "earliest_block_height": earliest.as_ref().map_or(0, |block| block.get_height()).to_string(),
+                "earliest_block_time": rfc3339(earliest.as_ref().map_or(0, |block| block.get_header().timestamp)),
+                "finalized_block_height": status.finalized_height.to_string(),
+                "tip_block_hash": hex::encode_upper(&status.tip_hash),
+                "tip_block_height": status.tip_height.to_string(),
+                "catching_up": false,
+            },
+            "validator_info": validator_info,
+        }))
+    }
+
+    /// The app's `Info` from the handshake, at the height the app has since committed.
+    pub fn abci_info(&self, status: &ChainStatus) -> Result<Value, RpcError> {
+        Ok(json!({
+            "response": {
+                "data": status.app_info.data,
+                "version": status.app_info.version,
+                "app_version": status.app_info.app_version.to_string(),
+                "last_block_height": status.finalized_height.to_string(),
+                "last_block_app_hash": encode(&status.app_hash),
+            },
+        }))
+    }
+
+    pub fn block(&self, status: &ChainStatus, params: &Value) -> Result<Value, RpcError> {
+        let height = self.height(status, height_param(params, "height")?)?;
+        let block = self.load_block(height)?;
+        Ok(json!({
+            "block_id": block_id_json(&block.get_hash()),
+            "block": block_json(&block, &self.state()?),
+        }))
+    }
+
+    pub fn block_results(&self, status: &ChainStatus, params: &Value) -> Result<Value, RpcError> {
+        let height = self.height(status, height_param(params, "height")?)?;
+        let results = self
+            .store
+            .load_results(height)?
+            .ok_or(RpcError::HeightNotAvailable { height, lowest: self.lowest_height() })?;
+        Ok(block_results_json(&results))
+    }
+
+    /// Metas of the blocks from `maxHeight` down to `minHeight`, at most 20 of them.
+    pub fn blockchain(&self, status: &ChainStatus, params: &Value) -> Result<Value, RpcError> {
+        let latest = status.finalized_height;
+        let max = height_param(params, "maxHeight")?.filter(|height| *height > 0).unwrap_or(latest).min(latest);
+        let min = height_param(params, "minHeight")?
+            .filter(|height| *height > 0)
+            .unwrap_or(self.lowest_height())
+            .max(self.lowest_height())
+            .max(max - MAX_BLOCKCHAIN_METAS + 1);
+        if min > max && latest > 0 {
+            return Err(RpcError::InvalidParams(format!("min height {} can't be greater than max height {}", min, max)));
+        }
+
+        let state = self.state()?;
+        let mut metas = vec![];
+        for height in (min..=max).rev() {
+            metas.push(block_meta_json(&self.load_block(height)?, &state));
+        }
+        Ok(json!({
+            "last_height": latest.to_string(),
+            "block_metas": metas,
+        }))
+    }
+
+    /// The header and commit of a block. The commit carried by the next block is
+    /// canonical, the one this node saw for the latest block is not yet.
+    pub fn commit(&self, status: &ChainStatus, params: &Value) -> Result<Value, RpcError> {
+        let height = self.height(status, height_param(params, "height")?)?;
+        let block = self.load_block(height)?;
+        let next = self.store.load_block(height + 1)?;
+        let (commit, canonical) = match next.as_ref().and_then(|next| next.get_last_commit()) {
+            Some(commit) => (Some(commit.clone()), true),
+            None => (self.store.load_commit(height)?, next.is_some()),
+        };
+        Ok(json!({
+            "signed_header": {
+                "header": header_json(&block, &self.state()?),
+                "commit": commit_json(commit.as_ref(), height, &block.get_hash()),
+            },
+            "canonical": canonical,
+        }))
+    }
+
+    /// The validator set that votes on a height, from the sets the store keeps per height.
+    pub fn validators(&self, status: &ChainStatus, params: &Value) -> Result<Value, RpcError> {
+        let height = self.height(status, height_param(params, "height")?)?;
+        // a node that ran before the store kept the sets has none for its older heights
+        let set = self
+            .store
+            .load_validators(height)?
+            .ok_or(RpcError::HeightNotAvailable { height, lowest: self.lowest_height() })?;
+        let (page, per_page) = page_params(params)?;
+        let validators: Vec<Value> = set
+            .iter()
+            .skip((page - 1) * per_page)
+            .take(per_page)
+            .map(validator_json)
+            .collect();
+        Ok(json!({
+            "block_height": height.to_string(),
+            "count": validators.len().to_string(),
+            "total": set.len().to_string(),
+            "validators": validators,
+        }))
+    }
+
+    pub fn genesis(&self) -> Result<Value, RpcError> {
+        Ok(json!({ "genesis": self.genesis }))
+    }
+
+    pub fn health(&self) -> Result<Value, RpcError> {
+        Ok(json!({}))
+    }
+
+    pub fn net_info(&self) -> Result<Value, RpcError> {
+        let peers = self.p2p.as_ref().map_or(vec![], |p2p| p2p.peer_info());
+        let peers: Vec<Value> = peers
+            .iter()
+            .map(|peer| {
+                json!({
+                    "node_info": { "id": peer.id, "network": self.genesis.chain_id },
+                    "is_outbound": peer.outbound,
+                    "connection_status": {},
+                    "remote_ip": peer.remote_addr.map_or(String::new(), |addr| addr.ip().to_string()),
+                })
+            })
+            .collect();
+        Ok(json!({
+            "listening": self.p2p.is_some(),
+            "listeners": [format!("Listener(@tcp://{})", self.node_info.p2p_laddr)],
+            "n_peers": peers.len().to_string(),
+            "peers": peers,
+        }))
+    }
+
+    /// A committed tx by its hash, given as base64 like CometBFT or as hex.
+    pub fn tx(&self, params: &Value) -> Result<Value, RpcError> {
+        let hash = params["hash"].as_str().ok_or_else(|| RpcError::InvalidParams("missing hash".to_string()))?;
+        let hash = base64::decode(hash)
+            .ok()
+            .filter(|hash| hash.len() == 32)
+            .or_else(|| hex::decode(hash).ok())
+            .ok_or_else(|| RpcError::InvalidParams(format!("invalid tx hash {:?}", hash)))?;
+        let location = self
+            .store
+            .load_tx_location(&hash)?
+            .ok_or_else(|| RpcError::TxNotFound { hash: hex::encode_upper(&hash) })?;
+        let (tx, result) = self.tx_at(location.height, location.index)?;
+        Ok(tx_json(&hash, location.height, location.index, &tx, &result))
+    }
+
+    /// Committed txs whose events match a query, paginated and ordered by height.
+    ///
+    /// There is no event index: the block results are scanned in order, only the blocks at
+    /// the height a `tx.height=<h>` condition asks for when there is one. Every match is
+    /// counted for `total_count`, only the ones on the page are encoded.
+    pub fn tx_search(&self, status: &ChainStatus, params: &Value) -> Result<Value, RpcError> {
+        let query: Query = params["query"]
+            .as_str()
+            .ok_or_else(|| RpcError::InvalidParams("missing query".to_string()))?
+            .parse()?;
+        let (page, per_page) = page_params(params)?;
+        let descending = match params["order_by"].as_str().unwrap_or("asc") {
+            "" | "asc" => false,
+            "desc" => true,
+            other => return Err(RpcError::InvalidParams(format!("invalid order_by {:?}", other))),
+        };
+
+        let (low, high) = match query.equal_value("tx.height").and_then(|height| height.parse().ok()) {
+            Some(height) => (height, height),
+            None => (self.lowest_height(), status.finalized_height),
+        };
+        let heights: Box<dyn Iterator<Item = i64>> =
+            if descending { Box::new((low..=high).rev()) } else { Box::new(low..=high) };
+        let skip = (page - 1) * per_page;
+        let mut total = 0;
+        let mut txs = vec![];
+        for height in heights {
+            let (block, results) = match (self.store.load_block(height)?, self.store.load_results(height)?) {
+                (Some(block), Some(results)) => (block, results),
+                _ => continue,
+            };
+            let mut block_txs: Vec<_> = block.get_txs().iter().zip(&results.deliver_txs).enumerate().collect();
+            if descending {
+                block_txs.reverse();
+            }
+            for (index, (tx, result)) in block_txs {
+                let hash = crate::tx_hash(tx);
+                if !query.matches(&tx_events(&hash, height, result)) {
+                    continue;
+                }
+                total += 1;
+                if total > skip && total <= skip + per_page {
+                    txs.push(tx_json(&hash, height, index as u32, tx, result));
+                }
+            }
+        }
+        Ok(json!({
+            "txs": txs,
+            "total_count": total.to_string(),
+        }))
+    }
+
+    fn tx_at(&self, height: i64, index: u32) -> Result<(Vec<u8>, TxResult), RpcError> {
+        let block = self.load_block(height)?;
+        let results = self
+            .store
+            .load_results(height)?
+            .ok_or(RpcError::HeightNotAvailable { height, lowest: self.lowest_height() })?;
+        let tx = block.get_txs().get(index as usize).cloned().unwrap_or_default();
+        let result = results.deliver_txs.get(index as usize).cloned().unwrap_or_default();
+        Ok((tx, result))
+    }
+}
+
+/// Events of a committed tx, as `tx_search` and the subscriptions match them.
+pub fn tx_events(hash: &[u8], height: i64, result: &TxResult) -> EventMap {
+    let mut events = EventMap::new();
+    events.insert("tm.event".to_string(), vec!["Tx".to_string()]);
+    events.insert("tx.hash".to_string(), vec![hex::encode_upper(hash)]);
+    events.insert("tx.height".to_string(), vec![height.to_string()]);
+    add_events(&mut events, &result.events);
+    events
+}
+
+/// The app query of an `abci_query` call: `data` is hex, and a `height` of 0 or none
+/// queries the latest state.
+pub fn query_request(params: &Value) -> Result<QueryInfo, RpcError> {
+    let path = params["path"].as_str().unwrap_or_default().to_string();
+    let data = params["data"].as_str().unwrap_or_default();
+    let data = hex::decode(data).map_err(|err| RpcError::InvalidParams(format!("data must be hex: {}", err)))?;
+    let height = match height_param(params, "height")? {
+        Some(height) if height < 0 => {
+            return Err(RpcError::InvalidParams(format!("height must be non negative, got {}", height)))
+        }
+        Some(0) | None => None,
+        Some(height) => Some(height as u64),
+    };
+    let prove = params["prove"].as_bool().unwrap_or_default();
+    Ok(QueryInfo { path: Some(path), data, height, prove })
+}
+
+/// The base64 `tx` of the broadcast methods.
+pub fn tx_param(params: &Value) -> Result<Vec<u8>, RpcError> {
+    let tx = params["tx"].as_str().ok_or_else(|| RpcError::InvalidParams("missing tx".to_string()))?;
+    base64::decode(tx).map_err(|err| RpcError::InvalidParams(format!("tx must be base64: {}", err)))
+}
+
+fn tx_json(hash: &[u8], height: i64, index: u32, tx: &[u8], result: &TxResult) -> Value {
+    json!({
+        "hash": hex::encode_upper(hash),
+        "height": height.to_string(),
+        "index": index,
+        "tx_result": tx_result_json(result),
+        "tx": encode(tx),
+    })
+}
+
+// a height given as a JSON string, like CometBFT clients send them, or as a number
+fn height_param(params: &Value, name: &str) -> Result<Option<i64>, RpcError> {
+    let invalid = || RpcError::InvalidParams(format!("invalid {}: {}", name, params[name]));
+    match &params[name] {
+        Value::Null => Ok(None),
+        Value::String(s) if s.is_empty() => Ok(None),
+        Value::String(s) => s.parse().map(Some).map_err(|_| invalid()),
+        Value::Number(n) => n.as_i64().map(Some).ok_or_else(invalid),
+        _ => Err(invalid()),
+    }
+}
+
+fn page_params(params: &Value) -> Result<(usize, usize), RpcError> {
+    let page = height_param(params, "page")?.unwrap_or(1);
+    if page < 1 {
+        return Err(RpcError::InvalidParams(format!("page must be at least 1, got {}", page)));
+    }
+    let per_page = height_param(params, "per_page")?.unwrap_or(DEFAULT_PER_PAGE as i64);
+    let per_page = per_page.clamp(1, MAX_PER_PAGE as i64);
+    Ok((page as usize, per_page as usize))
+}
@@ -0,0 +1,9 @@
+mod json;
+mod methods;
+mod query;
+mod websocket;
+
+pub use json::*;
+pub use methods::*;
+pub use query::*;
+pub use websocket::*;
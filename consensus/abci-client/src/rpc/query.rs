@@ -0,0 +1,296 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, Utc};
+
+use crate::error::RpcError;
+use crate::EventRecord;
+
+/// Values of the events of a block or a tx, keyed by `<event type>.<attribute key>`,
+/// plus the `tm.event`, `tx.hash` and `tx.height` keys CometBFT adds.
+pub type EventMap = HashMap<String, Vec<String>>;
+
+/// Adds the attributes of ABCI events to an event map.
+pub fn add_events(map: &mut EventMap, events: &[EventRecord]) {
+    for event in events {
+        for attribute in &event.attributes {
+            let key = format!("{}.{}", event.r#type, String::from_utf8_lossy(&attribute.key));
+            map.entry(key).or_default().push(String::from_utf8_lossy(&attribute.value).into_owned());
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Eq,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+    Exists,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Str(String),
+    Number(f64),
+    Time(DateTime<Utc>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Condition {
+    key: String,
+    op: Operator,
+    operand: Option<Operand>,
+}
+
+/// A query in CometBFT's event query language: conditions on event keys joined with
+/// `AND`, e.g. `tm.event='Tx' AND transfer.recipient='cosmos1...' AND tx.height>5`.
+///
+/// Operators are `=`, `<`, `<=`, `>`, `>=`, `CONTAINS` and `EXISTS`, and operands are
+/// quoted strings, numbers, `TIME 2006-01-02T15:04:05Z` or `DATE 2006-01-02`. In a
+/// string, a backslash escapes a quote or a backslash.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    conditions: Vec<Condition>,
+}
+
+impl Query {
+    /// Whether every condition holds for at least one value of its key.
+    pub fn matches(&self, events: &EventMap) -> bool {
+        self.conditions.iter().all(|condition| match events.get(&condition.key) {
+            Some(values) => condition.op == Operator::Exists || values.iter().any(|value| condition.matches(value)),
+            None => false,
+        })
+    }
+
+    /// The value a condition `key=<value>` requires, if the query has one.
+    pub fn equal_value(&self, key: &str) -> Option<String> {
+        self.conditions.iter().find_map(|condition| match (&condition.op, &condition.operand) {
+            (Operator::Eq, Some(Operand::Str(value))) if condition.key == key => Some(value.clone()),
+            (Operator::Eq, Some(Operand::Number(value))) if condition.key == key => Some(value.to_string()),
+            _ => None,
+        })
+    }
+}
+
+impl Condition {
+    fn matches(&self, value: &str) -> bool {
+        match (&self.op, &self.operand) {
+            (Operator::Exists, _) => true,
+            (Operator::Contains, Some(Operand::Str(operand))) => value.contains(operand.as_str()),
+            (Operator::Eq, Some(Operand::Str(operand))) => value == operand,
+            (op, Some(Operand::Number(operand))) => match value.parse::<f64>() {
+                Ok(value) => compare(*op, value.partial_cmp(operand)),
+                Err(_) => false,
+            },
+            (op, Some(Operand::Time(operand))) => match DateTime::parse_from_rfc3339(value) {
+                Ok(value) => compare(*op, value.with_timezone(&Utc).partial_cmp(operand)),
+                Err(_) => false,
+            },
+            _ => false,
+        }
+    }
+}
+
+fn compare(op: Operator, ordering: Option<std::cmp::Ordering>) -> bool {
+    use std::cmp::Ordering::*;
+    matches!(
+        (op, ordering),
+        (Operator::Eq, Some(Equal))
+            | (Operator::Lt, Some(Less))
+            | (Operator::Le, Some(Less | Equal))
+            | (Operator::Gt, Some(Greater))
+            | (Operator::Ge, Some(Greater | Equal))
+    )
+}
+
+// a token of a query: words are split on whitespace, quotes and operators
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Str(String),
+    Op(Operator),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        match c {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '\'' => {
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        // a backslash escapes the next char, a quote or a backslash
+                        Some('\\') => value.push(chars.next().ok_or("unterminated string")?),
+                        Some(c) => value.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            '<' | '>' | '=' => {
+                chars.next();
+                let or_equal = c != '=' && chars.next_if_eq(&'=').is_some();
+                tokens.push(Token::Op(match (c, or_equal) {
+                    ('<', false) => Operator::Lt,
+                    ('<', true) => Operator::Le,
+                    ('>', false) => Operator::Gt,
+                    ('>', true) => Operator::Ge,
+                    _ => Operator::Eq,
+                }));
+            }
+            _ => {
+                let mut word = String::new();
+                while let Some(c) = chars.next_if(|c| !(c.is_whitespace() || "'<>=".contains(*c))) {
+                    word.push(c);
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+impl FromStr for Query {
+    type Err = RpcError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| RpcError::InvalidQuery { query: s.to_string(), reason: reason.to_string() };
+        let is_key = |word: &str| word.chars().all(|c| c.is_ascii_alphanumeric() || "._-".contains(c));
+
+        let mut tokens = tokenize(s).map_err(|reason| invalid(&reason))?.into_iter();
+        let mut conditions = vec![];
+        loop {
+            let key = match tokens.next() {
+                Some(Token::Word(key)) if is_key(&key) => key,
+                _ => return Err(invalid("expected an event key")),
+            };
+            let op = match tokens.next() {
+                Some(Token::Op(op)) => op,
+                Some(Token::Word(word)) if word == "CONTAINS" => Operator::Contains,
+                Some(Token::Word(word)) if word == "EXISTS" => Operator::Exists,
+                _ => return Err(invalid(&format!("expected an operator after {}", key))),
+            };
+            let operand = match op {
+                Operator::Exists => None,
+                _ => Some(parse_operand(&mut tokens).ok_or_else(|| invalid(&format!("invalid operand for {}", key)))?),
+            };
+            if op == Operator::Contains && !matches!(operand, Some(Operand::Str(_))) {
+                return Err(invalid("CONTAINS needs a string"));
+            }
+            conditions.push(Condition { key, op, operand });
+
+            match tokens.next() {
+                None => break,
+                Some(Token::Word(word)) if word == "AND" => {}
+                _ => return Err(invalid("conditions must be joined with AND")),
+            }
+        }
+        Ok(Self { conditions })
+    }
+}
+
+// the operand of a condition, from the tokens after its operator
+fn parse_operand(tokens: &mut impl Iterator<Item = Token>) -> Option<Operand> {
+    let word = match tokens.next()? {
+        Token::Str(value) => return Some(Operand::Str(value)),
+        Token::Word(word) => word,
+        Token::Op(_) => return None,
+    };
+    let mut next_word = || match tokens.next() {
+        Some(Token::Word(word)) => Some(word),
+        _ => None,
+    };
+    match word.as_str() {
+        "TIME" => {
+            let time = DateTime::parse_from_rfc3339(&next_word()?).ok()?;
+            Some(Operand::Time(time.with_timezone(&Utc)))
+        }
+        "DATE" => {
+            let date = NaiveDate::parse_from_str(&next_word()?, "%Y-%m-%d").ok()?.and_hms_opt(0, 0, 0)?;
+            Some(Operand::Time(DateTime::from_utc(date, Utc)))
+        }
+        number => Some(Operand::Number(number.parse().ok()?)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events(pairs: &[(&str, &str)]) -> EventMap {
+        let mut events = EventMap::new();
+        for (key, value) in pairs {
+            events.entry(key.to_string()).or_default().push(value.to_string());
+        }
+        events
+    }
+
+    fn query(s: &str) -> Query {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn matches_conditions_joined_with_and() {
+        let query = query("tm.event='Tx' AND transfer.recipient='alice'");
+        assert!(query.matches(&events(&[("tm.event", "Tx"), ("transfer.recipient", "alice")])));
+        assert!(!query.matches(&events(&[("tm.event", "Tx"), ("transfer.recipient", "bob")])));
+        assert!(!query.matches(&events(&[("tm.event", "Tx")])));
+    }
+
+    #[test]
+    fn reads_quoted_strings_as_they_are() {
+        let query = query(r"memo.text='a AND b=c' AND memo.quote='it\'s \\ ok'");
+        assert_eq!(query.equal_value("memo.text"), Some("a AND b=c".to_string()));
+        assert_eq!(query.equal_value("memo.quote"), Some(r"it's \ ok".to_string()));
+        assert!(query.matches(&events(&[("memo.text", "a AND b=c"), ("memo.quote", r"it's \ ok")])));
+    }
+
+    #[test]
+    fn compares_numbers_and_times() {
+        let query = query("tx.height>5 AND tx.height<=7 AND block.time>=TIME 2021-01-01T00:00:00Z");
+        let at = |height: &str, time: &str| events(&[("tx.height", height), ("block.time", time)]);
+        assert!(query.matches(&at("6", "2021-01-01T00:00:00Z")));
+        assert!(query.matches(&at("7", "2022-06-01T12:00:00Z")));
+        assert!(!query.matches(&at("5", "2021-01-01T00:00:00Z")));
+        assert!(!query.matches(&at("8", "2021-01-01T00:00:00Z")));
+        assert!(!query.matches(&at("6", "2020-12-31T23:59:59Z")));
+
+        let query = self::query("block.time<DATE 2021-01-02 AND transfer.amount CONTAINS 'stake' AND fee EXISTS");
+        assert!(query.matches(&events(&[
+            ("block.time", "2021-01-01T23:00:00Z"),
+            ("transfer.amount", "100stake"),
+            ("fee", ""),
+        ])));
+        assert!(!query.matches(&events(&[("block.time", "2021-01-01T23:00:00Z"), ("transfer.amount", "100stake")])));
+    }
+
+    #[test]
+    fn rejects_malformed_queries() {
+        for malformed in [
+            "",
+            "tm.event",
+            "tm.event=",
+            "tm.event=='Tx'",
+            "tm.event='Tx",
+            "tm.event=Tx",
+            "tm.event='Tx' tx.height=5",
+            "tm.event='Tx' ANDtx.height=5",
+            "tm.event='Tx' AND",
+            "tm.event='Tx' and tx.height=5",
+            "transfer.amount CONTAINS 5",
+            "block.time>TIME yesterday",
+            "'tm.event'='Tx'",
+        ] {
+            assert!(malformed.parse::<Query>().is_err(), "{:?} parsed", malformed);
+        }
+    }
+}
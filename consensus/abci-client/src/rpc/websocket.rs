@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use base64::encode;
+use futures::{SinkExt, StreamExt};
+use serde_json::{json, Value};
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+use warp::ws::{Message, WebSocket};
+
+use super::{add_events, block_json, end_block_json, events_json, header_json, response_json, tx_events, tx_result_json};
+use super::{EventMap, Query};
+use crate::error::RpcError;
+use crate::{tx_hash, CommittedBlock};
+
+/// Most queries one websocket connection can subscribe to, as in CometBFT.
+pub const MAX_SUBSCRIPTIONS_PER_CLIENT: usize = 5;
+
+// a query of the connection, with the id of the `subscribe` call its events carry
+struct Subscription {
+    query: Query,
+    id: Value,
+}
+
+/// The NewBlock, NewBlockHeader and Tx events of a committed block, each with the keys
+/// queries match it on.
+pub fn block_events(committed: &CommittedBlock) -> Vec<(Value, EventMap)> {
+    let CommittedBlock { block, results, state } = committed;
+    let begin_block = json!({ "events": events_json(&results.begin_block_events) });
+    let end_block = end_block_json(&results.end_block);
+    let block_event = |name: &str| {
+        let mut events = EventMap::new();
+        events.insert("tm.event".to_string(), vec![name.to_string()]);
+        add_events(&mut events, &results.begin_block_events);
+        add_events(&mut events, &results.end_block.events);
+        events
+    };
+
+    let mut events = vec![
+        (
+            json!({
+                "type": "tendermint/event/NewBlock",
+                "value": {
+                    "block": block_json(block, state),
+                    "result_begin_block": begin_block,
+                    "result_end_block": end_block,
+                },
+            }),
+            block_event("NewBlock"),
+        ),
+        (
+            json!({
+                "type": "tendermint/event/NewBlockHeader",
+                "value": {
+                    "header": header_json(block, state),
+                    "num_txs": block.get_txs().len().to_string(),
+                    "result_begin_block": begin_block,
+                    "result_end_block": end_block,
+                },
+            }),
+            block_event("NewBlockHeader"),
+        ),
+    ];
+    for (index, (tx, result)) in block.get_txs().iter().zip(results.deliver_txs.iter()).enumerate() {
+        let data = json!({
+            "type": "tendermint/event/Tx",
+            "value": {
+                "TxResult": {
+                    "height": block.get_height().to_string(),
+                    "index": index,
+                    "tx": encode(tx),
+                    "result": tx_result_json(result),
+                },
+            },
+        });
+        events.push((data, tx_events(&tx_hash(tx), block.get_height(), result)));
+    }
+    events
+}
+
+/// Serves `subscribe`, `unsubscribe` and `unsubscribe_all` on a websocket connection, and
+/// pushes the events of every committed block that match one of its queries.
+pub async fn serve_websocket(socket: WebSocket, mut committed: Receiver<Arc<CommittedBlock>>) {
+    let (mut sink, mut stream) = socket.split();
+    let mut subscriptions: HashMap<String, Subscription> = HashMap::new();
+    loop {
+        let replies = tokio::select! {
+            msg = stream.next() => match msg {
+                Some(Ok(msg)) if msg.is_close() => break,
+                Some(Ok(msg)) => match msg.to_str() {
+                    Ok(text) => vec![handle_request(&mut subscriptions, text)],
+                    // pings are answered by warp, binary frames are not JSON-RPC
+                    Err(_) => continue,
+                },
+                Some(Err(err)) => {
+                    log::debug!("websocket connection failed: {}", err);
+                    break;
+                }
+                None => break,
+            },
+            block = committed.recv() => match block {
+                Ok(block) => notifications(&subscriptions, &block),
+                // CometBFT drops the subscriptions of clients that fall behind
+                Err(RecvError::Lagged(skipped)) => {
+                    log::warn!("websocket client fell {} blocks behind, cancelling its subscriptions", skipped);
+                    let cancelled = subscriptions
+                        .values()
+                        .map(|subscription| response_json(&event_id(&subscription.id), Err(RpcError::SubscriptionCancelled)))
+                        .collect();
+                    subscriptions.clear();
+                    cancelled
+                }
+                Err(RecvError::Closed) => break,
+            },
+        };
+        for reply in replies {
+            if sink.send(Message::text(reply.to_string())).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+fn handle_request(subscriptions: &mut HashMap<String, Subscription>, text: &str) -> Value {
+    let request: Value = match serde_json::from_str(text) {
+        Ok(request) => request,
+        Err(err) => {
+            return json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32700, "message": "Parse error", "data": err.to_string() },
+                "id": Value::Null,
+            })
+        }
+    };
+    let id = &request["id"];
+    let query = request["params"]["query"].as_str().unwrap_or_default().to_string();
+    let result = match request["method"].as_str().unwrap_or_default() {
+        "subscribe" => subscribe(subscriptions, query, id),
+        "unsubscribe" => match subscriptions.remove(&query) {
+            Some(_) => Ok(json!({})),
+            None => Err(RpcError::SubscriptionNotFound { query }),
+        },
+        "unsubscribe_all" => {
+            subscriptions.clear();
+            Ok(json!({}))
+        }
+        _ => {
+            return json!({
+                "jsonrpc": "2.0",
+                "error": { "code": -32601, "message": "Method not found", "data": "" },
+                "id": id,
+            })
+        }
+    };
+    response_json(id, result)
+}
+
+fn subscribe(subscriptions: &mut HashMap<String, Subscription>, query: String, id: &Value) -> Result<Value, RpcError> {
+    if subscriptions.contains_key(&query) {
+        return Err(RpcError::AlreadySubscribed { query });
+    }
+    if subscriptions.len() >= MAX_SUBSCRIPTIONS_PER_CLIENT {
+        return Err(RpcError::TooManySubscriptions { max: MAX_SUBSCRIPTIONS_PER_CLIENT });
+    }
+    let parsed = query.parse()?;
+    subscriptions.insert(query, Subscription { query: parsed, id: id.clone() });
+    Ok(json!({}))
+}
+
+// the events of a block for every subscription whose query they match
+fn notifications(subscriptions: &HashMap<String, Subscription>, committed: &CommittedBlock) -> Vec<Value> {
+    if subscriptions.is_empty() {
+        return vec![];
+    }
+    let mut notifications = vec![];
+    for (data, events) in block_events(committed) {
+        for (query, subscription) in subscriptions {
+            if subscription.query.matches(&events) {
+                let result = json!({ "query": query, "data": data, "events": events });
+                notifications.push(response_json(&event_id(&subscription.id), Ok(result)));
+            }
+        }
+    }
+    notifications
+}
+
+// CometBFT sends the events of a subscription with the id of the `subscribe` call and `#event`
+fn event_id(id: &Value) -> Value {
+    match id {
+        Value::String(id) => Value::String(format!("{}#event", id)),
+        id => Value::String(format!("{}#event", id)),
+    }
+}
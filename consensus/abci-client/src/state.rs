@@ -0,0 +1,88 @@
+use serde::{Serialize, Deserialize};
+use tendermint_proto::abci::ResponseInitChain;
+
+use crate::utils::{ripemd160_digest, sha256_digest};
+use crate::{BlockParamsRecord, EndBlockResult, Genesis, ValidatorUpdateRecord};
+
+/// Block protocol version of the headers sent to the app (CometBFT v0.34).
+pub const BLOCK_PROTOCOL: u64 = 11;
+
+/// Chain-wide parameters agreed on at genesis and updated by the app through
+/// InitChain and EndBlock. Persisted in the block store next to the blocks.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct ChainState {
+    pub chain_id: String,
+    pub initial_height: i64,
+    pub app_version: u64,
+    // as of the latest EndBlock, the block store keeps the set voting on each height
+    pub validators: Vec<ValidatorUpdateRecord>,
+    pub block_params: BlockParamsRecord,
+    // app hash returned by InitChain
+    pub genesis_app_hash: Vec<u8>,
+}
+
+impl ChainState {
+    /// State right after `InitChain`. As in CometBFT, validators and consensus params
+    /// returned by the app take precedence over the ones in the genesis file.
+    pub fn from_genesis(genesis: &Genesis, resp: &ResponseInitChain) -> eyre::Result<Self> {
+        let validators = if resp.validators.is_empty() {
+            genesis.validator_updates()?
+        } else {
+            resp.validators.iter().map(ValidatorUpdateRecord::from).collect()
+        };
+
+        let mut block_params = BlockParamsRecord {
+            max_bytes: genesis.consensus_params.block.max_bytes,
+            max_gas: genesis.consensus_params.block.max_gas,
+        };
+        let mut app_version = genesis.consensus_params.version.app_version as u64;
+        if let Some(params) = &resp.consensus_params {
+            if let Some(block) = &params.block {
+                block_params = BlockParamsRecord {
+                    max_bytes: block.max_bytes,
+                    max_gas: block.max_gas,
+                };
+            }
+            if let Some(version) = &params.version {
+                app_version = version.app_version;
+            }
+        }
+
+        Ok(Self {
+            chain_id: genesis.chain_id.clone(),
+            initial_height: genesis.initial_height,
+            app_version,
+            validators,
+            block_params,
+            genesis_app_hash: resp.app_hash.to_vec(),
+        })
+    }
+
+    /// Applies the validator and consensus param updates returned by EndBlock.
+    pub fn apply_end_block(&mut self, end_block: &EndBlockResult) {
+        for update in &end_block.validator_updates {
+            self.validators.retain(|validator| validator.pub_key != update.pub_key);
+            if update.power > 0 {
+                self.validators.push(update.clone());
+            }
+        }
+        if let Some(block_params) = &end_block.block_params_update {
+            self.block_params = block_params.clone();
+        }
+    }
+
+    /// Address of the validator proposing blocks on this node, the first one in the set.
+    pub fn proposer_address(&self) -> Vec<u8> {
+        self.validators.first().map(|validator| validator.address()).unwrap_or_default()
+    }
+}
+
+impl ValidatorUpdateRecord {
+    /// Tendermint address of the validator's public key.
+    pub fn address(&self) -> Vec<u8> {
+        match self.pub_key_type.as_str() {
+            "secp256k1" => ripemd160_digest(&sha256_digest(&self.pub_key)),
+            _ => sha256_digest(&self.pub_key)[..20].to_vec(),
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use serde::Serialize;
+
+use crate::{Block, BlockResults, ChainState};
+
+/// Committed blocks the RPC's event subscribers may fall behind by before they are dropped.
+pub const EVENT_BUFFER: usize = 100;
+
+/// Where the node's chain is, published by `Engine` for the RPC.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct ChainStatus {
+    /// Head of the consensus engine's chain, ahead of the last final block while
+    /// blocks wait to be buried deep enough.
+    pub tip_height: i64,
+    pub tip_hash: Vec<u8>,
+    /// Last block the app committed.
+    pub finalized_height: i64,
+    pub finalized_hash: Vec<u8>,
+    /// App hash after the last final block.
+    pub app_hash: Vec<u8>,
+    /// What the app reported about itself at the handshake.
+    pub app_info: AppInfo,
+}
+
+/// The app's answer to `Info`, but for the height and app hash the status tracks.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct AppInfo {
+    pub data: String,
+    pub version: String,
+    pub app_version: u64,
+}
+
+/// A block the app committed, with what the app returned for it and the chain state
+/// after it, published by `Engine` for the RPC's event subscriptions.
+#[derive(Debug, Clone)]
+pub struct CommittedBlock {
+    pub block: Block,
+    pub results: BlockResults,
+    pub state: ChainState,
+}
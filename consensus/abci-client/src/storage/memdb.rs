@@ -0,0 +1,92 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, RwLock};
+
+use crate::mempool::tx_hash;
+use crate::{error::BlockchainError, Block, BlockResults, BlockStore, ChainState, Commit, TxLocation, ValidatorUpdateRecord};
+
+/// In-memory block store, for tests and throwaway nodes.
+#[derive(Clone, Default)]
+pub struct MemBlockStore {
+    blocks: Arc<RwLock<BTreeMap<i64, Block>>>,
+    hashes: Arc<RwLock<HashMap<Vec<u8>, i64>>>,
+    txs: Arc<RwLock<HashMap<Vec<u8>, TxLocation>>>,
+    results: Arc<RwLock<BTreeMap<i64, BlockResults>>>,
+    commits: Arc<RwLock<BTreeMap<i64, Commit>>>,
+    validators: Arc<RwLock<BTreeMap<i64, Vec<ValidatorUpdateRecord>>>>,
+    state: Arc<RwLock<Option<ChainState>>>,
+}
+
+impl MemBlockStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl BlockStore for MemBlockStore {
+    fn height(&self) -> Result<i64, BlockchainError> {
+        let blocks = self.blocks.read().unwrap();
+        Ok(blocks.keys().next_back().copied().unwrap_or(0))
+    }
+
+    fn save_block(&self, block: &Block) -> Result<(), BlockchainError> {
+        self.hashes.write().unwrap().insert(block.get_hash(), block.get_height());
+        let mut txs = self.txs.write().unwrap();
+        for (index, tx) in block.get_txs().iter().enumerate() {
+            txs.insert(tx_hash(tx), TxLocation { height: block.get_height(), index: index as u32 });
+        }
+        self.blocks.write().unwrap().insert(block.get_height(), block.clone());
+        Ok(())
+    }
+
+    fn load_block(&self, height: i64) -> Result<Option<Block>, BlockchainError> {
+        Ok(self.blocks.read().unwrap().get(&height).cloned())
+    }
+
+    fn load_block_by_hash(&self, hash: &[u8]) -> Result<Option<Block>, BlockchainError> {
+        let height = self.hashes.read().unwrap().get(hash).copied();
+        match height {
+            Some(height) => self.load_block(height),
+            None => Ok(None),
+        }
+    }
+
+    fn load_tx_location(&self, hash: &[u8]) -> Result<Option<TxLocation>, BlockchainError> {
+        Ok(self.txs.read().unwrap().get(hash).copied())
+    }
+
+    fn save_results(&self, results: &BlockResults) -> Result<(), BlockchainError> {
+        self.results.write().unwrap().insert(results.height, results.clone());
+        Ok(())
+    }
+
+    fn load_results(&self, height: i64) -> Result<Option<BlockResults>, BlockchainError> {
+        Ok(self.results.read().unwrap().get(&height).cloned())
+    }
+
+    fn save_commit(&self, commit: &Commit) -> Result<(), BlockchainError> {
+        self.commits.write().unwrap().insert(commit.height, commit.clone());
+        Ok(())
+    }
+
+    fn load_commit(&self, height: i64) -> Result<Option<Commit>, BlockchainError> {
+        Ok(self.commits.read().unwrap().get(&height).cloned())
+    }
+
+    fn save_validators(&self, height: i64, validators: &[ValidatorUpdateRecord]) -> Result<(), BlockchainError> {
+        self.validators.write().unwrap().insert(height, validators.to_vec());
+        Ok(())
+    }
+
+    fn load_validators(&self, height: i64) -> Result<Option<Vec<ValidatorUpdateRecord>>, BlockchainError> {
+        Ok(self.validators.read().unwrap().get(&height).cloned())
+    }
+
+    fn save_state(&self, state: &ChainState) -> Result<(), BlockchainError> {
+        *self.state.write().unwrap() = Some(state.clone());
+        Ok(())
+    }
+
+    fn load_state(&self) -> Result<Option<ChainState>, BlockchainError> {
+        Ok(self.state.read().unwrap().clone())
+    }
+}
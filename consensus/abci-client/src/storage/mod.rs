@@ -0,0 +1,52 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{error::BlockchainError, Block, BlockResults, ChainState, Commit, ValidatorUpdateRecord};
+mod sleddb;
+mod memdb;
+mod orphan_pool;
+
+pub use sleddb::SledBlockStore;
+pub use memdb::MemBlockStore;
+pub use orphan_pool::*;
+
+pub const HEIGHT: &str = "height";
+pub const CHAIN_STATE: &str = "state";
+pub const TABLE_OF_BLOCK: &str = "blocks";
+pub const TABLE_OF_BLOCK_HASH: &str = "block_hashes";
+pub const TABLE_OF_RESULTS: &str = "results";
+pub const TABLE_OF_COMMITS: &str = "commits";
+pub const TABLE_OF_TX_HASH: &str = "tx_hashes";
+pub const TABLE_OF_VALIDATORS: &str = "validators";
+
+/// Where a tx landed in the chain.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+pub struct TxLocation {
+    pub height: i64,
+    /// Position of the tx in its block.
+    pub index: u32,
+}
+
+/// Consensus-layer storage of the chain: blocks by height and hash, and the
+/// results the app returned when executing them.
+pub trait BlockStore: Send + Sync + 'static {
+    /// Height of the latest saved block, `0` if the store is empty.
+    fn height(&self) -> Result<i64, BlockchainError>;
+    /// Saves a block, indexing its txs by hash.
+    fn save_block(&self, block: &Block) -> Result<(), BlockchainError>;
+    fn load_block(&self, height: i64) -> Result<Option<Block>, BlockchainError>;
+    fn load_block_by_hash(&self, hash: &[u8]) -> Result<Option<Block>, BlockchainError>;
+    /// Block and position of the tx with the given `tx_hash`.
+    fn load_tx_location(&self, hash: &[u8]) -> Result<Option<TxLocation>, BlockchainError>;
+    fn save_results(&self, results: &BlockResults) -> Result<(), BlockchainError>;
+    fn load_results(&self, height: i64) -> Result<Option<BlockResults>, BlockchainError>;
+    /// Votes this node saw committing a block. The commit stored in the next block may
+    /// hold other votes, this one is what the node proposes its next block with.
+    fn save_commit(&self, commit: &Commit) -> Result<(), BlockchainError>;
+    fn load_commit(&self, height: i64) -> Result<Option<Commit>, BlockchainError>;
+    /// Validators voting on a height. As in CometBFT, the updates EndBlock returns at
+    /// height H take effect at H + 2.
+    fn save_validators(&self, height: i64, validators: &[ValidatorUpdateRecord]) -> Result<(), BlockchainError>;
+    fn load_validators(&self, height: i64) -> Result<Option<Vec<ValidatorUpdateRecord>>, BlockchainError>;
+    fn save_state(&self, state: &ChainState) -> Result<(), BlockchainError>;
+    fn load_state(&self) -> Result<Option<ChainState>, BlockchainError>;
+}
@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use crate::{Block, PeerId};
+
+/// Default number of blocks the orphan pool holds.
+pub const MAX_ORPHANS: usize = 100;
+/// Default size of the blocks the orphan pool holds, in bytes.
+pub const MAX_ORPHAN_BYTES: usize = 64 * 1024 * 1024;
+/// Default time an orphan waits for its parent before it is dropped.
+pub const ORPHAN_EXPIRY: Duration = Duration::from_secs(600);
+
+struct Orphan {
+    block: Block,
+    // peer the block came from, to pass it on to the others once it is attached
+    from: PeerId,
+    size: usize,
+    received_at: Instant,
+}
+
+/// Blocks received before their parent, waiting for it to arrive. The pool is bounded
+/// in blocks and bytes, dropping the oldest orphans first, and orphans expire, so a
+/// peer sending blocks nobody can attach only ever costs a bounded amount of memory.
+pub struct OrphanPool {
+    orphans: HashMap<Vec<u8>, Orphan>,
+    // hashes of the orphans waiting for each parent
+    children: HashMap<Vec<u8>, Vec<Vec<u8>>>,
+    bytes: usize,
+    max_orphans: usize,
+    max_bytes: usize,
+    expiry: Duration,
+}
+
+impl Default for OrphanPool {
+    fn default() -> Self {
+        Self::new(MAX_ORPHANS, MAX_ORPHAN_BYTES, ORPHAN_EXPIRY)
+    }
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize, max_bytes: usize, expiry: Duration) -> Self {
+        Self {
+            orphans: HashMap::new(),
+            children: HashMap::new(),
+            bytes: 0,
+            max_orphans,
+            max_bytes,
+            expiry,
+        }
+    }
+
+    pub fn contains(&self, hash: &[u8]) -> bool {
+        self.orphans.contains_key(hash)
+    }
+
+    pub fn len(&self) -> usize {
+        self.orphans.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.orphans.is_empty()
+    }
+
+    /// Total size of the orphans, in bytes.
+    pub fn size_bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// Adds a block whose parent is unknown, `size` being its encoded size. Expired
+    /// orphans, then the oldest ones, make room for it. Returns false if the block alone
+    /// is over the limits.
+    pub fn insert(&mut self, block: Block, from: PeerId, size: usize, now: Instant) -> bool {
+        if size > self.max_bytes || self.max_orphans == 0 {
+            return false;
+        }
+        let hash = block.get_hash();
+        if self.orphans.contains_key(&hash) {
+            return true;
+        }
+
+        self.expire(now);
+        while self.orphans.len() >= self.max_orphans || self.bytes + size > self.max_bytes {
+            let oldest = self
+                .orphans
+                .iter()
+                .min_by_key(|(_, orphan)| orphan.received_at)
+                .map(|(hash, _)| hash.clone())
+                .unwrap();
+            self.remove(&oldest);
+        }
+
+        self.children.entry(block.get_header().prev_hash.clone()).or_default().push(hash.clone());
+        self.bytes += size;
+        self.orphans.insert(hash, Orphan { block, from, size, received_at: now });
+        true
+    }
+
+    /// Takes the orphans waiting for `parent` out of the pool, with the peers they came from.
+    pub fn take_children(&mut self, parent: &[u8]) -> Vec<(Block, PeerId)> {
+        let hashes = self.children.remove(parent).unwrap_or_default();
+        hashes
+            .iter()
+            .filter_map(|hash| self.remove(hash))
+            .map(|orphan| (orphan.block, orphan.from))
+            .collect()
+    }
+
+    /// The block missing for an orphan to be attached: the parent of its oldest ancestor
+    /// in the pool.
+    pub fn missing_ancestor(&self, hash: &[u8]) -> Vec<u8> {
+        let mut hash = hash.to_vec();
+        while let Some(orphan) = self.orphans.get(&hash) {
+            hash = orphan.block.get_header().prev_hash.clone();
+        }
+        hash
+    }
+
+    /// Drops the orphans that waited longer than the expiry.
+    pub fn expire(&mut self, now: Instant) {
+        let expired: Vec<Vec<u8>> = self
+            .orphans
+            .iter()
+            .filter(|(_, orphan)| now.saturating_duration_since(orphan.received_at) > self.expiry)
+            .map(|(hash, _)| hash.clone())
+            .collect();
+        for hash in expired {
+            self.remove(&hash);
+        }
+    }
+
+    /// Drops every orphan.
+    pub fn clear(&mut self) {
+        self.orphans.clear();
+        self.children.clear();
+        self.bytes = 0;
+    }
+
+    fn remove(&mut self, hash: &[u8]) -> Option<Orphan> {
+        let orphan = self.orphans.remove(hash)?;
+        self.bytes -= orphan.size;
+        let parent = &orphan.block.get_header().prev_hash;
+        if let Some(siblings) = self.children.get_mut(parent) {
+            siblings.retain(|sibling| sibling.as_slice() != hash);
+            if siblings.is_empty() {
+                self.children.remove(parent);
+            }
+        }
+        Some(orphan)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a block waiting for the parent `parent`
+    fn orphan(parent: &str, tag: u8) -> Block {
+        Block::new(2, parent.as_bytes().to_vec(), vec![], 0, 0, vec![vec![tag]])
+    }
+
+    fn insert(pool: &mut OrphanPool, block: &Block, size: usize, now: Instant) -> bool {
+        pool.insert(block.clone(), "peer".to_string(), size, now)
+    }
+
+    #[test]
+    fn orphans_expire() {
+        let mut pool = OrphanPool::new(10, 1000, Duration::from_secs(60));
+        let start = Instant::now();
+        let (a, b) = (orphan("x", 1), orphan("y", 2));
+        assert!(insert(&mut pool, &a, 100, start));
+        assert!(insert(&mut pool, &b, 100, start + Duration::from_secs(30)));
+
+        pool.expire(start + Duration::from_secs(60));
+        assert_eq!(pool.len(), 2);
+        pool.expire(start + Duration::from_secs(61));
+        assert!(!pool.contains(&a.get_hash()) && pool.contains(&b.get_hash()));
+        assert_eq!(pool.size_bytes(), 100);
+        assert!(pool.take_children(b"x").is_empty());
+    }
+
+    #[test]
+    fn the_oldest_orphan_makes_room_at_max_orphans() {
+        let mut pool = OrphanPool::new(2, 1000, Duration::from_secs(60));
+        let start = Instant::now();
+        let blocks: Vec<Block> = (0..3).map(|tag| orphan("x", tag)).collect();
+        for (i, block) in blocks.iter().enumerate() {
+            assert!(insert(&mut pool, block, 100, start + Duration::from_secs(i as u64)));
+        }
+
+        assert_eq!(pool.len(), 2);
+        assert!(!pool.contains(&blocks[0].get_hash()));
+        assert!(pool.contains(&blocks[1].get_hash()) && pool.contains(&blocks[2].get_hash()));
+        assert_eq!(pool.take_children(b"x").len(), 2);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn the_oldest_orphans_make_room_at_max_bytes() {
+        let mut pool = OrphanPool::new(10, 1000, Duration::from_secs(60));
+        let start = Instant::now();
+        let (a, b, c) = (orphan("x", 1), orphan("y", 2), orphan("z", 3));
+        assert!(insert(&mut pool, &a, 400, start));
+        assert!(insert(&mut pool, &b, 400, start + Duration::from_secs(1)));
+        assert!(insert(&mut pool, &c, 700, start + Duration::from_secs(2)));
+
+        assert!(!pool.contains(&a.get_hash()) && !pool.contains(&b.get_hash()));
+        assert!(pool.contains(&c.get_hash()));
+        assert_eq!(pool.size_bytes(), 700);
+
+        // a block alone over the limit is refused and evicts nothing
+        assert!(!insert(&mut pool, &orphan("x", 4), 1001, start + Duration::from_secs(3)));
+        assert_eq!(pool.len(), 1);
+    }
+}
@@ -0,0 +1,140 @@
+use std::path::Path;
+use sled::Db;
+
+use crate::{
+    error::BlockchainError, utils::{deserialize, serialize}, Block, BlockResults, BlockStore,
+    ChainState, Commit, TxLocation, ValidatorUpdateRecord, CHAIN_STATE, HEIGHT, TABLE_OF_BLOCK, TABLE_OF_BLOCK_HASH,
+    TABLE_OF_COMMITS, TABLE_OF_RESULTS, TABLE_OF_TX_HASH, TABLE_OF_VALIDATORS,
+};
+use crate::mempool::tx_hash;
+
+/// On-disk block store backed by sled.
+#[derive(Clone)]
+pub struct SledBlockStore {
+    db: Db,
+}
+
+impl SledBlockStore {
+    pub fn new(path: impl AsRef<Path>) -> Result<Self, BlockchainError> {
+        Ok(Self {
+            db: sled::open(path)?,
+        })
+    }
+
+    // zero padded so that keys sort by height
+    fn get_height_key(table: &str, height: i64) -> String {
+        format!("{}:{:020}", table, height)
+    }
+
+    fn get_hash_key(hash: &[u8]) -> String {
+        format!("{}:{}", TABLE_OF_BLOCK_HASH, hex::encode(hash))
+    }
+
+    fn get_tx_key(hash: &[u8]) -> String {
+        format!("{}:{}", TABLE_OF_TX_HASH, hex::encode(hash))
+    }
+}
+
+impl BlockStore for SledBlockStore {
+    fn height(&self) -> Result<i64, BlockchainError> {
+        let result = self.db.get(HEIGHT)?.map(|v| deserialize::<i64>(&v));
+        result.unwrap_or(Ok(0))
+    }
+
+    fn save_block(&self, block: &Block) -> Result<(), BlockchainError> {
+        let height = block.get_height();
+        let block_key = Self::get_height_key(TABLE_OF_BLOCK, height);
+        let hash_key = Self::get_hash_key(&block.get_hash());
+        let block_ser = serialize(block)?;
+        let height_ser = serialize(&height)?;
+        let mut tx_locations = vec![];
+        for (index, tx) in block.get_txs().iter().enumerate() {
+            let location = TxLocation { height, index: index as u32 };
+            tx_locations.push((Self::get_tx_key(&tx_hash(tx)), serialize(&location)?));
+        }
+
+        self.db.transaction(|db| {
+            db.insert(block_key.as_str(), block_ser.clone())?;
+            db.insert(hash_key.as_str(), height_ser.clone())?;
+            for (tx_key, location_ser) in &tx_locations {
+                db.insert(tx_key.as_str(), location_ser.clone())?;
+            }
+            db.insert(HEIGHT, height_ser.clone())?;
+            Ok(())
+        }).map_err(|err: sled::transaction::TransactionError<()>| match err {
+            sled::transaction::TransactionError::Storage(err) => BlockchainError::SledError(err),
+            sled::transaction::TransactionError::Abort(()) => unreachable!(),
+        })?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_block(&self, height: i64) -> Result<Option<Block>, BlockchainError> {
+        let name = Self::get_height_key(TABLE_OF_BLOCK, height);
+        let result = self.db.get(name)?.map(|v| deserialize::<Block>(&v));
+        result.map_or(Ok(None), |v| v.map(Some))
+    }
+
+    fn load_block_by_hash(&self, hash: &[u8]) -> Result<Option<Block>, BlockchainError> {
+        let height = match self.db.get(Self::get_hash_key(hash))? {
+            Some(v) => deserialize::<i64>(&v)?,
+            None => return Ok(None),
+        };
+        self.load_block(height)
+    }
+
+    fn load_tx_location(&self, hash: &[u8]) -> Result<Option<TxLocation>, BlockchainError> {
+        let result = self.db.get(Self::get_tx_key(hash))?.map(|v| deserialize::<TxLocation>(&v));
+        result.map_or(Ok(None), |v| v.map(Some))
+    }
+
+    fn save_results(&self, results: &BlockResults) -> Result<(), BlockchainError> {
+        let name = Self::get_height_key(TABLE_OF_RESULTS, results.height);
+        self.db.insert(name, serialize(results)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_results(&self, height: i64) -> Result<Option<BlockResults>, BlockchainError> {
+        let name = Self::get_height_key(TABLE_OF_RESULTS, height);
+        let result = self.db.get(name)?.map(|v| deserialize::<BlockResults>(&v));
+        result.map_or(Ok(None), |v| v.map(Some))
+    }
+
+    fn save_commit(&self, commit: &Commit) -> Result<(), BlockchainError> {
+        let name = Self::get_height_key(TABLE_OF_COMMITS, commit.height);
+        self.db.insert(name, serialize(commit)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_commit(&self, height: i64) -> Result<Option<Commit>, BlockchainError> {
+        let name = Self::get_height_key(TABLE_OF_COMMITS, height);
+        let result = self.db.get(name)?.map(|v| deserialize::<Commit>(&v));
+        result.map_or(Ok(None), |v| v.map(Some))
+    }
+
+    fn save_validators(&self, height: i64, validators: &[ValidatorUpdateRecord]) -> Result<(), BlockchainError> {
+        let name = Self::get_height_key(TABLE_OF_VALIDATORS, height);
+        self.db.insert(name, serialize(validators)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_validators(&self, height: i64) -> Result<Option<Vec<ValidatorUpdateRecord>>, BlockchainError> {
+        let name = Self::get_height_key(TABLE_OF_VALIDATORS, height);
+        let result = self.db.get(name)?.map(|v| deserialize::<Vec<ValidatorUpdateRecord>>(&v));
+        result.map_or(Ok(None), |v| v.map(Some))
+    }
+
+    fn save_state(&self, state: &ChainState) -> Result<(), BlockchainError> {
+        self.db.insert(CHAIN_STATE, serialize(state)?)?;
+        self.db.flush()?;
+        Ok(())
+    }
+
+    fn load_state(&self) -> Result<Option<ChainState>, BlockchainError> {
+        let result = self.db.get(CHAIN_STATE)?.map(|v| deserialize::<ChainState>(&v));
+        result.map_or(Ok(None), |v| v.map(Some))
+    }
+}
@@ -0,0 +1,83 @@
+use crate::utils::sha256_digest;
+
+const LEAF_PREFIX: u8 = 0;
+const INNER_PREFIX: u8 = 1;
+
+/// Merkle root of `items`, computed the same way as Tendermint's `SimpleHashFromByteSlices`
+/// (RFC 6962 with domain separated leaves and inner nodes).
+pub fn merkle_root(items: &[Vec<u8>]) -> Vec<u8> {
+    match items.len() {
+        0 => sha256_digest(&[]),
+        1 => leaf_hash(&items[0]),
+        n => {
+            let k = split_point(n);
+            let left = merkle_root(&items[..k]);
+            let right = merkle_root(&items[k..]);
+            inner_hash(&left, &right)
+        }
+    }
+}
+
+fn leaf_hash(leaf: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(leaf.len() + 1);
+    data.push(LEAF_PREFIX);
+    data.extend_from_slice(leaf);
+    sha256_digest(&data)
+}
+
+fn inner_hash(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let mut data = Vec::with_capacity(left.len() + right.len() + 1);
+    data.push(INNER_PREFIX);
+    data.extend_from_slice(left);
+    data.extend_from_slice(right);
+    sha256_digest(&data)
+}
+
+// largest power of two strictly less than `n`
+fn split_point(n: usize) -> usize {
+    let mut k = 1;
+    while k * 2 < n {
+        k *= 2;
+    }
+    k
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the test vectors of RFC 6962's reference implementation (certificate-transparency)
+    const LEAVES: [&str; 8] = ["", "00", "10", "2021", "3031", "40414243", "5051525354555657", "606162636465666768696a6b6c6d6e6f"];
+    const ROOTS: [&str; 8] = [
+        "6e340b9cffb37a989ca544e6bb780a2c78901d3fb33738768511a30617afa01d",
+        "fac54203e7cc696cf0dfcb42c92a1d9dbaf70ad9e621f4bd8d98662f00e3c125",
+        "aeb6bcfe274b70a14fb067a5e5578264db0fa9b51af5e0ba159158f329e06e77",
+        "d37ee418976dd95753c1c73862b9398fa2a2cf9b4ff0fdfe8b30cd95209614b7",
+        "4e3bbb1f7b478dcfe71fb631631519a3bca12c9aefca1612bfce4c13a86264d4",
+        "76e67dadbcdf1e10e1b74ddc608abd2f98dfb16fbce75277b5232a127f2087ef",
+        "ddb89be403809e325750d3d263cd78929c2942b7942a34b77e122c9594a74c8c",
+        "5dc9da79a70659a9ad559cb701ded9a2ab9d823aad2f4960cfe370eff4604328",
+    ];
+
+    #[test]
+    fn empty_tree_is_the_hash_of_nothing() {
+        assert_eq!(hex::encode(merkle_root(&[])), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+    }
+
+    #[test]
+    fn matches_the_rfc_6962_vectors() {
+        let leaves: Vec<Vec<u8>> = LEAVES.iter().map(|leaf| hex::decode(leaf).unwrap()).collect();
+        for (n, root) in ROOTS.iter().enumerate() {
+            assert_eq!(hex::encode(merkle_root(&leaves[..n + 1])), *root, "tree of {} leaves", n + 1);
+        }
+    }
+
+    #[test]
+    fn split_point_is_the_largest_power_of_two_below() {
+        assert_eq!(split_point(2), 1);
+        assert_eq!(split_point(3), 2);
+        assert_eq!(split_point(4), 2);
+        assert_eq!(split_point(5), 4);
+        assert_eq!(split_point(9), 8);
+    }
+}
@@ -1,5 +1,7 @@
 mod serializer;
 mod secret;
+mod merkle;
 
 pub use serializer::*;
-pub use secret::*;
\ No newline at end of file
+pub use secret::*;
+pub use merkle::*;
\ No newline at end of file
@@ -1,9 +1,13 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
 use std::iter::repeat;
+use std::path::Path;
 
 use crypto::digest::Digest;
 use crypto::ripemd160::Ripemd160;
 use ring::rand::SystemRandom;
-use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P256_SHA256_FIXED};
+use ring::rand::SecureRandom;
+use ring::signature::{EcdsaKeyPair, Ed25519KeyPair, KeyPair, ECDSA_P256_SHA256_FIXED_SIGNING, ECDSA_P256_SHA256_FIXED, ED25519};
 use ring::digest::{Context, SHA256};
 use bs58::*;
 
@@ -46,4 +50,59 @@ pub fn ecdsa_p256_sha256_sign_verify(public_key: &[u8], signature: &[u8], messag
         ring::signature::UnparsedPublicKey::new(&ECDSA_P256_SHA256_FIXED, public_key);
     let result = peer_public_key.verify(message, signature.as_ref());
     result.is_ok()
-}
\ No newline at end of file
+}
+
+/// New random ed25519 seed, the private half of a validator key.
+pub fn new_ed25519_seed() -> Vec<u8> {
+    let mut seed = vec![0u8; 32];
+    SystemRandom::new().fill(&mut seed).unwrap();
+    seed
+}
+
+pub fn ed25519_public_key(seed: &[u8]) -> Vec<u8> {
+    let key_pair = Ed25519KeyPair::from_seed_unchecked(seed).unwrap();
+    key_pair.public_key().as_ref().to_vec()
+}
+
+pub fn ed25519_sign(seed: &[u8], message: &[u8]) -> Vec<u8> {
+    let key_pair = Ed25519KeyPair::from_seed_unchecked(seed).unwrap();
+    key_pair.sign(message).as_ref().to_vec()
+}
+
+pub fn ed25519_sign_verify(public_key: &[u8], signature: &[u8], message: &[u8]) -> bool {
+    let peer_public_key = ring::signature::UnparsedPublicKey::new(&ED25519, public_key);
+    peer_public_key.verify(message, signature).is_ok()
+}
+
+/// Writes a file holding a private key, readable and writable by its owner only.
+pub fn write_private_file(path: impl AsRef<Path>, contents: &[u8]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    // before anything is written, and also for a file that existed with other permissions
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    }
+    file.write_all(contents)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    #[test]
+    fn private_files_are_only_readable_by_their_owner() {
+        let path = std::env::temp_dir().join(format!("private-file-{}", std::process::id()));
+        fs::write(&path, b"old").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+
+        write_private_file(&path, b"secret").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        let contents = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+        assert_eq!(contents, b"secret");
+    }
+}
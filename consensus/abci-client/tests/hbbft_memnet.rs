@@ -0,0 +1,65 @@
+use hbbft::NetworkInfo;
+
+use pow_abci::{ChainState, HbbftEngine, MemNetwork, PeerId};
+
+// N hbbft validators over the in-memory network, on an empty chain
+fn network(nodes: usize) -> (MemNetwork, Vec<PeerId>) {
+    let ids: Vec<PeerId> = (0..nodes).map(|i| format!("node-{}", i)).collect();
+    let netinfos = NetworkInfo::generate_map(ids.clone(), &mut rand::thread_rng()).unwrap();
+    let state = ChainState { chain_id: "hbbft-test".to_string(), initial_height: 1, ..Default::default() };
+    let mut network = MemNetwork::new();
+    for (id, netinfo) in netinfos {
+        network.add_node(id, Box::new(HbbftEngine::with_netinfo(netinfo)), state.clone()).unwrap();
+    }
+    (network, ids)
+}
+
+// runs `blocks` epochs, every node in `proposers` contributing one tx to each
+fn run_epochs(network: &mut MemNetwork, proposers: &[PeerId], blocks: i64) {
+    for height in 1..=blocks {
+        for id in proposers {
+            let tx = format!("tx-{}-from-{}", height, id).into_bytes();
+            assert!(network.propose(id, vec![tx], height * 1_000_000_000).unwrap(), "{} could not propose", id);
+        }
+        network.run().unwrap();
+    }
+}
+
+// hashes of the blocks the node committed, in height order
+fn chain(network: &MemNetwork, id: &PeerId) -> Vec<Vec<u8>> {
+    (1..=network.height(id)).map(|height| network.block(id, height).unwrap().unwrap().get_hash()).collect()
+}
+
+#[test]
+fn four_nodes_commit_the_same_blocks_in_the_same_order() {
+    let (mut network, ids) = network(4);
+    run_epochs(&mut network, &ids, 3);
+
+    let expected = chain(&network, &ids[0]);
+    assert_eq!(expected.len(), 3);
+    for id in &ids {
+        assert_eq!(chain(&network, id), expected, "{} committed other blocks", id);
+    }
+    // every validator's contribution made it into every block
+    for height in 1..=3 {
+        assert_eq!(network.block(&ids[0], height).unwrap().unwrap().get_txs().len(), 4);
+    }
+}
+
+#[test]
+fn commits_with_one_node_silent() {
+    let (mut network, ids) = network(4);
+    let silent = &ids[3];
+    network.set_down(silent, true);
+    run_epochs(&mut network, &ids[..3], 3);
+
+    let expected = chain(&network, &ids[0]);
+    assert_eq!(expected.len(), 3);
+    for id in &ids[..3] {
+        assert_eq!(chain(&network, id), expected, "{} committed other blocks", id);
+    }
+    for height in 1..=3 {
+        assert_eq!(network.block(&ids[0], height).unwrap().unwrap().get_txs().len(), 3);
+    }
+    assert_eq!(network.height(silent), 0);
+}
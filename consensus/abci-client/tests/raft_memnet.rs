@@ -0,0 +1,128 @@
+use std::path::PathBuf;
+
+use pow_abci::raft::{RaftEngine, RaftTimeouts, SledRaftStorage};
+use pow_abci::{ChainState, MemNetwork, PeerId};
+
+// most ticks to wait for something to happen
+const MAX_TICKS: usize = 1_000;
+
+// directory of the raft logs of a test, removed with it
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("raft-memnet-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&path);
+        Self(path)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+// N raft nodes over the in-memory network, each with its log in sled
+fn network(dir: &TempDir, nodes: usize) -> (MemNetwork, Vec<PeerId>, Vec<SledRaftStorage>) {
+    let ids: Vec<PeerId> = (0..nodes).map(|i| format!("node-{}", i)).collect();
+    let storages: Vec<SledRaftStorage> =
+        ids.iter().map(|id| SledRaftStorage::new(dir.0.join(id)).unwrap()).collect();
+    let state = ChainState { chain_id: "raft-test".to_string(), initial_height: 1, ..Default::default() };
+    let mut network = MemNetwork::new();
+    for (i, id) in ids.iter().enumerate() {
+        network.add_node(id.clone(), engine(&ids, i, &storages[i]), state.clone()).unwrap();
+    }
+    (network, ids, storages)
+}
+
+// a fresh engine of the i-th node, which reads back what its log holds
+fn engine(ids: &[PeerId], i: usize, storage: &SledRaftStorage) -> Box<RaftEngine> {
+    Box::new(RaftEngine::new(ids[i].clone(), ids.to_vec(), RaftTimeouts::default(), Box::new(storage.clone())).unwrap())
+}
+
+// Has the leader among `ids` commit `blocks` blocks of one tx on all of them, and returns it.
+fn commit_blocks(network: &mut MemNetwork, ids: &[PeerId], blocks: i64) -> PeerId {
+    let mut leader = None;
+    for _ in 0..blocks {
+        let target = ids.iter().map(|id| network.height(id)).max().unwrap() + 1;
+        let tx = format!("tx-{}", target).into_bytes();
+        wait_for(network, |network| {
+            // only the leader may propose
+            for id in ids {
+                if network.propose(id, vec![tx.clone()], target * 1_000_000_000).unwrap() {
+                    leader = Some(id.clone());
+                    return true;
+                }
+            }
+            false
+        });
+        wait_for(network, |network| ids.iter().all(|id| network.height(id) >= target));
+    }
+    leader.expect("no leader was elected")
+}
+
+fn wait_for(network: &mut MemNetwork, mut done: impl FnMut(&mut MemNetwork) -> bool) {
+    for _ in 0..MAX_TICKS {
+        network.run().unwrap();
+        if done(network) {
+            return;
+        }
+        network.tick().unwrap();
+    }
+    panic!("nothing happened in {} ticks", MAX_TICKS);
+}
+
+// hashes of the blocks the node committed, in height order
+fn chain(network: &MemNetwork, id: &PeerId) -> Vec<Vec<u8>> {
+    (1..=network.height(id)).map(|height| network.block(id, height).unwrap().unwrap().get_hash()).collect()
+}
+
+#[test]
+fn three_nodes_agree_after_the_leader_is_killed() {
+    let dir = TempDir::new("leader");
+    let (mut network, ids, storages) = network(&dir, 3);
+    let leader = commit_blocks(&mut network, &ids, 3);
+
+    network.set_down(&leader, true);
+    let live: Vec<PeerId> = ids.iter().filter(|id| **id != leader).cloned().collect();
+    let new_leader = commit_blocks(&mut network, &live, 3);
+    assert_ne!(new_leader, leader);
+
+    let expected = chain(&network, &live[0]);
+    assert_eq!(expected.len(), 6);
+    assert_eq!(chain(&network, &live[1]), expected);
+    // the old leader missed the blocks of the new term
+    assert_eq!(network.height(&leader), 3);
+
+    // back on its log, it catches up with the new leader
+    let index = ids.iter().position(|id| *id == leader).unwrap();
+    network.set_down(&leader, false);
+    network.restart(&leader, engine(&ids, index, &storages[index])).unwrap();
+    wait_for(&mut network, |network| network.height(&leader) >= 6);
+    assert_eq!(chain(&network, &leader), expected);
+}
+
+#[test]
+fn five_nodes_agree_after_a_follower_restarts() {
+    let dir = TempDir::new("follower");
+    let (mut network, ids, storages) = network(&dir, 5);
+    let leader = commit_blocks(&mut network, &ids, 2);
+
+    let follower = ids.iter().find(|id| **id != leader).unwrap().clone();
+    let index = ids.iter().position(|id| *id == follower).unwrap();
+    network.set_down(&follower, true);
+    let live: Vec<PeerId> = ids.iter().filter(|id| **id != follower).cloned().collect();
+    commit_blocks(&mut network, &live, 2);
+    assert_eq!(network.height(&follower), 2);
+
+    network.set_down(&follower, false);
+    network.restart(&follower, engine(&ids, index, &storages[index])).unwrap();
+    commit_blocks(&mut network, &ids, 1);
+
+    let expected = chain(&network, &ids[0]);
+    assert_eq!(expected.len(), 5);
+    for id in &ids {
+        assert_eq!(chain(&network, id), expected, "{} committed other blocks", id);
+    }
+}